@@ -1,11 +1,28 @@
 #[cfg(feature = "oidc")]
 pub mod oidc;
 
+use actix_session::SessionExt;
+use actix_web::HttpRequest;
+
 pub struct Identity {
     pub username: String,
     pub groups: Vec<String>,
 }
 
+impl Identity {
+    /// Identity of user logged in with the session of the request
+    pub fn from_request(req: &HttpRequest) -> Option<Self> {
+        let session = req.get_session();
+        let username = session.get::<String>("username").ok()??;
+        let groups = session
+            .get::<Vec<String>>("groups")
+            .ok()
+            .flatten()
+            .unwrap_or_default();
+        Some(Identity { username, groups })
+    }
+}
+
 #[cfg(not(feature = "oidc"))]
 pub mod oidc {
     use super::Identity;
@@ -9,6 +9,7 @@ use figment::Figment;
 use log::info;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::env;
 use std::path::PathBuf;
 
@@ -305,6 +306,28 @@ impl DsGpkgCfg {
 pub struct WmsHttpSourceProviderCfg {
     pub baseurl: String,
     pub format: String,
+    /// WMS version `1.1.1` or `1.3.0` (Default: `VERSION` parameter of `baseurl` or `1.3.0`)
+    pub version: Option<String>,
+    /// Additional request parameters, e.g. vendor specific parameters
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// Authentication for WMS requests
+    pub auth: Option<HttpAuthCfg>,
+}
+
+/// HTTP authentication
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub enum HttpAuthCfg {
+    /// Basic authentication
+    #[serde(rename = "basic")]
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// Bearer token authentication
+    #[serde(rename = "bearer")]
+    Bearer { token: String },
 }
 
 #[cfg(test)]
@@ -11,6 +11,8 @@ pub enum Format {
     Json,
     Mvt,
     Png,
+    /// 8-bit palette PNG
+    Png8,
     Webp,
 }
 
@@ -20,9 +22,10 @@ impl Format {
         Some(match value.to_ascii_lowercase().as_str() {
             "gif" => Self::Gif,
             "jpg" | "jpeg" => Self::Jpeg,
-            "json" => Self::Json,
+            "json" | "geojson" => Self::Json,
             "pbf" | "mvt" => Self::Mvt,
             "png" => Self::Png,
+            "png8" => Self::Png8,
             "webp" => Self::Webp,
             _ => None?,
         })
@@ -32,9 +35,10 @@ impl Format {
         Some(match mime {
             "image/gif" => Self::Gif,
             "image/jpeg" => Self::Jpeg,
-            "application/json" => Self::Json,
+            "application/json" | "application/geo+json" => Self::Json,
             "application/x-protobuf" => Self::Mvt,
             "image/png" => Self::Png,
+            "image/png; mode=8bit" | "image/png;mode=8bit" => Self::Png8,
             "image/webp" => Self::Webp,
             _ => None?,
         })
@@ -47,6 +51,7 @@ impl Format {
             Self::Json => "json",
             Self::Mvt => "pbf",
             Self::Png => "png",
+            Self::Png8 => "png8",
             Self::Webp => "webp",
         }
     }
@@ -58,7 +63,8 @@ impl Format {
             Self::Jpeg => "image/jpeg",
             Self::Json => "application/json",
             Self::Mvt => "application/x-protobuf",
-            Self::Png => "image/png", // TODO: support for "image/png; mode=8bit"!
+            Self::Png => "image/png",
+            Self::Png8 => "image/png; mode=8bit",
             Self::Webp => "image/webp",
         }
     }
@@ -67,6 +73,8 @@ impl Format {
     pub fn is_detectable(&self) -> bool {
         match *self {
             Self::Png | Self::Jpeg | Self::Gif | Self::Webp => true,
+            // Detected as PNG
+            Self::Png8 => false,
             // TODO: Json can be detected, but currently we only detect it
             //       when it's not compressed, so to avoid a warning, keeping it as false for now.
             //       Once we can detect it inside a compressed data, change it to true.
@@ -83,6 +91,7 @@ impl Display for Format {
             Self::Json => write!(f, "json"),
             Self::Mvt => write!(f, "mvt"),
             Self::Png => write!(f, "png"),
+            Self::Png8 => write!(f, "png8"),
             Self::Webp => write!(f, "webp"),
         }
     }
@@ -183,7 +192,9 @@ impl From<Format> for TileInfo {
         Self::new(
             format,
             match format {
-                Format::Png | Format::Jpeg | Format::Webp | Format::Gif => Encoding::Internal,
+                Format::Png | Format::Png8 | Format::Jpeg | Format::Webp | Format::Gif => {
+                    Encoding::Internal
+                }
                 Format::Mvt | Format::Json => Encoding::Uncompressed,
             },
         )
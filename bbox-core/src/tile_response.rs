@@ -116,6 +116,9 @@ impl Default for TileResponse {
 }
 
 impl TileResponseData {
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
     /// Insert a header, replacing any that were set with an equivalent field name.
     pub fn insert_header(&mut self, header: impl TryIntoHeaderPair) -> &mut Self {
         if let Ok((key, value)) = header.try_into_pair() {
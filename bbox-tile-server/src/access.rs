@@ -0,0 +1,142 @@
+//! Access control for tilesets and layers based on user groups.
+
+use crate::config::TileSetCfg;
+use crate::datasource::TileSourceError;
+use bbox_core::auth::Identity;
+use geozero::mvt::{self, Message};
+
+/// Check group membership. Access is granted if no groups are configured.
+pub(crate) fn groups_allowed(allowed_groups: &[String], identity: Option<&Identity>) -> bool {
+    allowed_groups.is_empty()
+        || identity.map_or(false, |identity| {
+            identity
+                .groups
+                .iter()
+                .any(|group| allowed_groups.contains(group))
+        })
+}
+
+impl TileSetCfg {
+    /// Tileset or layers with group restrictions
+    pub fn is_restricted(&self) -> bool {
+        !self.allowed_groups.is_empty() || !self.layer_access.is_empty()
+    }
+    pub fn is_accessible(&self, identity: Option<&Identity>) -> bool {
+        groups_allowed(&self.allowed_groups, identity)
+    }
+    /// Layers which are not accessible by `identity`
+    pub fn hidden_layers(&self, identity: Option<&Identity>) -> Vec<String> {
+        self.layer_access
+            .iter()
+            .filter(|access| !groups_allowed(&access.allowed_groups, identity))
+            .map(|access| access.layer.clone())
+            .collect()
+    }
+    /// Apply group restrictions of a vector tileset rendered into this tileset.
+    ///
+    /// Layers can't be removed from rendered tiles, so layer restricted tilesets are refused.
+    pub fn inherit_access(&mut self, vector_cfg: &TileSetCfg) -> Result<(), String> {
+        if !vector_cfg.layer_access.is_empty() {
+            return Err(format!(
+                "tileset `{}` with layer restrictions can't be rendered",
+                vector_cfg.name
+            ));
+        }
+        if vector_cfg.allowed_groups.is_empty() {
+            return Ok(());
+        }
+        if self.allowed_groups.is_empty() {
+            self.allowed_groups = vector_cfg.allowed_groups.clone();
+        } else if let Some(group) = self
+            .allowed_groups
+            .iter()
+            .find(|group| !vector_cfg.allowed_groups.contains(group))
+        {
+            return Err(format!(
+                "group `{group}` has no access to tileset `{}`",
+                vector_cfg.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Remove layers from MVT tile
+pub fn remove_mvt_layers(data: &[u8], layers: &[String]) -> Result<Vec<u8>, TileSourceError> {
+    let mut tile = mvt::Tile::decode(data).map_err(|_| TileSourceError::MvtDecodeError)?;
+    tile.layers.retain(|layer| !layers.contains(&layer.name));
+    Ok(tile.encode_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LayerAccessCfg, MbtilesStoreCfg, SourceParamCfg};
+    use crate::datasource::mvt::MvtBuilder;
+
+    #[test]
+    fn layer_access() {
+        let mut cfg = TileSetCfg::from_source(
+            "osm".to_string(),
+            SourceParamCfg::Mbtiles(MbtilesStoreCfg {
+                path: "osm.mbtiles".into(),
+            }),
+        );
+        assert!(!cfg.is_restricted());
+        cfg.allowed_groups = vec!["staff".to_string(), "partners".to_string()];
+        cfg.layer_access = vec![LayerAccessCfg {
+            layer: "buildings".to_string(),
+            allowed_groups: vec!["staff".to_string()],
+        }];
+        let identity = |groups: &[&str]| Identity {
+            username: "user".to_string(),
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        };
+        assert!(!cfg.is_accessible(None));
+        assert!(!cfg.is_accessible(Some(&identity(&["guests"]))));
+        assert!(cfg.is_accessible(Some(&identity(&["guests", "partners"]))));
+        assert_eq!(
+            cfg.hidden_layers(Some(&identity(&["partners"]))),
+            ["buildings"]
+        );
+        assert!(cfg.hidden_layers(Some(&identity(&["staff"]))).is_empty());
+
+        let mut mvt = MvtBuilder::new();
+        mvt.push_layer(MvtBuilder::new_layer("roads", 4096));
+        mvt.push_layer(MvtBuilder::new_layer("buildings", 4096));
+        let data =
+            remove_mvt_layers(&mvt.into_blob().unwrap(), &["buildings".to_string()]).unwrap();
+        let tile = mvt::Tile::decode(data.as_slice()).unwrap();
+        assert_eq!(tile.layers.len(), 1);
+        assert_eq!(tile.layers[0].name, "roads");
+    }
+
+    #[test]
+    fn rendered_tileset_access() {
+        let tileset_cfg = |name: &str| {
+            TileSetCfg::from_source(
+                name.to_string(),
+                SourceParamCfg::Mbtiles(MbtilesStoreCfg {
+                    path: "osm.mbtiles".into(),
+                }),
+            )
+        };
+        let mut vector_cfg = tileset_cfg("osm");
+        vector_cfg.allowed_groups = vec!["staff".to_string()];
+
+        let mut render_cfg = tileset_cfg("osm_png");
+        render_cfg.inherit_access(&vector_cfg).unwrap();
+        assert_eq!(render_cfg.allowed_groups, ["staff"]);
+
+        let mut render_cfg = tileset_cfg("osm_png");
+        render_cfg.allowed_groups = vec!["guests".to_string()];
+        assert!(render_cfg.inherit_access(&vector_cfg).is_err());
+
+        vector_cfg.layer_access = vec![LayerAccessCfg {
+            layer: "buildings".to_string(),
+            allowed_groups: vec!["staff".to_string()],
+        }];
+        let mut render_cfg = tileset_cfg("osm_png");
+        assert!(render_cfg.inherit_access(&vector_cfg).is_err());
+    }
+}
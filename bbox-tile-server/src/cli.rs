@@ -1,4 +1,5 @@
 use clap::{Args, Parser};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(name = "bbox-tile-server")]
@@ -29,6 +30,15 @@ pub enum Commands {
     /// Upload tiles
     #[command(arg_required_else_help = true)]
     Upload(UploadArgs),
+    /// Copy tiles between tile stores
+    #[command(arg_required_else_help = true)]
+    Copy(CopyArgs),
+    /// Tile statistics of tile store
+    #[command(arg_required_else_help = true)]
+    Stats(StatsArgs),
+    /// Convert t-rex configuration
+    #[command(arg_required_else_help = true)]
+    ConvertConfig(ConvertConfigArgs),
 }
 
 #[derive(Debug, Args)]
@@ -95,6 +105,98 @@ pub struct UploadArgs {
     pub tasks: Option<usize>,
 }
 
+#[derive(Debug, Args)]
+pub struct CopyArgs {
+    /// Source MBTiles/PMTiles file or tile directory
+    #[arg(group = "source")]
+    pub src: Option<String>,
+    /// Copy from tile cache of tile set
+    #[arg(long, group = "source")]
+    pub tileset: Option<String>,
+    /// Minimum zoom level
+    #[arg(long)]
+    pub minzoom: Option<u8>,
+    /// Maximum zoom level
+    #[arg(long)]
+    pub maxzoom: Option<u8>,
+    /// tile matrix set id
+    #[arg(long)]
+    pub tms: Option<String>,
+    /// Extent minx,miny,maxx,maxy (in grid reference system)
+    #[arg(long)]
+    pub extent: Option<String>,
+    /// Tile format (e.g. png), defaults to format of source metadata
+    #[arg(long)]
+    pub format: Option<String>,
+    /// Base directory for file store
+    #[arg(long, group = "store")]
+    pub tile_path: Option<String>,
+    /// S3 path to upload to (e.g. s3://tiles)
+    #[arg(long, group = "store")]
+    pub s3_path: Option<String>,
+    /// MBTiles path to store tiles
+    #[arg(long, group = "store")]
+    pub mb_path: Option<String>,
+    /// PMTiles path to store tiles
+    #[arg(long, group = "store")]
+    pub pm_path: Option<String>,
+    /// Compression of tiles written to file or S3 store
+    #[arg(long, value_enum)]
+    pub compression: Option<CompressionArg>,
+    /// Number of threads to use, defaults to number of logical cores
+    #[arg(short, long)]
+    pub threads: Option<usize>,
+    /// Size of tasks queue for parallel processing
+    #[arg(long)]
+    pub tasks: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// MBTiles/PMTiles file or tile directory
+    #[arg(group = "source")]
+    pub src: Option<String>,
+    /// Statistics of tile cache of tile set
+    #[arg(long, group = "source")]
+    pub tileset: Option<String>,
+    /// Minimum zoom level
+    #[arg(long)]
+    pub minzoom: Option<u8>,
+    /// Maximum zoom level
+    #[arg(long)]
+    pub maxzoom: Option<u8>,
+    /// tile matrix set id
+    #[arg(long)]
+    pub tms: Option<String>,
+    /// Extent minx,miny,maxx,maxy (in grid reference system)
+    #[arg(long)]
+    pub extent: Option<String>,
+    /// Tile format (e.g. png), defaults to format of source metadata
+    #[arg(long)]
+    pub format: Option<String>,
+    /// Number of largest tiles to list
+    #[arg(long, default_value("10"))]
+    pub top: usize,
+    /// Number of threads to use, defaults to number of logical cores
+    #[arg(short, long)]
+    pub threads: Option<usize>,
+}
+
+#[derive(Debug, Args)]
+pub struct ConvertConfigArgs {
+    /// t-rex config file
+    pub t_rex_config: PathBuf,
+    /// Output file for bbox configuration (Default: stdout)
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum CompressionArg {
+    None,
+    Gzip,
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum Mode {
     Sequential,
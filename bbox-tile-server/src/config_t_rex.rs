@@ -1,7 +1,12 @@
 //! t-rex compatibilty functions
 
+use crate::cli::ConvertConfigArgs;
+use crate::config::TileServiceCfg;
+use anyhow::anyhow;
+use log::{info, warn};
 use serde::Deserialize;
-use std::fs::File;
+use serde_json::json;
+use std::fs::{self, File};
 use std::io::Read;
 use tile_grid::{Extent, Grid, Origin, Unit};
 use toml::Value;
@@ -137,6 +142,55 @@ pub struct UserGridCfg {
     pub origin: String,
 }
 
+impl UserGridCfg {
+    /// Grid as OGC TileMatrixSet JSON with SRID as identifier
+    pub fn tms_json(&self) -> serde_json::Value {
+        let ext = &self.extent;
+        let degrees = self.units.to_lowercase() == "dd";
+        let meters_per_unit = match self.units.to_lowercase().as_str() {
+            "dd" => 2.0 * std::f64::consts::PI * 6378137.0 / 360.0,
+            "ft" => 0.3048,
+            _ => 1.0,
+        };
+        let (corner, origin) = if self.origin == "BottomLeft" {
+            ("bottomLeft", [ext.minx, ext.miny])
+        } else {
+            ("topLeft", [ext.minx, ext.maxy])
+        };
+        let matrix_size = |extent: f64, res: f64, tile_size: u16| {
+            ((extent / (res * tile_size as f64) - 1e-9).ceil() as u64).max(1)
+        };
+        let tile_matrices = self
+            .resolutions
+            .iter()
+            .enumerate()
+            .map(|(z, res)| {
+                json!({
+                    "id": z.to_string(),
+                    "scaleDenominator": res * meters_per_unit / 0.00028,
+                    "cellSize": res,
+                    "cornerOfOrigin": corner,
+                    "pointOfOrigin": origin,
+                    "tileWidth": self.width,
+                    "tileHeight": self.height,
+                    "matrixWidth": matrix_size(ext.maxx - ext.minx, *res, self.width),
+                    "matrixHeight": matrix_size(ext.maxy - ext.miny, *res, self.height),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut tms = json!({
+            "title": format!("EPSG:{}", self.srid),
+            "id": self.srid.to_string(),
+            "crs": format!("http://www.opengis.net/def/crs/EPSG/0/{}", self.srid),
+            "tileMatrices": tile_matrices,
+        });
+        if !degrees {
+            tms["orderedAxes"] = json!(["E", "N"]);
+        }
+        tms
+    }
+}
+
 #[derive(Deserialize, Clone, Debug)]
 pub struct TilesetCfg {
     pub name: String,
@@ -267,6 +321,107 @@ pub struct WebserverStaticCfg {
     pub dir: String,
 }
 
+/// bbox configuration converted from t-rex
+pub struct ConvertedConfig {
+    /// Content of `bbox.toml`
+    pub toml: String,
+    /// Custom grid files (file name, TileMatrixSet JSON)
+    pub grids: Vec<(String, String)>,
+    /// Options which could not be converted
+    pub unmapped: Vec<String>,
+}
+
+/// Convert t-rex configuration into bbox configuration
+pub fn convert_config(t_rex_config: ApplicationCfg) -> Result<ConvertedConfig, String> {
+    let mut unmapped = Vec::new();
+    let mut cfg = toml::Table::new();
+
+    let ws = &t_rex_config.webserver;
+    let mut webserver = toml::Table::new();
+    webserver.insert(
+        "server_addr".to_string(),
+        Value::String(format!(
+            "{}:{}",
+            ws.bind.as_deref().unwrap_or("127.0.0.1"),
+            ws.port.unwrap_or(6767)
+        )),
+    );
+    if let Some(threads) = ws.threads {
+        webserver.insert("worker_threads".to_string(), Value::Integer(threads.into()));
+    }
+    cfg.insert("webserver".to_string(), Value::Table(webserver));
+
+    if !ws.static_.is_empty() {
+        let static_dirs = ws
+            .static_
+            .iter()
+            .map(|st| {
+                let mut dir = toml::Table::new();
+                dir.insert("path".to_string(), Value::String(st.path.clone()));
+                dir.insert("dir".to_string(), Value::String(st.dir.clone()));
+                Value::Table(dir)
+            })
+            .collect();
+        let mut assets = toml::Table::new();
+        assets.insert("static".to_string(), Value::Array(static_dirs));
+        cfg.insert("assets".to_string(), Value::Table(assets));
+    }
+
+    if !t_rex_config.service.mvt.viewer {
+        unmapped.push("`service.mvt.viewer = false` is not supported".to_string());
+    }
+
+    let grids = t_rex_config
+        .grid
+        .user
+        .iter()
+        .map(|grid| {
+            let json = serde_json::to_string_pretty(&grid.tms_json()).map_err(|e| e.to_string());
+            json.map(|json| (format!("{}.json", grid.srid), json))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tile_cfg = TileServiceCfg::from_t_rex(t_rex_config, &mut unmapped);
+    let Value::Table(tile_cfg) = Value::try_from(&tile_cfg).map_err(|e| e.to_string())? else {
+        return Err("Invalid tile service configuration".to_string());
+    };
+    cfg.extend(tile_cfg);
+
+    Ok(ConvertedConfig {
+        toml: toml::to_string(&cfg).map_err(|e| e.to_string())?,
+        grids,
+        unmapped,
+    })
+}
+
+/// Write converted t-rex configuration
+pub fn convert_config_cmd(args: &ConvertConfigArgs) -> anyhow::Result<()> {
+    let path = args.t_rex_config.to_string_lossy();
+    let t_rex_config: ApplicationCfg = read_config(&path).map_err(|e| anyhow!(e))?;
+    let converted = convert_config(t_rex_config).map_err(|e| anyhow!(e))?;
+    let out_dir = args
+        .output
+        .as_ref()
+        .and_then(|output| output.parent())
+        .map(|dir| dir.to_path_buf())
+        .unwrap_or_default();
+    for (fname, json) in &converted.grids {
+        let grid_path = out_dir.join(fname);
+        info!("Writing grid definition `{}`", grid_path.display());
+        fs::write(grid_path, json)?;
+    }
+    if let Some(output) = &args.output {
+        info!("Writing configuration `{}`", output.display());
+        fs::write(output, &converted.toml)?;
+    } else {
+        println!("{}", converted.toml);
+    }
+    for msg in &converted.unmapped {
+        warn!("{msg}");
+    }
+    Ok(())
+}
+
 /// Load and parse the config file into an config struct.
 pub fn read_config<'a, T: Deserialize<'a>>(path: &str) -> Result<T, String> {
     let mut file = match File::open(path) {
@@ -0,0 +1,389 @@
+use crate::cli::{CompressionArg, CopyArgs};
+use crate::config::{FileStoreCfg, StoreCompressionCfg, TileStoreCfg};
+use crate::mbtiles_ds::MbtilesDatasource;
+use crate::seed::{parse_extent, progress_bar, write_tiles, PipelineError};
+use crate::service::{ServiceError, TileService};
+use crate::store::mbtiles::MbtilesStore;
+use crate::store::pmtiles::PmtilesStoreReader;
+use crate::store::{
+    metadata_tms_id, metadata_with_tms, stored_tiles, tile_store_from_config, CacheLayout,
+    StoreFromConfig, TileData, TileReader, TileStoreError, GZIP_MAGIC,
+};
+use bbox_core::{Compression, Format, TileResponse};
+use indicatif::ProgressStyle;
+use log::{info, warn};
+use martin_mbtiles::Metadata;
+use pumps::{Concurrency, Pipeline};
+use serde_json::json;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tile_grid::{tms, Tms, Xyz};
+use tilejson::TileJSON;
+
+/// Tile source of copy and stats commands
+pub(crate) struct StoreSource {
+    pub reader: Box<dyn TileReader>,
+    pub metadata: Metadata,
+    pub format: Format,
+    pub tms: Tms,
+}
+
+impl TileService {
+    /// Copy tiles from a tile cache, MBTiles/PMTiles file or tile directory into another tile store
+    pub async fn copy_tiles(&self, args: &CopyArgs) -> anyhow::Result<()> {
+        let progress = progress_bar();
+        let progress_main = progress.clone();
+
+        let Some(store_cfg) = TileStoreCfg::from_store_args(
+            args.tile_path.as_deref(),
+            args.s3_path.as_deref(),
+            args.mb_path.as_deref(),
+            args.pm_path.as_deref(),
+        ) else {
+            anyhow::bail!("Missing target store (--tile-path, --s3-path, --mb-path or --pm-path)");
+        };
+        let source = self
+            .store_source(
+                args.src.as_deref(),
+                args.tileset.as_deref(),
+                args.tms.as_deref(),
+                args.format.as_deref(),
+            )
+            .await?;
+        let format = source.format;
+        let tms = source.tms;
+
+        let bbox = parse_extent(&args.extent)?;
+        let threads = args.threads.unwrap_or(num_cpus::get());
+        let minzoom = args.minzoom.or(source.metadata.tilejson.minzoom);
+        let maxzoom = args.maxzoom.or(source.metadata.tilejson.maxzoom);
+        let tiles = stored_tiles(
+            source.reader.as_ref(),
+            &tms,
+            minzoom,
+            maxzoom,
+            bbox.as_ref(),
+        )
+        .await?;
+
+        // Transfer source metadata with copied zoom range
+        let mut metadata = source.metadata;
+        metadata.tilejson.minzoom = minzoom;
+        metadata.tilejson.maxzoom = maxzoom;
+        if let Some(tile_format) = martin_tile_utils::Format::parse(format.file_suffix()) {
+            metadata.tile_info.format = tile_format;
+        }
+        let metadata = metadata_with_tms(metadata, &tms);
+        let compression = args.compression.as_ref().map(|c| match c {
+            CompressionArg::None => StoreCompressionCfg::None,
+            CompressionArg::Gzip => StoreCompressionCfg::Gzip,
+        });
+        let tile_store =
+            tile_store_from_config(&store_cfg, "", None, &format, &compression, metadata).await;
+        let compression = tile_store.compression();
+        let tile_writer = Arc::new(tile_store.setup_writer(true).await?);
+        let tile_reader = Arc::new(source.reader);
+
+        info!("Copying tiles");
+
+        let read_concurrency = match &store_cfg {
+            TileStoreCfg::Pmtiles { .. } => Concurrency::serial(),
+            _ => Concurrency::concurrent_unordered(threads),
+        };
+        let copy_count = Arc::new(AtomicU64::new(0));
+        let copy_count_main = copy_count.clone();
+        let pipeline_error = PipelineError::default();
+        let pipeline_error_main = pipeline_error.clone();
+        let iter_error = pipeline_error.clone();
+        let iter = tiles
+            .take_while(move |_| !iter_error.is_set())
+            .inspect(move |xyz| {
+                let path = CacheLayout::Zxy.path_string(&PathBuf::new(), xyz, &format);
+                progress.set_message(path);
+                progress.inc(1);
+            });
+        let pipeline = Pipeline::from_iter(iter)
+            .map(
+                move |xyz| {
+                    let tile_reader = tile_reader.clone();
+                    let compression = compression.clone();
+                    let copy_count = copy_count.clone();
+                    let pipeline_error = pipeline_error.clone();
+                    async move {
+                        match copy_tile(tile_reader.as_ref().as_ref(), &xyz, &compression).await {
+                            Ok(Some(tile)) => {
+                                copy_count.fetch_add(1, Ordering::Relaxed);
+                                vec![tile]
+                            }
+                            Ok(None) => Vec::new(),
+                            Err(e) => {
+                                warn!("Copying tile {xyz:?} failed: {e}");
+                                pipeline_error.set(format!("tile {xyz:?}: {e}"));
+                                Vec::new()
+                            }
+                        }
+                    }
+                },
+                read_concurrency,
+            )
+            .backpressure(100);
+        let pipeline = write_tiles(
+            pipeline,
+            &store_cfg,
+            tile_writer,
+            1,
+            threads,
+            args.tasks,
+            pipeline_error_main.clone(),
+        );
+
+        let (mut output_receiver, _join_handle) = pipeline.build();
+        while let Some(_output) = output_receiver.recv().await {}
+
+        if let Some(error) = pipeline_error_main.take() {
+            progress_main.abandon_with_message(format!("Copying failed: {error}"));
+            anyhow::bail!("Copying failed at {error}");
+        }
+        progress_main.set_style(
+            ProgressStyle::default_spinner().template("{elapsed_precise} ({per_sec}) {msg}"),
+        );
+        let cnt = progress_main.position();
+        let elapsed = progress_main.elapsed().as_millis() as f64 / 1000.0;
+        let copied = copy_count_main.load(Ordering::Relaxed);
+        progress_main
+            .finish_with_message(format!("{copied} of {cnt} tiles copied in {elapsed:.2}s"));
+
+        Ok(())
+    }
+
+    /// Tile source from tileset cache or MBTiles/PMTiles file or tile directory
+    pub(crate) async fn store_source(
+        &self,
+        src: Option<&str>,
+        tileset: Option<&str>,
+        tms_id: Option<&str>,
+        format: Option<&str>,
+    ) -> anyhow::Result<StoreSource> {
+        if let Some(name) = tileset {
+            let tileset = self
+                .tileset(name)
+                .ok_or(ServiceError::TilesetNotFound(name.to_string()))?;
+            let tms = if let Some(tms_id) = tms_id {
+                tileset.grid(tms_id)?
+            } else {
+                &tileset
+                    .tms
+                    .first()
+                    .ok_or(ServiceError::TilesetGridNotFound)?
+                    .tms
+            };
+            let Some(tile_store) = tileset.tile_store(tms) else {
+                return Err(ServiceError::TilesetNotFound(
+                    "Tile store configuration not found".to_string(),
+                )
+                .into());
+            };
+            return Ok(StoreSource {
+                reader: tile_store.setup_reader(true).await?,
+                metadata: tileset.mbtiles_metadata(tms).await?,
+                format: *tileset.tile_format(),
+                tms: tms.clone(),
+            });
+        }
+        let Some(src) = src else {
+            anyhow::bail!("Missing source (file, directory or --tileset)");
+        };
+        let name = Path::new(src)
+            .file_stem()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(src.to_string());
+        let arg_format = if let Some(suffix) = format {
+            let Some(format) = Format::from_suffix(suffix) else {
+                anyhow::bail!("Unsupported tile format `{suffix}`");
+            };
+            Some(format)
+        } else {
+            None
+        };
+        let (reader, metadata, format) = if let Some(cfg) = MbtilesStore::config_from_cli_arg(src) {
+            let ds = MbtilesDatasource::from_config(&cfg, None).await?;
+            let metadata = ds.get_metadata().await?;
+            let format = Format::from_suffix(&metadata.tile_info.format.to_string());
+            let reader: Box<dyn TileReader> = Box::new(ds);
+            (reader, metadata, format)
+        } else if let Some(cfg) = PmtilesStoreReader::config_from_cli_arg(src) {
+            let reader = PmtilesStoreReader::from_config(&cfg).await?;
+            let metadata = pmtiles_metadata(&name, &reader.get_metadata().await?);
+            let format = Format::from_suffix(&metadata.tile_info.format.to_string());
+            let reader: Box<dyn TileReader> = Box::new(reader);
+            (reader, metadata, format)
+        } else {
+            let Some(format) = arg_format else {
+                anyhow::bail!("Tile format of tile directory missing (--format)");
+            };
+            let metadata = default_metadata(&name);
+            let reader = tile_dir_reader(src, &format, metadata.clone()).await?;
+            (reader, metadata, Some(format))
+        };
+        let format = arg_format.or(format).unwrap_or(Format::Mvt);
+        let tms_id = tms_id
+            .or_else(|| metadata.json.as_ref().and_then(metadata_tms_id))
+            .unwrap_or("WebMercatorQuad");
+        let tms = if let Some(grid) = self.grid(tms_id) {
+            grid
+        } else {
+            tms().lookup(tms_id)?
+        };
+        Ok(StoreSource {
+            reader,
+            metadata,
+            format,
+            tms,
+        })
+    }
+}
+
+/// Reader of tiles in `{z}/{x}/{y}.{format}` files below `dir`
+async fn tile_dir_reader(
+    dir: &str,
+    format: &Format,
+    metadata: Metadata,
+) -> Result<Box<dyn TileReader>, TileStoreError> {
+    let store_cfg = FileStoreCfg {
+        base_dir: dir.into(),
+        deduplication: None,
+    };
+    // Without tileset name and grid namespace, tiles are read from `dir` itself
+    let store = store_cfg.to_store("", None, format, &None, metadata);
+    store.setup_reader(true).await
+}
+
+/// Read tile with target compression
+///
+/// References to the stored empty tile are written as empty tiles.
+async fn copy_tile(
+    reader: &dyn TileReader,
+    xyz: &Xyz,
+    compression: &Compression,
+) -> anyhow::Result<Option<TileData>> {
+    let Some(tile) = reader.get_stored_tile(xyz).await? else {
+        return Ok(None);
+    };
+    let data = tile.response.read_bytes(&Compression::None)?;
+    Ok(Some(TileData {
+        xyz: *xyz,
+        data: recompress(data.body, compression)?,
+        empty: tile.empty,
+    }))
+}
+
+/// Tile data with target compression
+///
+/// Compression of source data is detected from content, since not all stores record it.
+pub(crate) fn recompress(data: Vec<u8>, compression: &Compression) -> std::io::Result<Vec<u8>> {
+    let mut response = TileResponse::new();
+    if data.starts_with(&GZIP_MAGIC) {
+        response.insert_header(("Content-Encoding", "gzip"));
+    }
+    let data = response
+        .with_body(Box::new(Cursor::new(data)))
+        .with_compression(compression)
+        .read_bytes(&Compression::None)?;
+    Ok(data.body)
+}
+
+fn default_metadata(name: &str) -> Metadata {
+    Metadata {
+        id: name.to_string(),
+        tile_info: martin_tile_utils::TileInfo {
+            format: martin_tile_utils::Format::Mvt,
+            encoding: martin_tile_utils::Encoding::Uncompressed,
+        },
+        tilejson: tilejson::tilejson! { tiles: vec![] },
+        layer_type: None,
+        json: None,
+        agg_tiles_hash: None,
+    }
+}
+
+/// Metadata from PMTiles JSON metadata
+fn pmtiles_metadata(name: &str, json: &str) -> Metadata {
+    let mut metadata = default_metadata(name);
+    let Ok(mut json) = serde_json::from_str::<serde_json::Value>(json) else {
+        return metadata;
+    };
+    if let Some(obj) = json.as_object_mut() {
+        obj.entry("tiles").or_insert_with(|| json!([]));
+    }
+    if let Ok(tilejson) = serde_json::from_value::<TileJSON>(json.clone()) {
+        metadata.tilejson = tilejson;
+    }
+    if let Some(format) = json
+        .get("format")
+        .and_then(|f| f.as_str())
+        .and_then(martin_tile_utils::Format::parse)
+    {
+        metadata.tile_info.format = format;
+    }
+    if let Some(tms_id) = metadata_tms_id(&json) {
+        metadata.json = Some(json!({ "tms": tms_id }));
+    }
+    metadata
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_log::test;
+
+    #[test]
+    fn recompress_tiles() {
+        let raw = b"tile data".to_vec();
+        let gzip = recompress(raw.clone(), &Compression::Gzip).unwrap();
+        assert!(gzip.starts_with(&GZIP_MAGIC));
+        // Already compressed data is not compressed twice
+        assert_eq!(recompress(gzip.clone(), &Compression::Gzip).unwrap(), gzip);
+        assert_eq!(recompress(gzip, &Compression::None).unwrap(), raw);
+        assert_eq!(recompress(raw.clone(), &Compression::None).unwrap(), raw);
+    }
+
+    #[test]
+    fn metadata_from_pmtiles() {
+        let metadata = pmtiles_metadata(
+            "tiles",
+            r#"{"name":"Countries","format":"pbf","tms":"WebMercatorQuad","vector_layers":[{"id":"country","fields":{}}]}"#,
+        );
+        assert_eq!(metadata.tilejson.name.as_deref(), Some("Countries"));
+        assert_eq!(metadata.tilejson.vector_layers.unwrap()[0].id, "country");
+        assert_eq!(
+            metadata.json.as_ref().and_then(metadata_tms_id),
+            Some("WebMercatorQuad")
+        );
+    }
+
+    #[test(tokio::test)]
+    async fn read_tile_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let tile_path = dir.path().join("3/1/2.pbf");
+        std::fs::create_dir_all(tile_path.parent().unwrap()).unwrap();
+        std::fs::write(&tile_path, b"tile data").unwrap();
+
+        let reader = tile_dir_reader(
+            &dir.path().to_string_lossy(),
+            &Format::Mvt,
+            default_metadata("tiles"),
+        )
+        .await
+        .unwrap();
+        let xyz = Xyz::new(1, 2, 3);
+        assert!(reader.get_tile(&xyz).await.unwrap().is_some());
+        let tms = tms().lookup("WebMercatorQuad").unwrap();
+        let tiles = stored_tiles(reader.as_ref(), &tms, None, None, None)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>();
+        assert_eq!(tiles, [xyz]);
+    }
+}
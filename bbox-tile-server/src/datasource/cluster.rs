@@ -0,0 +1,188 @@
+//! Point clustering of MVT features.
+
+use crate::config::{AggregateFunction, ClusterCfg};
+use crate::datasource::mvt::{unzigzag, zigzag, MvtLayerBuilder};
+use crate::datasource::TileSourceError;
+use geozero::mvt;
+use std::collections::{BTreeMap, HashMap};
+
+/// Grid based clustering of point features in tile coordinates
+pub struct PointClusterer<'a> {
+    cfg: &'a ClusterCfg,
+    cell_size: f64,
+    clusters: BTreeMap<(i64, i64), PointCluster>,
+}
+
+struct PointCluster {
+    count: i64,
+    sum_x: f64,
+    sum_y: f64,
+    /// Aggregated values in order of configured aggregates
+    values: Vec<Option<f64>>,
+}
+
+impl<'a> PointClusterer<'a> {
+    pub fn new(cfg: &'a ClusterCfg, tile_size: u32) -> Self {
+        PointClusterer {
+            cfg,
+            cell_size: cfg.cell_size(tile_size).max(1.0),
+            clusters: BTreeMap::new(),
+        }
+    }
+    /// Add point feature. Returns `false` for other geometry types.
+    pub fn add_feature(
+        &mut self,
+        feature: &mvt::tile::Feature,
+        values: &HashMap<&str, mvt::tile::Value>,
+    ) -> bool {
+        let Some((x, y)) = point_coords(&feature.geometry) else {
+            return false;
+        };
+        let key = (
+            (x as f64 / self.cell_size).floor() as i64,
+            (y as f64 / self.cell_size).floor() as i64,
+        );
+        let cluster = self.clusters.entry(key).or_insert_with(|| PointCluster {
+            count: 0,
+            sum_x: 0.0,
+            sum_y: 0.0,
+            values: vec![None; self.cfg.aggregates.len()],
+        });
+        cluster.count += 1;
+        cluster.sum_x += x as f64;
+        cluster.sum_y += y as f64;
+        for (aggregate, acc) in self.cfg.aggregates.iter().zip(cluster.values.iter_mut()) {
+            let Some(value) = values.get(aggregate.field.as_str()) else {
+                continue;
+            };
+            if aggregate.function == AggregateFunction::Count {
+                *acc = Some(acc.unwrap_or(0.0) + 1.0);
+                continue;
+            }
+            let Some(value) = numeric_value(value) else {
+                continue;
+            };
+            *acc = Some(match (aggregate.function, *acc) {
+                (_, None) => value,
+                (AggregateFunction::Sum, Some(sum)) => sum + value,
+                (AggregateFunction::Min, Some(min)) => min.min(value),
+                (AggregateFunction::Max, Some(max)) => max.max(value),
+                (AggregateFunction::Count, Some(count)) => count,
+            });
+        }
+        true
+    }
+    /// Add cluster features with `point_count` and aggregated attributes to layer
+    pub fn push_features(self, mvt_layer: &mut MvtLayerBuilder) -> Result<(), TileSourceError> {
+        for cluster in self.clusters.into_values() {
+            let x = (cluster.sum_x / cluster.count as f64).round() as i32;
+            let y = (cluster.sum_y / cluster.count as f64).round() as i32;
+            let mut feat = mvt::tile::Feature {
+                r#type: Some(mvt::tile::GeomType::Point as i32),
+                geometry: vec![9, zigzag(x), zigzag(y)],
+                ..Default::default()
+            };
+            let point_count = mvt::tile::Value {
+                int_value: Some(cluster.count),
+                ..Default::default()
+            };
+            mvt_layer.add_feature_attribute(&mut feat, "point_count", point_count)?;
+            for (aggregate, value) in self.cfg.aggregates.iter().zip(cluster.values) {
+                let Some(value) = value else {
+                    continue;
+                };
+                let value = if aggregate.function == AggregateFunction::Count {
+                    mvt::tile::Value {
+                        int_value: Some(value as i64),
+                        ..Default::default()
+                    }
+                } else {
+                    mvt::tile::Value {
+                        double_value: Some(value),
+                        ..Default::default()
+                    }
+                };
+                mvt_layer.add_feature_attribute(&mut feat, &aggregate.name(), value)?;
+            }
+            mvt_layer.push_feature(feat);
+        }
+        Ok(())
+    }
+}
+
+/// Coordinates of single point geometry
+fn point_coords(geometry: &[u32]) -> Option<(i32, i32)> {
+    // MoveTo command with one point
+    if let [9, x, y] = geometry {
+        Some((unzigzag(*x), unzigzag(*y)))
+    } else {
+        None
+    }
+}
+
+fn numeric_value(value: &mvt::tile::Value) -> Option<f64> {
+    value
+        .double_value
+        .or(value.float_value.map(f64::from))
+        .or(value.int_value.map(|v| v as f64))
+        .or(value.sint_value.map(|v| v as f64))
+        .or(value.uint_value.map(|v| v as f64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ClusterAggregateCfg;
+    use crate::datasource::mvt::MvtBuilder;
+    use geozero::mvt::Message;
+
+    fn point(x: i32, y: i32) -> mvt::tile::Feature {
+        mvt::tile::Feature {
+            r#type: Some(mvt::tile::GeomType::Point as i32),
+            geometry: vec![9, zigzag(x), zigzag(y)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn cluster_points() {
+        let cfg = ClusterCfg {
+            radius: 16,
+            maxzoom: None,
+            aggregates: vec![ClusterAggregateCfg {
+                field: "population".to_string(),
+                function: AggregateFunction::Sum,
+                name: None,
+            }],
+        };
+        // Cell size 256 in tile coordinates
+        let mut clusterer = PointClusterer::new(&cfg, 4096);
+        for (x, y, pop) in [(10, 10, 100), (30, 50, 200), (300, 10, 50)] {
+            let value = mvt::tile::Value {
+                int_value: Some(pop),
+                ..Default::default()
+            };
+            let values = HashMap::from([("population", value)]);
+            assert!(clusterer.add_feature(&point(x, y), &values));
+        }
+        let line = mvt::tile::Feature {
+            geometry: vec![9, 0, 0, 10, 2, 2],
+            ..Default::default()
+        };
+        assert!(!clusterer.add_feature(&line, &HashMap::new()));
+
+        let mut layer = MvtBuilder::new_layer("places", 4096);
+        clusterer.push_features(&mut layer).unwrap();
+        let mut mvt = MvtBuilder::new();
+        mvt.push_layer(layer);
+        let blob = mvt.into_blob().unwrap();
+        let tile = mvt::Tile::decode(blob.as_slice()).unwrap();
+        let tile = &tile.layers[0];
+        assert_eq!(tile.features.len(), 2);
+        assert_eq!(point_coords(&tile.features[0].geometry), Some((20, 30)));
+        assert_eq!(tile.keys, vec!["point_count", "population_sum"]);
+        // point_count = 2, population_sum = 300
+        assert_eq!(tile.values[0].int_value, Some(2));
+        assert_eq!(tile.values[1].double_value, Some(300.0));
+    }
+}
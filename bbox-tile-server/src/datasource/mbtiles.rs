@@ -6,6 +6,7 @@ use crate::datasource::{
 };
 use crate::filter_params::FilterParams;
 use crate::mbtiles_ds::MbtilesDatasource;
+use crate::service::TileSetGrid;
 use crate::store::TileReader;
 use async_trait::async_trait;
 use bbox_core::Format;
@@ -58,6 +59,7 @@ impl TileSource for MbtilesDatasource {
     async fn mbtiles_metadata(
         &self,
         _tileset: &TileSetCfg,
+        _grid: &TileSetGrid,
         _format: &Format,
     ) -> Result<Metadata, TileSourceError> {
         Ok(self.get_metadata().await?)
@@ -1,29 +1,33 @@
 //! Tile source implementations.
 
+mod cluster;
 pub mod mbtiles;
-mod mvt;
+pub(crate) mod mvt;
 pub mod pmtiles;
 pub mod postgis;
 mod postgis_queries;
+pub mod vector_render;
 #[cfg(feature = "map-server")]
 pub mod wms_fcgi;
 pub mod wms_http;
 
-use crate::config::{SourceParamCfg, TileSetCfg, TilesetTmsCfg};
-use crate::filter_params::FilterParams;
+use crate::config::{PostgisTablesCfg, SourceParamCfg, TileSetCfg, TilesetTmsCfg};
+use crate::filter_params::{FilterParamError, FilterParams};
 use crate::mbtiles_ds::MbtilesDatasource;
-use crate::service::{TileSetGrid, TmsExtensions};
+use crate::service::{QueryExtent, TileSetGrid, Tilesets, TmsExtensions};
 use crate::store::mbtiles::MbtilesStore;
+use crate::store::metadata_tms_id;
 use crate::store::pmtiles::PmtilesStoreReader;
 use async_trait::async_trait;
 use bbox_core::config::{error_exit, DatasourceCfg, NamedDatasourceCfg};
 use bbox_core::{Format, NamedObjectStore, TileResponse};
 use dyn_clone::{clone_trait_object, DynClone};
 use geozero::error::GeozeroError;
+use log::{info, warn};
 use martin_mbtiles::Metadata;
 use once_cell::sync::OnceCell;
 use std::env;
-use tile_grid::{tms, RegistryError, Tms, Xyz};
+use tile_grid::{RegistryError, Tms, Xyz};
 use tilejson::TileJSON;
 
 #[derive(thiserror::Error, Debug)]
@@ -34,6 +38,8 @@ pub enum TileSourceError {
     TileSourceTypeError(String),
     #[error("missing filter parameter")]
     FilterParamError,
+    #[error(transparent)]
+    InvalidFilterParam(#[from] FilterParamError),
     #[error("tile not found / out of bounds")]
     TileXyzError,
     #[error(transparent)]
@@ -52,12 +58,30 @@ pub enum TileSourceError {
     GeozeroError(#[from] GeozeroError),
     #[error("MVT encoding error")]
     MvtEncodeError, // prost::error::EncodeError
+    #[error("MVT decoding error")]
+    MvtDecodeError,
+    #[error("GeoJSON encoding error")]
+    GeoJsonEncodeError,
+    #[error("Coordinate transformation not supported")]
+    TransformError,
+    #[error(transparent)]
+    ImageError(#[from] image::ImageError),
+    #[error(transparent)]
+    PngEncodingError(#[from] png::EncodingError),
+    #[error("Overzooming of `{0}` tiles not supported")]
+    OverzoomFormatError(String),
     #[error(transparent)]
     WmsHttpError(#[from] reqwest::Error),
+    #[error("WMS request failed: {0}")]
+    WmsServiceError(String),
     #[error(transparent)]
     MbtilesError(#[from] martin_mbtiles::MbtError),
     #[error(transparent)]
     PmtilesError(#[from] ::pmtiles::PmtError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("Vector tile request failed: {0}")]
+    VectorTileError(String),
 }
 
 #[derive(PartialEq, Clone, Debug)]
@@ -84,17 +108,30 @@ pub trait TileSource: DynClone + Send + Sync {
         format: &Format,
         request_params: wms_fcgi::HttpRequestParams<'_>,
     ) -> Result<TileResponse, TileSourceError>;
+    /// Request image covering a custom extent, e.g. a metatile
+    async fn extent_request(
+        &self,
+        _extent_info: &QueryExtent,
+        _format: &Format,
+        _request_params: wms_fcgi::HttpRequestParams<'_>,
+    ) -> Result<TileResponse, TileSourceError> {
+        Err(TileSourceError::TileSourceTypeError(
+            "wms_proxy or map_service".to_string(),
+        ))
+    }
     /// Type information
     fn source_type(&self) -> SourceType;
     /// Default tile format
     fn default_format(&self) -> &Format {
         match self.source_type() {
             SourceType::Vector => &Format::Mvt,
-            SourceType::Raster => &Format::Png, // TODO: support for "image/png; mode=8bit"
+            SourceType::Raster => &Format::Png,
         }
     }
     /// Set MapService for WmsFcgiSource
     fn set_map_service(&mut self, _service: &wms_fcgi::MapService) {}
+    /// Set tilesets with initialized caches for VectorRenderSource
+    fn set_tilesets(&mut self, _tilesets: &Tilesets) {}
     /// MapService metrics
     fn wms_metrics(&self) -> &'static wms_fcgi::WmsMetrics {
         static DUMMY_METRICS: OnceCell<wms_fcgi::WmsMetrics> = OnceCell::new();
@@ -108,9 +145,33 @@ pub trait TileSource: DynClone + Send + Sync {
     async fn mbtiles_metadata(
         &self,
         tileset: &TileSetCfg,
+        grid: &TileSetGrid,
         format: &Format,
     ) -> Result<Metadata, TileSourceError> {
-        let tms = tms().lookup("WebMercatorQuad").unwrap(); // FIXME: Use TMS from tilegrid
+        let mut tilejson = self.tilejson(&grid.tms, format).await?;
+        tilejson.minzoom = Some(grid.minzoom);
+        tilejson.maxzoom = Some(grid.maxzoom);
+        if tilejson.bounds.is_none() {
+            tilejson.bounds = grid.tms.bbox().ok().map(|bbox| tilejson::Bounds {
+                left: bbox.left,
+                bottom: bbox.bottom,
+                right: bbox.right,
+                top: bbox.top,
+            });
+        }
+        if tilejson.center.is_none() {
+            tilejson.center = tilejson.bounds.as_ref().map(|bounds| tilejson::Center {
+                longitude: (bounds.left + bounds.right) / 2.0,
+                latitude: (bounds.bottom + bounds.top) / 2.0,
+                zoom: grid.minzoom,
+            });
+        }
+        if let Some(layers) = &mut tilejson.vector_layers {
+            for layer in layers {
+                layer.minzoom = Some(layer.minzoom.unwrap_or(0).max(grid.minzoom));
+                layer.maxzoom = Some(layer.maxzoom.unwrap_or(grid.maxzoom).min(grid.maxzoom));
+            }
+        }
         Ok(Metadata {
             id: tileset.name.clone(),
             tile_info: martin_tile_utils::TileInfo {
@@ -118,7 +179,7 @@ pub trait TileSource: DynClone + Send + Sync {
                     .unwrap_or(martin_tile_utils::Format::Mvt),
                 encoding: martin_tile_utils::Encoding::Uncompressed,
             },
-            tilejson: self.tilejson(&tms, format).await?,
+            tilejson,
             layer_type: None,
             json: None,
             agg_tiles_hash: None,
@@ -158,6 +219,25 @@ impl Datasources {
         }
         ds_handler
     }
+    /// Tileset configurations for geometry tables of a PostGIS datasource
+    pub async fn postgis_tilesets(&self, cfg: &PostgisTablesCfg) -> Vec<TileSetCfg> {
+        let ds = self
+            .pg_datasources
+            .get_or_default(cfg.datasource.as_deref())
+            .unwrap_or_else(|| {
+                error_exit(TileSourceError::TileSourceNotFound(
+                    cfg.datasource.clone().unwrap_or("(default)".to_string()),
+                ))
+            });
+        let tables = postgis::detect_geometry_tables(ds)
+            .await
+            .unwrap_or_else(error_exit);
+        let tilesets = postgis::tables_tileset_cfgs(cfg, &tables);
+        for ts in &tilesets {
+            info!("Adding PostGIS tileset `{}`", ts.name);
+        }
+        tilesets
+    }
     /// Setup tile source instance
     pub async fn setup_tile_source(
         &self,
@@ -192,9 +272,8 @@ impl Datasources {
                         "wms_proxy".to_string(),
                     ))
                 };
-                let first_srid = ts_grids.first().expect("default grid missing").tms.srid(); // TODO: Support multiple grids
                 Box::new(wms_http::WmsHttpSource::from_config(
-                    provider, cfg, first_srid,
+                    provider, cfg, ts_grids,
                 ))
             }
             #[cfg(feature = "map-server")]
@@ -220,20 +299,43 @@ impl Datasources {
                     });
                 Box::new(postgis::PgSource::create(ds, pg_cfg, ts_grids, tms_cfg).await)
             }
-            SourceParamCfg::Mbtiles(cfg) => Box::new(
-                MbtilesDatasource::from_config(cfg, None)
+            SourceParamCfg::Mbtiles(cfg) => {
+                let source = MbtilesDatasource::from_config(cfg, None)
+                    .await
+                    .unwrap_or_else(error_exit);
+                let metadata = source.get_metadata().await.ok().and_then(|m| m.json);
+                check_stored_grid(&cfg.path.to_string_lossy(), metadata, ts_grids);
+                Box::new(source)
+            }
+            SourceParamCfg::Pmtiles(cfg) => {
+                let source = PmtilesStoreReader::from_config(cfg)
                     .await
-                    .unwrap_or_else(error_exit),
-            ),
-            SourceParamCfg::Pmtiles(cfg) => Box::new(
-                PmtilesStoreReader::from_config(cfg)
+                    .unwrap_or_else(error_exit);
+                let metadata = source
+                    .get_metadata()
                     .await
-                    .unwrap_or_else(error_exit),
-            ),
+                    .ok()
+                    .and_then(|m| serde_json::from_str(&m).ok());
+                check_stored_grid(&cfg.path.to_string_lossy(), metadata, ts_grids);
+                Box::new(source)
+            }
+            SourceParamCfg::VectorRender(_) => {
+                unreachable!("vector_render sources are created from their vector tileset")
+            }
         }
     }
 }
 
+/// Warn about tile archives with tiles of a grid not configured for the tileset
+fn check_stored_grid(path: &str, metadata: Option<serde_json::Value>, ts_grids: &[TileSetGrid]) {
+    let Some(tms_id) = metadata.as_ref().and_then(metadata_tms_id) else {
+        return;
+    };
+    if !ts_grids.iter().any(|grid| grid.tms.id() == tms_id) {
+        warn!("Tile archive `{path}` contains tiles of grid `{tms_id}`, which is not configured for this tileset");
+    }
+}
+
 pub fn source_config_from_cli_arg(file_or_url: &str) -> Option<SourceParamCfg> {
     MbtilesStore::config_from_cli_arg(file_or_url)
         .map(SourceParamCfg::Mbtiles)
@@ -72,6 +72,57 @@ impl MvtLayerBuilder {
     }
 }
 
+/// Zigzag encoding of MVT geometry parameters
+pub(crate) fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+/// Zigzag decoding of MVT geometry parameters
+pub(crate) fn unzigzag(n: u32) -> i32 {
+    ((n >> 1) as i32) ^ -((n & 1) as i32)
+}
+
+/// JSON value of MVT attribute value
+pub(crate) fn json_value(value: &mvt::tile::Value) -> Option<serde_json::Value> {
+    if let Some(v) = &value.string_value {
+        Some(v.clone().into())
+    } else if let Some(v) = value.double_value {
+        Some(v.into())
+    } else if let Some(v) = value.float_value {
+        Some(f64::from(v).into())
+    } else if let Some(v) = value.int_value {
+        Some(v.into())
+    } else if let Some(v) = value.uint_value {
+        Some(v.into())
+    } else if let Some(v) = value.sint_value {
+        Some(v.into())
+    } else {
+        value.bool_value.map(serde_json::Value::from)
+    }
+}
+
+/// Encoded size and feature count of a tile layer.
+pub struct MvtLayerStats {
+    pub name: String,
+    pub bytes: usize,
+    pub features: usize,
+}
+
+/// Layer statistics of a tile, largest layers first.
+pub fn mvt_layer_stats(tile: &mvt::Tile) -> Vec<MvtLayerStats> {
+    let mut layer_stats = tile
+        .layers
+        .iter()
+        .map(|tl| MvtLayerStats {
+            name: tl.name.clone(),
+            bytes: tl.encoded_len(),
+            features: tl.features.len(),
+        })
+        .collect::<Vec<_>>();
+    layer_stats.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+    layer_stats
+}
+
 impl MvtBuilder {
     /// Diagnostics tile layer.
     pub fn add_diagnostics_layer(
@@ -97,19 +148,7 @@ impl MvtBuilder {
         .into();
         let mut feat = geom.to_mvt_unscaled()?;
 
-        let mut layer_stats = self
-            .tile
-            .layers
-            .iter()
-            .map(|tl| {
-                (
-                    format!("layer-{}", &tl.name),
-                    tl.encoded_len(),
-                    tl.features.len(),
-                )
-            })
-            .collect::<Vec<_>>();
-        layer_stats.sort_by(|a, b| b.1.cmp(&a.1));
+        let layer_stats = mvt_layer_stats(&self.tile);
 
         layer.add_feature_attribute(
             &mut feat,
@@ -126,13 +165,13 @@ impl MvtBuilder {
         for tl in layer_stats.iter().take(5) {
             layer.add_feature_attribute(
                 &mut feat,
-                &format!("{}-bytes", tl.0),
-                mvt::TileValue::Uint(tl.1 as u64).into(),
+                &format!("layer-{}-bytes", tl.name),
+                mvt::TileValue::Uint(tl.bytes as u64).into(),
             )?;
             layer.add_feature_attribute(
                 &mut feat,
-                &format!("{}-count", tl.0),
-                mvt::TileValue::Uint(tl.2 as u64).into(),
+                &format!("layer-{}-count", tl.name),
+                mvt::TileValue::Uint(tl.features as u64).into(),
             )?;
         }
 
@@ -1,13 +1,17 @@
 //! PostGIS tile source.
 
-use crate::config::{PostgisSourceParamsCfg, TilesetTmsCfg, VectorLayerCfg};
+use crate::config::{
+    ClusterCfg, PostgisSourceParamsCfg, PostgisTablesCfg, SourceParamCfg, TileSetCfg,
+    TilesetTmsCfg, VectorLayerCfg,
+};
 use crate::datasource::{
+    cluster::PointClusterer,
     mvt::MvtBuilder,
     postgis_queries::{QueryParam, SqlQuery},
     wms_fcgi::HttpRequestParams,
     LayerInfo, SourceType, TileSource, TileSourceError,
 };
-use crate::filter_params::FilterParams;
+use crate::filter_params::{FilterParamError, FilterParams, ParamValue};
 use crate::service::{TileSetGrid, TmsExtensions};
 use async_trait::async_trait;
 use bbox_core::pg_ds::PgDatasource;
@@ -41,6 +45,8 @@ pub struct PgMvtLayer {
     tile_size: u32,
     fid_field: Option<String>,
     query_limit: Option<u32>,
+    /// Point clustering
+    cluster: Option<ClusterCfg>,
     /// Queries for zoom steps for each grid_srid
     queries: HashMap<i32, HashMap<u8, QueryInfo>>,
     /// Query zoom step for all zoom levels (z -> minzoom step)
@@ -77,6 +83,19 @@ impl PgMvtLayer {
             .get(&zoom)
             .and_then(|minzoom| self.queries.get(&grid_srid).and_then(|gq| gq.get(minzoom)))
     }
+    /// Clustering in Rust for zoom level
+    fn cluster(&self, zoom: u8) -> Option<&ClusterCfg> {
+        if self.tile_coord_sys {
+            // Clustered in SQL query
+            return None;
+        }
+        self.cluster.as_ref().filter(|cluster| {
+            cluster
+                .maxzoom
+                .map(|maxzoom| zoom <= maxzoom)
+                .unwrap_or(true)
+        })
+    }
     pub fn minzoom(&self) -> u8 {
         *self.query_zoom_steps.keys().min().unwrap_or(&0)
     }
@@ -199,7 +218,21 @@ impl PgSource {
                 );
                 let param_types = query.param_types();
                 let stmt = match ds.pool.prepare_with(&query.sql, &param_types).await {
-                    Ok(stmt) => Statement::to_owned(&stmt), //stmt.to_owned()
+                    Ok(stmt) => {
+                        if layer.cluster(zoom).is_some() && !postgis2 {
+                            // Cluster attributes
+                            fields = stmt
+                                .columns()
+                                .iter()
+                                .map(|col| FieldInfo {
+                                    name: col.name().to_string(),
+                                    info: column_info(col, &layer.name),
+                                })
+                                .filter(|field| field.info != FieldTypeInfo::Ignored)
+                                .collect();
+                        }
+                        Statement::to_owned(&stmt) //stmt.to_owned()
+                    }
                     Err(e) => {
                         error!("Layer `{}` z{zoom}: Invalid query - {e}", layer.name);
                         error!(" Query: {}", query.sql);
@@ -250,12 +283,127 @@ impl PgSource {
             tile_size: layer.tile_size,
             fid_field: layer.fid_field.clone(),
             query_limit: layer.query_limit,
+            cluster: layer.cluster.clone(),
             queries: layer_queries,
             query_zoom_steps,
         })
     }
 }
 
+/// Geometry column registered in `geometry_columns`
+#[derive(Clone, Debug)]
+pub struct GeometryTable {
+    pub schema: String,
+    pub table: String,
+    pub geometry_column: String,
+    pub geometry_type: String,
+    pub srid: i32,
+    /// Integer primary key column
+    pub pk_column: Option<String>,
+}
+
+impl GeometryTable {
+    /// Table name, qualified with schema outside of `public`
+    fn name(&self) -> String {
+        if self.schema == "public" {
+            self.table.clone()
+        } else {
+            format!("{}_{}", self.schema, self.table)
+        }
+    }
+    /// Layer selecting all fields of the table
+    fn layer_cfg(&self, name: String) -> VectorLayerCfg {
+        let table_name = format!(r#""{}"."{}""#, self.schema, self.table);
+        VectorLayerCfg {
+            geometry_field: Some(self.geometry_column.clone()),
+            geometry_type: (self.geometry_type != "GEOMETRY").then(|| self.geometry_type.clone()),
+            srid: (self.srid > 0).then_some(self.srid),
+            fid_field: self.pk_column.clone(),
+            ..VectorLayerCfg::from_table(name, table_name)
+        }
+    }
+}
+
+/// Detect tables with geometry columns
+pub async fn detect_geometry_tables(
+    ds: &PgDatasource,
+) -> Result<Vec<GeometryTable>, TileSourceError> {
+    let sql = r#"
+        SELECT f_table_schema::text, f_table_name::text, f_geometry_column::text, srid, type::text,
+          (SELECT a.attname::text
+           FROM pg_index i
+             JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = i.indkey[0]
+           WHERE i.indrelid = format('%I.%I', f_table_schema, f_table_name)::regclass
+             AND i.indisprimary AND i.indnatts = 1
+             AND a.atttypid IN ('int2'::regtype, 'int4'::regtype, 'int8'::regtype)
+          ) AS pk_column
+        FROM geometry_columns
+        ORDER BY f_table_schema, f_table_name, f_geometry_column
+    "#;
+    let mut tables = Vec::new();
+    let mut rows = sqlx::query(sql).fetch(&ds.pool);
+    while let Some(row) = rows.try_next().await? {
+        tables.push(GeometryTable {
+            schema: row.try_get("f_table_schema")?,
+            table: row.try_get("f_table_name")?,
+            geometry_column: row.try_get("f_geometry_column")?,
+            geometry_type: row.try_get("type")?,
+            srid: row.try_get("srid")?,
+            pk_column: row.try_get("pk_column")?,
+        });
+    }
+    Ok(tables)
+}
+
+/// Tileset configurations for published geometry tables
+pub fn tables_tileset_cfgs(cfg: &PostgisTablesCfg, tables: &[GeometryTable]) -> Vec<TileSetCfg> {
+    let tables = tables
+        .iter()
+        .filter(|t| cfg.publish_table(&t.schema, &t.table))
+        .collect::<Vec<_>>();
+    let mut geom_counts = HashMap::new();
+    for t in &tables {
+        *geom_counts.entry((&t.schema, &t.table)).or_insert(0) += 1;
+    }
+    let layers = tables.iter().map(|t| {
+        // Tables with multiple geometry columns get a layer for each column
+        let name = if geom_counts[&(&t.schema, &t.table)] > 1 {
+            format!("{}_{}", t.name(), t.geometry_column)
+        } else {
+            t.name()
+        };
+        t.layer_cfg(name)
+    });
+    let source_cfg = |layers: Vec<VectorLayerCfg>| {
+        SourceParamCfg::Postgis(PostgisSourceParamsCfg {
+            datasource: cfg.datasource.clone(),
+            extent: None,
+            center: None,
+            start_zoom: None,
+            attribution: None,
+            postgis2: false,
+            diagnostics: None,
+            layers,
+        })
+    };
+    let tileset_cfg = |name: String, layers: Vec<VectorLayerCfg>| TileSetCfg {
+        tms: cfg.tms.clone(),
+        cache: cfg.cache.clone(),
+        ..TileSetCfg::from_source(name, source_cfg(layers))
+    };
+    if let Some(name) = &cfg.tileset {
+        let layers = layers.collect::<Vec<_>>();
+        if layers.is_empty() {
+            return Vec::new();
+        }
+        vec![tileset_cfg(name.clone(), layers)]
+    } else {
+        layers
+            .map(|layer| tileset_cfg(layer.name.clone(), vec![layer]))
+            .collect()
+    }
+}
+
 fn layer_query<'a>(
     layer: &'a PgMvtLayer,
     query_info: &'a QueryInfo,
@@ -294,14 +442,23 @@ fn layer_query<'a>(
                     return Err(TileSourceError::FilterParamError);
                 }
             }
-            QueryParam::QueryField(ref field) => {
+            QueryParam::QueryField(ref field, None) => {
                 if let Some(value) = filter.filters.get(field) {
                     query.bind(value)
                 } else {
                     info!("Filter parameter `{field}` missing");
-                    return Err(TileSourceError::FilterParamError);
+                    return Err(FilterParamError::Missing(field.clone()).into());
                 }
             }
+            QueryParam::QueryField(_, Some(ref param_cfg)) => match param_cfg.value(filter)? {
+                ParamValue::Text(v) => query.bind(v),
+                ParamValue::Int(v) => query.bind(v),
+                ParamValue::Float(v) => query.bind(v),
+                ParamValue::Date(v) => query.bind(v),
+                ParamValue::Bbox([minx, miny, maxx, maxy]) => {
+                    query.bind(minx).bind(miny).bind(maxx).bind(maxy)
+                }
+            },
         }
     }
     Ok(query)
@@ -333,6 +490,9 @@ impl TileSource for PgSource {
             debug!("Query layer `{id}`");
             let mut rows = query.fetch(&self.ds.pool);
             let mut mvt_layer = MvtBuilder::new_layer(id, layer.tile_size);
+            let mut clusterer = layer
+                .cluster(tile.z)
+                .map(|cfg| PointClusterer::new(cfg, layer.tile_size));
             let mut cnt = 0;
             let query_limit = layer.query_limit.unwrap_or(0);
             while let Some(row) = rows.try_next().await? {
@@ -353,6 +513,17 @@ impl TileSource for PgSource {
                         extent.top,
                     )?
                 };
+                if let Some(clusterer) = &mut clusterer {
+                    let mut values = HashMap::new();
+                    for field in &query_info.fields {
+                        if let Some(val) = column_value(&row, field)? {
+                            values.insert(field.name.as_str(), val);
+                        }
+                    }
+                    if clusterer.add_feature(&feat, &values) {
+                        continue;
+                    }
+                }
                 for field in &query_info.fields {
                     if field.name == query_info.geometry_field {
                         continue;
@@ -379,6 +550,9 @@ impl TileSource for PgSource {
                     break;
                 }
             }
+            if let Some(clusterer) = clusterer {
+                clusterer.push_features(&mut mvt_layer)?;
+            }
             mvt.push_layer(mvt_layer);
         }
         if let Some(diaganostics_cfg) = &self.config.diagnostics {
@@ -628,6 +802,52 @@ mod tests {
     use test_log::test;
     use tile_grid::tms;
 
+    #[test]
+    fn geometry_table_tilesets() {
+        let table = |schema: &str, table: &str, geometry_column: &str| GeometryTable {
+            schema: schema.to_string(),
+            table: table.to_string(),
+            geometry_column: geometry_column.to_string(),
+            geometry_type: "MULTIPOLYGON".to_string(),
+            srid: 3857,
+            pk_column: Some("fid".to_string()),
+        };
+        let tables = vec![
+            table("public", "countries", "geom"),
+            table("osm", "buildings", "geom"),
+            table("osm", "buildings", "centroid"),
+        ];
+        let mut cfg = PostgisTablesCfg {
+            datasource: None,
+            tileset: None,
+            include: vec!["*".to_string()],
+            exclude: Vec::new(),
+            tms: Vec::new(),
+            cache: None,
+        };
+        let names = tables_tileset_cfgs(&cfg, &tables)
+            .into_iter()
+            .map(|ts| ts.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["countries", "osm_buildings_geom", "osm_buildings_centroid"]
+        );
+
+        cfg.tileset = Some("all".to_string());
+        cfg.exclude = vec!["osm.*".to_string()];
+        let tilesets = tables_tileset_cfgs(&cfg, &tables);
+        assert_eq!(tilesets.len(), 1);
+        let SourceParamCfg::Postgis(ref source) = tilesets[0].source else {
+            panic!("Wrong tileset source")
+        };
+        let layer = &source.layers[0];
+        assert_eq!(layer.name, "countries");
+        assert_eq!(layer.table_name.as_deref(), Some(r#""public"."countries""#));
+        assert_eq!(layer.srid, Some(3857));
+        assert_eq!(layer.fid_field.as_deref(), Some("fid"));
+    }
+
     // docker run -p 127.0.0.1:5439:5432 -d --name mvtbenchdb --rm sourcepole/mvtbenchdb:v1.2
     //
     // For debug log output run with:
@@ -665,6 +885,8 @@ mod tests {
             buffer_size: Some(0),
             make_valid: false,
             shift_longitude: false,
+            cluster: None,
+            params: Vec::new(),
         };
         let pg_src_cfg = PostgisSourceParamsCfg {
             datasource: None,
@@ -681,6 +903,8 @@ mod tests {
             tms,
             minzoom: 0,
             maxzoom: 24,
+            overzoom: None,
+            extent: None,
         }];
         let ds = PgDatasource::from_config(&ds_cfg, None).await.unwrap();
         PgSource::create(&ds, &pg_src_cfg, &ts_grids, &Vec::new()).await
@@ -1,4 +1,4 @@
-use crate::config::VectorLayerCfg;
+use crate::config::{AggregateFunction, ClusterCfg, QueryParamCfg, QueryParamType, VectorLayerCfg};
 use crate::datasource::postgis::{FieldInfo, FieldTypeInfo};
 use log::{info, warn};
 use regex::Regex;
@@ -17,7 +17,8 @@ pub enum QueryParam {
     Zoom,
     X,
     Y,
-    QueryField(String),
+    /// Custom field query variable with optional declaration
+    QueryField(String, Option<QueryParamCfg>),
     PixelWidth,
     ScaleDenominator,
 }
@@ -28,7 +29,12 @@ impl SqlQuery {
         if let Some(sql) = user_query {
             // Replace vars with valid SQL
             let bbox_expr = "ST_MakeEnvelope($1,$2,$3,$4,3857)";
-            Self::replace_params(sql, bbox_expr.to_string(), bbox_expr.to_string())
+            Self::replace_params(
+                sql,
+                bbox_expr.to_string(),
+                bbox_expr.to_string(),
+                &layer.params,
+            )
         } else {
             let sql = format!(
                 "SELECT * FROM {}",
@@ -55,7 +61,14 @@ impl SqlQuery {
         postgis2: bool,
     ) -> Self {
         let mut sqlquery;
-        let geom_expr = if postgis2 {
+        // SQL clustering (PostGIS 2 compatible queries are clustered in Rust)
+        let cluster = layer.cluster(zoom).filter(|_| !postgis2);
+        let geom_expr = if cluster.is_some() {
+            format!(
+                "{} AS _geom",
+                build_transform_expr(layer, geom_name, geom_name, tile_srid, zoom)
+            )
+        } else if postgis2 {
             build_geom_expr_postgis2(layer, geom_name, tile_srid, zoom)
         } else {
             build_geom_expr(layer, geom_name, tile_srid, zoom)
@@ -83,15 +96,24 @@ impl SqlQuery {
             );
         };
 
+        if let Some(cluster) = cluster {
+            sqlquery = build_cluster_query(layer, cluster, geom_name, &sqlquery);
+        }
+
         let bbox_expr = build_bbox_expr(layer, tile_srid, layer.buffer_size);
         // !bbox_unbuffered! replacement expression for ST_AsMVTGeom
         let bbox_expr_unbuffered = format!("ST_MakeEnvelope($1,$2,$3,$4,{tile_srid})");
-        Self::replace_params(&sqlquery, bbox_expr, bbox_expr_unbuffered)
+        Self::replace_params(&sqlquery, bbox_expr, bbox_expr_unbuffered, &layer.params)
     }
 
     /// Replace variables (!bbox!, !zoom!, etc.) in query
     // https://github.com/mapnik/mapnik/wiki/PostGIS
-    fn replace_params(sqlin: &str, bbox_expr: String, bbox_expr_unbuffered: String) -> Self {
+    fn replace_params(
+        sqlin: &str,
+        bbox_expr: String,
+        bbox_expr_unbuffered: String,
+        param_cfgs: &[QueryParamCfg],
+    ) -> Self {
         let mut sql = sqlin.to_string();
         let mut params = Vec::new();
         let mut numvars = 0;
@@ -131,9 +153,26 @@ impl SqlQuery {
             unique_fields.insert(field.to_string());
         }
         for field in unique_fields {
-            params.push(QueryParam::QueryField(field.to_string()));
-            numvars += 1;
-            sql = sql.replace(&format!("!{field}!"), &format!("${numvars}"));
+            let param_cfg = param_cfgs.iter().find(|p| p.name == field).cloned();
+            let expr = match &param_cfg {
+                Some(cfg) if cfg.param_type == QueryParamType::Bbox => {
+                    let n = numvars + 1;
+                    numvars += 4;
+                    format!(
+                        "ST_MakeEnvelope(${n},${},${},${},{})",
+                        n + 1,
+                        n + 2,
+                        n + 3,
+                        cfg.srid.unwrap_or(4326)
+                    )
+                }
+                _ => {
+                    numvars += 1;
+                    format!("${numvars}")
+                }
+            };
+            params.push(QueryParam::QueryField(field.to_string(), param_cfg));
+            sql = sql.replace(&format!("!{field}!"), &expr);
         }
         SqlQuery { sql, params }
     }
@@ -154,7 +193,17 @@ impl SqlQuery {
                 QueryParam::PixelWidth | QueryParam::ScaleDenominator => {
                     vec![PgTypeInfo::with_name("FLOAT8")]
                 }
-                QueryParam::QueryField(_) => vec![PgTypeInfo::with_name("VARCHAR")],
+                QueryParam::QueryField(_, param_cfg) => {
+                    match param_cfg.as_ref().map(|cfg| cfg.param_type) {
+                        None | Some(QueryParamType::String) | Some(QueryParamType::Enum) => {
+                            vec![PgTypeInfo::with_name("VARCHAR")]
+                        }
+                        Some(QueryParamType::Int) => vec![PgTypeInfo::with_name("INT8")],
+                        Some(QueryParamType::Float) => vec![PgTypeInfo::with_name("FLOAT8")],
+                        Some(QueryParamType::Date) => vec![PgTypeInfo::with_name("DATE")],
+                        Some(QueryParamType::Bbox) => vec![PgTypeInfo::with_name("FLOAT8"); 4],
+                    }
+                }
             })
             .collect()
     }
@@ -178,24 +227,7 @@ fn build_geom_expr(layer: &VectorLayerCfg, geom_name: &str, tile_srid: i32, zoom
     };
 
     // Transform geometry to grid SRID
-    if layer_srid <= 0 {
-        warn!(
-            "Layer '{}': Undefined SRS of geometry '{geom_name}' - assuming SRID {tile_srid}",
-            layer.name
-        );
-        // TODO: Get SRS from geometry_columns instead
-        geom_expr = format!("ST_SetSRID({geom_expr},{tile_srid})")
-    } else if layer_srid != tile_srid {
-        if layer.no_transform {
-            geom_expr = format!("ST_SetSRID({geom_expr},{tile_srid})");
-        } else {
-            info!(
-                "Layer '{}' z{zoom}: Reprojecting geometry '{geom_name}' from SRID {layer_srid} to {tile_srid}",
-                layer.name
-            );
-            geom_expr = format!("ST_Transform({geom_expr},{tile_srid})");
-        }
-    }
+    geom_expr = build_transform_expr(layer, &geom_expr, geom_name, tile_srid, zoom);
 
     // Simplify
     if layer.simplify(zoom) {
@@ -238,6 +270,65 @@ fn build_geom_expr(layer: &VectorLayerCfg, geom_name: &str, tile_srid: i32, zoom
     geom_expr
 }
 
+/// Build expression transforming geometry to grid SRID.
+fn build_transform_expr(
+    layer: &VectorLayerCfg,
+    geom_expr: &str,
+    geom_name: &str,
+    tile_srid: i32,
+    zoom: u8,
+) -> String {
+    let layer_srid = layer.srid.unwrap_or(0);
+    if layer_srid <= 0 {
+        warn!(
+            "Layer '{}': Undefined SRS of geometry '{geom_name}' - assuming SRID {tile_srid}",
+            layer.name
+        );
+        // TODO: Get SRS from geometry_columns instead
+        format!("ST_SetSRID({geom_expr},{tile_srid})")
+    } else if layer_srid != tile_srid {
+        if layer.no_transform {
+            format!("ST_SetSRID({geom_expr},{tile_srid})")
+        } else {
+            info!(
+                "Layer '{}' z{zoom}: Reprojecting geometry '{geom_name}' from SRID {layer_srid} to {tile_srid}",
+                layer.name
+            );
+            format!("ST_Transform({geom_expr},{tile_srid})")
+        }
+    } else {
+        geom_expr.to_string()
+    }
+}
+
+/// Build clustering query for features with `_geom` in grid SRID.
+fn build_cluster_query(
+    layer: &VectorLayerCfg,
+    cluster: &ClusterCfg,
+    geom_name: &str,
+    feature_query: &str,
+) -> String {
+    let tile_size = layer.tile_size;
+    let buffer = layer.buffer_size.unwrap_or(0);
+    let clip_geom = layer.buffer_size.is_some();
+    let cell_size = cluster.cell_size(tile_size);
+    let mut select_list = vec![
+        format!("ST_AsMvtGeom(ST_Centroid(ST_Collect(_geom)), !bbox_unbuffered!, {tile_size}, {buffer}, {clip_geom}) AS {geom_name}"),
+        "count(*) AS point_count".to_string(),
+    ];
+    for aggregate in &cluster.aggregates {
+        let expr = match aggregate.function {
+            AggregateFunction::Sum => format!(r#"sum("{}")::FLOAT8"#, aggregate.field),
+            function => format!(r#"{}("{}")"#, function.as_sql(), aggregate.field),
+        };
+        select_list.push(format!(r#"{expr} AS "{}""#, aggregate.name()));
+    }
+    format!(
+        "SELECT {} FROM ({feature_query}) AS _c GROUP BY ST_SnapToGrid(_geom, {cell_size}*!pixel_width!)",
+        select_list.join(",")
+    )
+}
+
 /// Build PostGIS 2 compatible geometry selection expression for feature query.
 fn build_geom_expr_postgis2(
     layer: &VectorLayerCfg,
@@ -328,23 +419,7 @@ fn build_geom_expr_postgis2(
     }
 
     // Transform geometry to grid SRID
-    if layer_srid <= 0 {
-        warn!(
-            "Layer '{}': Undefined SRS of geometry '{geom_name}' - assuming SRID {tile_srid}",
-            layer.name
-        );
-        geom_expr = format!("ST_SetSRID({geom_expr},{tile_srid})")
-    } else if layer_srid != tile_srid {
-        if layer.no_transform {
-            geom_expr = format!("ST_SetSRID({geom_expr},{tile_srid})");
-        } else {
-            info!(
-                "Layer '{}' z{zoom}: Reprojecting geometry '{geom_name}' from SRID {layer_srid} to {tile_srid}",
-                layer.name
-            );
-            geom_expr = format!("ST_Transform({geom_expr},{tile_srid})");
-        }
-    }
+    geom_expr = build_transform_expr(layer, &geom_expr, geom_name, tile_srid, zoom);
 
     if geom_expr.starts_with("ST_") || geom_expr.starts_with("COALESCE") {
         geom_expr = format!("{geom_expr} AS {geom_name}");
@@ -405,7 +480,7 @@ fn build_bbox_expr(layer: &VectorLayerCfg, tile_srid: i32, buffer_size: Option<u
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::config::VectorLayerQueryCfg;
+    use crate::config::{ClusterAggregateCfg, VectorLayerQueryCfg};
 
     fn layer_cfg() -> (VectorLayerCfg, Vec<FieldInfo>) {
         let layer = VectorLayerCfg {
@@ -426,6 +501,8 @@ mod test {
             buffer_size: None,
             make_valid: false,
             shift_longitude: false,
+            cluster: None,
+            params: Vec::new(),
         };
         let fields = vec![FieldInfo {
             name: "geometry".to_string(),
@@ -661,5 +738,68 @@ mod test {
         assert_eq!(SqlQuery::build_tile_query(&layer, "geometry", &fields, 3857, 10, layer.queries[0].sql.as_ref(), postgis2)
                    .sql,
                "SELECT ST_AsMvtGeom(geometry, ST_MakeEnvelope($1,$2,$3,$4,3857), 256, 0, false) AS geometry FROM (SELECT geometry FROM osm_place_point WHERE col1=$5 OR col2=$5) AS _q WHERE geometry && ST_MakeEnvelope($1,$2,$3,$4,3857)");
+
+        layer.queries = vec![VectorLayerQueryCfg {
+            minzoom: Some(0),
+            maxzoom: Some(22),
+            simplify: None,
+            tolerance: None,
+            sql: Some(String::from(
+                "SELECT geometry FROM osm_place_point WHERE geometry && ST_Transform(!area!, 3857) AND rank<=!rank!",
+            )),
+        }];
+        layer.params = vec![QueryParamCfg {
+            name: "area".to_string(),
+            param_type: QueryParamType::Bbox,
+            default: None,
+            min: None,
+            max: None,
+            values: Vec::new(),
+            srid: None,
+            description: None,
+        }];
+        let query = SqlQuery::build_tile_query(
+            &layer,
+            "geometry",
+            &fields,
+            3857,
+            10,
+            layer.queries[0].sql.as_ref(),
+            postgis2,
+        );
+        assert_eq!(query.sql,
+               "SELECT ST_AsMvtGeom(geometry, ST_MakeEnvelope($1,$2,$3,$4,3857), 256, 0, false) AS geometry FROM (SELECT geometry FROM osm_place_point WHERE geometry && ST_Transform(ST_MakeEnvelope($5,$6,$7,$8,4326), 3857) AND rank<=$9) AS _q WHERE geometry && ST_MakeEnvelope($1,$2,$3,$4,3857)");
+        assert_eq!(query.param_types().len(), 9);
+    }
+
+    #[test]
+    fn test_cluster_query() {
+        let (mut layer, mut fields) = layer_cfg();
+        fields.push(FieldInfo {
+            name: "population".to_string(),
+            info: FieldTypeInfo::Property(PgTypeInfo::with_name("INT4")),
+        });
+        layer.cluster = Some(ClusterCfg {
+            radius: 40,
+            maxzoom: Some(10),
+            aggregates: vec![ClusterAggregateCfg {
+                field: "population".to_string(),
+                function: AggregateFunction::Sum,
+                name: None,
+            }],
+        });
+        assert_eq!(
+            SqlQuery::build_tile_query(&layer, "geometry", &fields, 3857, 10, None, false).sql,
+            r#"SELECT ST_AsMvtGeom(ST_Centroid(ST_Collect(_geom)), ST_MakeEnvelope($1,$2,$3,$4,3857), 256, 0, false) AS geometry,count(*) AS point_count,sum("population")::FLOAT8 AS "population_sum" FROM (SELECT geometry AS _geom,"population" FROM osm_place_point WHERE geometry && ST_MakeEnvelope($1,$2,$3,$4,3857)) AS _c GROUP BY ST_SnapToGrid(_geom, 40*$5::FLOAT8)"#
+        );
+        // No clustering above maxzoom and for PostGIS 2 compatible queries
+        assert_eq!(
+            SqlQuery::build_tile_query(&layer, "geometry", &fields, 3857, 11, None, true).sql,
+            r#"SELECT geometry,"population" FROM osm_place_point WHERE geometry && ST_MakeEnvelope($1,$2,$3,$4,3857)"#
+        );
+        assert_eq!(
+            SqlQuery::build_tile_query(&layer, "geometry", &fields, 3857, 10, None, true).sql,
+            r#"SELECT geometry,"population" FROM osm_place_point WHERE geometry && ST_MakeEnvelope($1,$2,$3,$4,3857)"#
+        );
     }
 }
@@ -0,0 +1,705 @@
+//! Raster tiles rendered from vector tiles.
+//!
+//! Supports `background`, `fill`, `line` and `circle` layers of a Mapbox / MapLibre GL style
+//! with constant or zoom dependent (`stops`) paint properties and legacy filters.
+
+use crate::datasource::mvt::{json_value, unzigzag};
+use crate::datasource::{
+    wms_fcgi::HttpRequestParams, LayerInfo, SourceType, TileSource, TileSourceError,
+};
+use crate::filter_params::FilterParams;
+use crate::service::{ServiceError, TileSet, Tilesets, TmsExtensions};
+use async_trait::async_trait;
+use bbox_core::{Compression, Format, TileResponse};
+use geozero::mvt::{self, Message};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Arc;
+use tile_grid::{Tms, Xyz};
+use tilejson::{tilejson, TileJSON};
+use tiny_skia::{
+    Color, FillRule, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, Stroke, Transform,
+};
+
+#[derive(Clone)]
+pub struct VectorRenderSource {
+    /// Rendered vector tileset, replaced with its cache enabled instance at service setup
+    tileset: Arc<TileSet>,
+    style: Arc<RenderStyle>,
+}
+
+impl VectorRenderSource {
+    /// Render tiles of vector `tileset` with layers of `style` referencing the tileset
+    pub fn new(tileset: &TileSet, style: &Value) -> Self {
+        VectorRenderSource {
+            tileset: Arc::new(tileset.clone()),
+            style: Arc::new(RenderStyle::from_json(style, &tileset.name)),
+        }
+    }
+}
+
+fn vector_tile_error(e: ServiceError) -> TileSourceError {
+    match e {
+        ServiceError::TileSourceError(e) => e,
+        e => TileSourceError::VectorTileError(e.to_string()),
+    }
+}
+
+#[async_trait]
+impl TileSource for VectorRenderSource {
+    async fn xyz_request(
+        &self,
+        tms: &Tms,
+        tile: &Xyz,
+        filter: &FilterParams,
+        _format: &Format,
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<TileResponse, TileSourceError> {
+        let size = u32::from(tms.xyz_extent(tile)?.tile_width.get());
+        // Vector tiles are read through the tileset cache, in grids served by the tileset only
+        self.tileset.grid(tms.id()).map_err(vector_tile_error)?;
+        let vector_tile = self
+            .tileset
+            .tile_cached(
+                tms,
+                tile,
+                filter,
+                &Format::Mvt,
+                Compression::None,
+                &[],
+                request_params,
+            )
+            .await
+            .map_err(vector_tile_error)?;
+        let mvt_tile = if let Some(vector_tile) = vector_tile {
+            let data = vector_tile.read_bytes(&Compression::None)?;
+            mvt::Tile::decode(data.body.as_slice()).map_err(|_| TileSourceError::MvtDecodeError)?
+        } else {
+            mvt::Tile::default()
+        };
+        let png = self.style.render(&mvt_tile, tile.z, size)?;
+        let mut response = TileResponse::new();
+        response.set_content_type("image/png");
+        Ok(response.with_body(Box::new(Cursor::new(png))))
+    }
+    fn source_type(&self) -> SourceType {
+        SourceType::Raster
+    }
+    fn set_tilesets(&mut self, tilesets: &Tilesets) {
+        if let Some(tileset) = tilesets.get(&self.tileset.name) {
+            self.tileset = Arc::new(tileset.clone());
+        }
+    }
+    async fn tilejson(&self, _tms: &Tms, format: &Format) -> Result<TileJSON, TileSourceError> {
+        let mut tj = tilejson! { tiles: vec![] };
+        tj.other
+            .insert("format".to_string(), format.file_suffix().into());
+        Ok(tj)
+    }
+    async fn layers(&self) -> Result<Vec<LayerInfo>, TileSourceError> {
+        Ok(vec![LayerInfo {
+            name: "VectorRenderSource".to_string(),
+            geometry_type: None,
+            style: None,
+        }])
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum LayerKind {
+    Background,
+    Fill,
+    Line,
+    Circle,
+}
+
+/// Style layer supported by the renderer
+#[derive(Debug)]
+struct RenderLayer {
+    kind: LayerKind,
+    source_layer: Option<String>,
+    minzoom: f64,
+    maxzoom: f64,
+    filter: Option<Value>,
+    paint: serde_json::Map<String, Value>,
+    layout: serde_json::Map<String, Value>,
+}
+
+#[derive(Debug)]
+struct RenderStyle {
+    layers: Vec<RenderLayer>,
+}
+
+impl RenderStyle {
+    /// Supported layers of style JSON. Layers of other sources are skipped.
+    fn from_json(style: &Value, source_name: &str) -> Self {
+        let layers = style["layers"]
+            .as_array()
+            .map(|layers| {
+                layers
+                    .iter()
+                    .filter_map(|layer| RenderLayer::from_json(layer, source_name))
+                    .collect()
+            })
+            .unwrap_or_default();
+        RenderStyle { layers }
+    }
+
+    fn render(&self, tile: &mvt::Tile, zoom: u8, size: u32) -> Result<Vec<u8>, TileSourceError> {
+        let mut pixmap = Pixmap::new(size, size).ok_or(TileSourceError::TileXyzError)?;
+        let zoom = f64::from(zoom);
+        for layer in self.layers.iter().filter(|layer| layer.visible(zoom)) {
+            if layer.kind == LayerKind::Background {
+                if let Some(color) = layer.color("background-color", "background-opacity", zoom) {
+                    pixmap.fill(color);
+                }
+                continue;
+            }
+            let Some(mvt_layer) = tile
+                .layers
+                .iter()
+                .find(|l| Some(&l.name) == layer.source_layer.as_ref())
+            else {
+                continue;
+            };
+            let scale = size as f32 / mvt_layer.extent.unwrap_or(4096) as f32;
+            for feature in &mvt_layer.features {
+                let info = FeatureInfo::new(mvt_layer, feature);
+                if !layer
+                    .filter
+                    .as_ref()
+                    .map_or(true, |filter| filter_matches(filter, &info))
+                {
+                    continue;
+                }
+                layer.render_feature(&mut pixmap, feature, info.geom_type, scale, zoom);
+            }
+        }
+        Ok(pixmap.encode_png()?)
+    }
+}
+
+impl RenderLayer {
+    fn from_json(layer: &Value, source_name: &str) -> Option<Self> {
+        let kind = match layer["type"].as_str()? {
+            "background" => LayerKind::Background,
+            "fill" => LayerKind::Fill,
+            "line" => LayerKind::Line,
+            "circle" => LayerKind::Circle,
+            _ => return None,
+        };
+        if kind != LayerKind::Background && layer["source"].as_str() != Some(source_name) {
+            return None;
+        }
+        Some(RenderLayer {
+            kind,
+            source_layer: layer["source-layer"].as_str().map(str::to_string),
+            minzoom: layer["minzoom"].as_f64().unwrap_or(0.0),
+            maxzoom: layer["maxzoom"].as_f64().unwrap_or(f64::MAX),
+            filter: layer.get("filter").cloned(),
+            paint: layer["paint"].as_object().cloned().unwrap_or_default(),
+            layout: layer["layout"].as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    fn visible(&self, zoom: f64) -> bool {
+        zoom >= self.minzoom
+            && zoom < self.maxzoom
+            && self.layout.get("visibility").and_then(Value::as_str) != Some("none")
+    }
+
+    fn render_feature(
+        &self,
+        pixmap: &mut Pixmap,
+        feature: &mvt::tile::Feature,
+        geom_type: &str,
+        scale: f32,
+        zoom: f64,
+    ) {
+        match (&self.kind, geom_type) {
+            (LayerKind::Fill, "Polygon") => {
+                let Some(path) = geometry_path(&feature.geometry, scale) else {
+                    return;
+                };
+                if let Some(color) = self.color("fill-color", "fill-opacity", zoom) {
+                    let paint = paint(color, self.bool("fill-antialias", true));
+                    pixmap.fill_path(
+                        &path,
+                        &paint,
+                        FillRule::EvenOdd,
+                        Transform::identity(),
+                        None,
+                    );
+                }
+                if let Some(color) = self.color("fill-outline-color", "fill-opacity", zoom) {
+                    let stroke = Stroke::default();
+                    pixmap.stroke_path(
+                        &path,
+                        &paint(color, true),
+                        &stroke,
+                        Transform::identity(),
+                        None,
+                    );
+                }
+            }
+            (LayerKind::Line, "LineString" | "Polygon") => {
+                let Some(path) = geometry_path(&feature.geometry, scale) else {
+                    return;
+                };
+                let Some(color) = self.color("line-color", "line-opacity", zoom) else {
+                    return;
+                };
+                let stroke = Stroke {
+                    width: self.number("line-width", zoom, 1.0) as f32,
+                    line_cap: match self.layout.get("line-cap").and_then(Value::as_str) {
+                        Some("round") => LineCap::Round,
+                        Some("square") => LineCap::Square,
+                        _ => LineCap::Butt,
+                    },
+                    line_join: match self.layout.get("line-join").and_then(Value::as_str) {
+                        Some("round") => LineJoin::Round,
+                        Some("bevel") => LineJoin::Bevel,
+                        _ => LineJoin::Miter,
+                    },
+                    ..Default::default()
+                };
+                pixmap.stroke_path(
+                    &path,
+                    &paint(color, true),
+                    &stroke,
+                    Transform::identity(),
+                    None,
+                );
+            }
+            (LayerKind::Circle, "Point") => {
+                let radius = self.number("circle-radius", zoom, 5.0) as f32;
+                let fill = self.color("circle-color", "circle-opacity", zoom);
+                let stroke_width = self.number("circle-stroke-width", zoom, 0.0) as f32;
+                let stroke_color = self.color("circle-stroke-color", "circle-stroke-opacity", zoom);
+                for (x, y) in geometry_points(&feature.geometry, scale) {
+                    let Some(path) = PathBuilder::from_circle(x, y, radius) else {
+                        continue;
+                    };
+                    if let Some(color) = fill {
+                        pixmap.fill_path(
+                            &path,
+                            &paint(color, true),
+                            FillRule::Winding,
+                            Transform::identity(),
+                            None,
+                        );
+                    }
+                    if let (Some(color), true) = (stroke_color, stroke_width > 0.0) {
+                        let stroke = Stroke {
+                            width: stroke_width,
+                            ..Default::default()
+                        };
+                        pixmap.stroke_path(
+                            &path,
+                            &paint(color, true),
+                            &stroke,
+                            Transform::identity(),
+                            None,
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Numeric paint property
+    fn number(&self, key: &str, zoom: f64, default: f64) -> f64 {
+        match self.paint.get(key) {
+            Some(Value::Number(n)) => n.as_f64().unwrap_or(default),
+            Some(Value::Object(function)) => interpolate_stops(function, zoom).unwrap_or(default),
+            _ => default,
+        }
+    }
+
+    fn bool(&self, key: &str, default: bool) -> bool {
+        self.paint
+            .get(key)
+            .and_then(Value::as_bool)
+            .unwrap_or(default)
+    }
+
+    /// Color paint property with opacity applied. The default color is black for colors
+    /// which are drawn without explicit style (`fill-color`, `line-color`, ...).
+    fn color(&self, key: &str, opacity_key: &str, zoom: f64) -> Option<Color> {
+        let value = match self.paint.get(key) {
+            Some(Value::String(color)) => Some(color.as_str()),
+            Some(Value::Object(function)) => step_stops(function, zoom).and_then(Value::as_str),
+            Some(_) => None,
+            None => match key {
+                "fill-outline-color" | "circle-stroke-color" => return None,
+                _ => Some("#000000"),
+            },
+        };
+        let mut color = parse_color(value?)?;
+        color.apply_opacity(self.number(opacity_key, zoom, 1.0) as f32);
+        Some(color)
+    }
+}
+
+fn paint(color: Color, anti_alias: bool) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.anti_alias = anti_alias;
+    paint
+}
+
+/// Zoom function stops like `{"base": 1.2, "stops": [[6, 1], [12, 4]]}`
+fn zoom_stops(function: &serde_json::Map<String, Value>) -> Vec<(f64, &Value)> {
+    function
+        .get("stops")
+        .and_then(Value::as_array)
+        .map(|stops| {
+            stops
+                .iter()
+                .filter_map(|stop| Some((stop.get(0)?.as_f64()?, stop.get(1)?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Exponential interpolation of numeric stops
+fn interpolate_stops(function: &serde_json::Map<String, Value>, zoom: f64) -> Option<f64> {
+    let stops = zoom_stops(function);
+    let base = function.get("base").and_then(Value::as_f64).unwrap_or(1.0);
+    let (first, last) = (stops.first()?, stops.last()?);
+    if zoom <= first.0 {
+        return first.1.as_f64();
+    }
+    if zoom >= last.0 {
+        return last.1.as_f64();
+    }
+    let i = stops.iter().position(|(z, _)| *z > zoom)?;
+    let ((z0, v0), (z1, v1)) = (stops[i - 1], stops[i]);
+    let (v0, v1) = (v0.as_f64()?, v1.as_f64()?);
+    let t = if base == 1.0 {
+        (zoom - z0) / (z1 - z0)
+    } else {
+        (base.powf(zoom - z0) - 1.0) / (base.powf(z1 - z0) - 1.0)
+    };
+    Some(v0 + (v1 - v0) * t)
+}
+
+/// Value of the last stop at or below `zoom`
+fn step_stops(function: &serde_json::Map<String, Value>, zoom: f64) -> Option<&Value> {
+    let stops = zoom_stops(function);
+    stops
+        .iter()
+        .rev()
+        .find(|(z, _)| *z <= zoom)
+        .or(stops.first())
+        .map(|(_, value)| *value)
+}
+
+/// Parse CSS color (`#rgb`, `#rrggbb`, `#rrggbbaa`, `rgb()`, `rgba()`, `hsl()`, `hsla()` and some names)
+fn parse_color(color: &str) -> Option<Color> {
+    let color = color.trim().to_ascii_lowercase();
+    if let Some(hex) = color.strip_prefix('#') {
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize, n: usize| -> Option<u8> {
+            let v = u8::from_str_radix(hex.get(i * n..(i + 1) * n)?, 16).ok()?;
+            Some(if n == 1 { v * 17 } else { v })
+        };
+        return match hex.len() {
+            3 => Some(Color::from_rgba8(
+                channel(0, 1)?,
+                channel(1, 1)?,
+                channel(2, 1)?,
+                255,
+            )),
+            6 => Some(Color::from_rgba8(
+                channel(0, 2)?,
+                channel(1, 2)?,
+                channel(2, 2)?,
+                255,
+            )),
+            8 => Some(Color::from_rgba8(
+                channel(0, 2)?,
+                channel(1, 2)?,
+                channel(2, 2)?,
+                channel(3, 2)?,
+            )),
+            _ => None,
+        };
+    }
+    if let Some((func, args)) = color.strip_suffix(')').and_then(|c| c.split_once('(')) {
+        let args: Vec<&str> = args.split(',').map(str::trim).collect();
+        let alpha = match args.get(3) {
+            Some(a) => a.parse::<f32>().ok()?,
+            None => 1.0,
+        };
+        let number = |i: usize| args.get(i)?.trim_end_matches('%').parse::<f32>().ok();
+        let (r, g, b) = match func {
+            "rgb" | "rgba" => (number(0)? / 255.0, number(1)? / 255.0, number(2)? / 255.0),
+            "hsl" | "hsla" => hsl_to_rgb(number(0)?, number(1)? / 100.0, number(2)? / 100.0),
+            _ => return None,
+        };
+        return Color::from_rgba(
+            r.clamp(0.0, 1.0),
+            g.clamp(0.0, 1.0),
+            b.clamp(0.0, 1.0),
+            alpha.clamp(0.0, 1.0),
+        );
+    }
+    match color.as_str() {
+        "black" => Some(Color::BLACK),
+        "white" => Some(Color::WHITE),
+        "transparent" => Some(Color::TRANSPARENT),
+        "red" => Some(Color::from_rgba8(255, 0, 0, 255)),
+        "green" => Some(Color::from_rgba8(0, 128, 0, 255)),
+        "blue" => Some(Color::from_rgba8(0, 0, 255, 255)),
+        "yellow" => Some(Color::from_rgba8(255, 255, 0, 255)),
+        "gray" | "grey" => Some(Color::from_rgba8(128, 128, 128, 255)),
+        _ => None,
+    }
+}
+
+fn hsl_to_rgb(h: f32, s: f32, l: f32) -> (f32, f32, f32) {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u8 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = l - c / 2.0;
+    (r + m, g + m, b + m)
+}
+
+/// Geometry type and properties of a feature for filter evaluation
+struct FeatureInfo<'a> {
+    geom_type: &'static str,
+    id: Option<u64>,
+    properties: HashMap<&'a str, Value>,
+}
+
+impl<'a> FeatureInfo<'a> {
+    fn new(layer: &'a mvt::tile::Layer, feature: &mvt::tile::Feature) -> Self {
+        let geom_type = match feature.r#type {
+            Some(t) if t == mvt::tile::GeomType::Point as i32 => "Point",
+            Some(t) if t == mvt::tile::GeomType::Linestring as i32 => "LineString",
+            Some(t) if t == mvt::tile::GeomType::Polygon as i32 => "Polygon",
+            _ => "Unknown",
+        };
+        let properties = feature
+            .tags
+            .chunks_exact(2)
+            .filter_map(|tag| {
+                let key = layer.keys.get(tag[0] as usize)?;
+                let value = json_value(layer.values.get(tag[1] as usize)?)?;
+                Some((key.as_str(), value))
+            })
+            .collect();
+        FeatureInfo {
+            geom_type,
+            id: feature.id,
+            properties,
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Value> {
+        match key {
+            "$type" => Some(self.geom_type.into()),
+            "$id" => self.id.map(Value::from),
+            _ => self.properties.get(key).cloned(),
+        }
+    }
+}
+
+fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Evaluate legacy filter. Expression filters are not supported and match all features.
+fn filter_matches(filter: &Value, feature: &FeatureInfo) -> bool {
+    let Some(args) = filter.as_array() else {
+        return true;
+    };
+    let Some(op) = args.first().and_then(Value::as_str) else {
+        return true;
+    };
+    let filters = &args[1..];
+    match op {
+        "all" => return filters.iter().all(|f| filter_matches(f, feature)),
+        "any" => return filters.iter().any(|f| filter_matches(f, feature)),
+        "none" => return !filters.iter().any(|f| filter_matches(f, feature)),
+        _ => {}
+    }
+    let Some(key) = args.get(1).and_then(Value::as_str) else {
+        return true;
+    };
+    let value = feature.get(key);
+    let equals = |other: &Value| {
+        value
+            .as_ref()
+            .and_then(|v| compare_values(v, other))
+            .map_or(false, Ordering::is_eq)
+    };
+    let compare = |cmp: fn(Ordering) -> bool| match (value.as_ref(), args.get(2)) {
+        (Some(v), Some(other)) => compare_values(v, other).map_or(false, cmp),
+        _ => false,
+    };
+    match op {
+        "has" => value.is_some(),
+        "!has" => value.is_none(),
+        "==" => args.get(2).map_or(false, equals),
+        "!=" => !args.get(2).map_or(false, equals),
+        "<" => compare(Ordering::is_lt),
+        "<=" => compare(Ordering::is_le),
+        ">" => compare(Ordering::is_gt),
+        ">=" => compare(Ordering::is_ge),
+        "in" => args[2..].iter().any(equals),
+        "!in" => !args[2..].iter().any(equals),
+        _ => true,
+    }
+}
+
+/// Decode MVT geometry commands into a path in pixel coordinates
+fn geometry_path(geometry: &[u32], scale: f32) -> Option<Path> {
+    let mut pb = PathBuilder::new();
+    decode_geometry(geometry, scale, |cmd, x, y| match cmd {
+        MOVE_TO => pb.move_to(x, y),
+        LINE_TO => pb.line_to(x, y),
+        _ => pb.close(),
+    });
+    pb.finish()
+}
+
+/// Decode MVT point geometry into pixel coordinates
+fn geometry_points(geometry: &[u32], scale: f32) -> Vec<(f32, f32)> {
+    let mut points = Vec::new();
+    decode_geometry(geometry, scale, |cmd, x, y| {
+        if cmd == MOVE_TO {
+            points.push((x, y));
+        }
+    });
+    points
+}
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+fn decode_geometry(geometry: &[u32], scale: f32, mut f: impl FnMut(u32, f32, f32)) {
+    let (mut x, mut y) = (0i32, 0i32);
+    let mut i = 0;
+    while i < geometry.len() {
+        let cmd = geometry[i] & 0x7;
+        let count = (geometry[i] >> 3) as usize;
+        i += 1;
+        match cmd {
+            MOVE_TO | LINE_TO => {
+                for _ in 0..count {
+                    let (Some(dx), Some(dy)) = (geometry.get(i), geometry.get(i + 1)) else {
+                        return;
+                    };
+                    x = x.wrapping_add(unzigzag(*dx));
+                    y = y.wrapping_add(unzigzag(*dy));
+                    i += 2;
+                    f(cmd, x as f32 * scale, y as f32 * scale);
+                }
+            }
+            CLOSE_PATH => f(cmd, 0.0, 0.0),
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::mvt::zigzag;
+    use serde_json::json;
+
+    #[test]
+    fn render_style() {
+        let style = json!({
+            "layers": [
+                {"id": "bg", "type": "background", "paint": {"background-color": "#fff"}},
+                {"id": "land", "type": "fill", "source": "ne", "source-layer": "country",
+                 "filter": ["==", "continent", "Europe"], "paint": {"fill-color": "rgb(255, 0, 0)"}},
+                {"id": "labels", "type": "symbol", "source": "ne", "source-layer": "country"},
+                {"id": "other", "type": "line", "source": "osm", "source-layer": "roads"}
+            ]
+        });
+        let style = RenderStyle::from_json(&style, "ne");
+        assert_eq!(style.layers.len(), 2);
+
+        // Square covering the upper left quarter of the tile
+        let square = mvt::tile::Feature {
+            r#type: Some(mvt::tile::GeomType::Polygon as i32),
+            geometry: vec![
+                9,
+                0,
+                0,
+                26,
+                zigzag(2048),
+                0,
+                0,
+                zigzag(2048),
+                zigzag(-2048),
+                0,
+                15,
+            ],
+            tags: vec![0, 0],
+            ..Default::default()
+        };
+        let tile = mvt::Tile {
+            layers: vec![mvt::tile::Layer {
+                version: 2,
+                name: "country".to_string(),
+                features: vec![square],
+                keys: vec!["continent".to_string()],
+                values: vec![mvt::tile::Value {
+                    string_value: Some("Europe".to_string()),
+                    ..Default::default()
+                }],
+                extent: Some(4096),
+            }],
+        };
+        let png = style.render(&tile, 0, 256).unwrap();
+        let pixmap = Pixmap::decode_png(&png).unwrap();
+        let red = pixmap.pixel(64, 64).unwrap();
+        assert_eq!((red.red(), red.green(), red.blue()), (255, 0, 0));
+        let white = pixmap.pixel(192, 192).unwrap();
+        assert_eq!((white.red(), white.green(), white.blue()), (255, 255, 255));
+    }
+
+    #[test]
+    fn paint_values() {
+        assert_eq!(parse_color("#f00"), Some(Color::from_rgba8(255, 0, 0, 255)));
+        assert_eq!(
+            parse_color("rgba(0, 0, 255, 0.5)").map(|c| c.alpha()),
+            Some(0.5)
+        );
+        assert_eq!(
+            parse_color("hsl(120, 100%, 50%)"),
+            Color::from_rgba(0.0, 1.0, 0.0, 1.0)
+        );
+        assert_eq!(parse_color("#ggg"), None);
+        let function = json!({"stops": [[4, 1], [8, 5]]});
+        let function = function.as_object().unwrap();
+        assert_eq!(interpolate_stops(function, 2.0), Some(1.0));
+        assert_eq!(interpolate_stops(function, 6.0), Some(3.0));
+        assert_eq!(interpolate_stops(function, 10.0), Some(5.0));
+        assert_eq!(step_stops(function, 6.0), Some(&json!(1)));
+    }
+}
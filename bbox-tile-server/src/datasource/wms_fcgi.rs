@@ -45,11 +45,7 @@ impl WmsFcgiSource {
     }
 
     pub fn get_map_request(&self, extent_info: &QueryExtent, format: &Format) -> String {
-        let (width, height) = if let Some(size) = self.tile_size {
-            (size, size)
-        } else {
-            (extent_info.tile_width, extent_info.tile_height)
-        };
+        let (width, height) = (extent_info.tile_width, extent_info.tile_height);
         let extent = &extent_info.extent;
         format!(
             "{}&CRS=EPSG:{}&BBOX={},{},{},{}&WIDTH={width}&HEIGHT={height}&FORMAT={}",
@@ -101,10 +97,22 @@ impl TileSource for WmsFcgiSource {
         format: &Format,
         request_params: HttpRequestParams<'_>,
     ) -> Result<TileResponse, TileSourceError> {
-        let extent_info = tms.xyz_extent(tile)?;
+        let mut extent_info = tms.xyz_extent(tile)?;
+        if let Some(size) = self.tile_size {
+            extent_info.tile_width = size;
+            extent_info.tile_height = size;
+        }
         self.bbox_request(&extent_info, format, request_params)
             .await
     }
+    async fn extent_request(
+        &self,
+        extent_info: &QueryExtent,
+        format: &Format,
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<TileResponse, TileSourceError> {
+        self.bbox_request(extent_info, format, request_params).await
+    }
     fn source_type(&self) -> SourceType {
         SourceType::Raster
     }
@@ -5,70 +5,179 @@ use crate::datasource::{
     wms_fcgi::HttpRequestParams, LayerInfo, SourceType, TileSource, TileSourceError,
 };
 use crate::filter_params::FilterParams;
-use crate::service::TmsExtensions;
+use crate::service::{QueryExtent, TileSetGrid, TmsExtensions};
 use async_trait::async_trait;
-use bbox_core::config::WmsHttpSourceProviderCfg;
+use bbox_core::config::{HttpAuthCfg, WmsHttpSourceProviderCfg};
 use bbox_core::{Format, TileResponse};
 use log::debug;
 use std::io::Cursor;
-use tile_grid::{BoundingBox, Tms, Xyz};
+use tile_grid::{Tms, Xyz};
 use tilejson::{tilejson, TileJSON};
 
 #[derive(Clone, Debug)]
 pub struct WmsHttpSource {
     client: reqwest::Client,
+    auth: Option<HttpAuthCfg>,
+    /// GetMap request without CRS and BBOX
     pub req_url: String,
+    /// WMS 1.1.1 request (`SRS` parameter and x/y axis order)
+    wms_111: bool,
+    /// Grid SRIDs with inverted axis order (lat/lon or northing/easting)
+    inverted_axes: Vec<i32>,
 }
 
 impl WmsHttpSource {
     pub fn from_config(
         provider: &WmsHttpSourceProviderCfg,
         params: &WmsHttpSourceParamsCfg,
-        srid: i32,
+        grids: &[TileSetGrid],
     ) -> Self {
         let client = reqwest::Client::new();
-        let req_url = format!(
-            "{}&SERVICE=WMS&REQUEST=GetMap&CRS=EPSG:{}&WIDTH={}&HEIGHT={}&LAYERS={}&STYLES=&FORMAT={}",
+        let url_version = url_param(&provider.baseurl, "version");
+        let version = provider
+            .version
+            .as_deref()
+            .or(url_version)
+            .unwrap_or("1.3.0");
+        let mut req_url = format!(
+            "{}{}SERVICE=WMS&REQUEST=GetMap&LAYERS={}&STYLES=&FORMAT={}",
             provider.baseurl,
-            srid,
-            256, //grid.width,
-            256, //grid.height,
+            if provider.baseurl.contains('?') {
+                "&"
+            } else {
+                "?"
+            },
             params.layers,
             provider.format,
         );
-        WmsHttpSource { client, req_url }
+        if url_version.is_none() {
+            req_url.push_str(&format!("&VERSION={version}"));
+        }
+        for (key, value) in &provider.params {
+            req_url.push_str(&format!("&{key}={value}"));
+        }
+        let wms_111 = version == "1.1.1";
+        let inverted_axes = if wms_111 {
+            Vec::new()
+        } else {
+            grids
+                .iter()
+                .filter(|grid| has_inverted_axes(&grid.tms))
+                .map(|grid| grid.tms.srid())
+                .collect()
+        };
+        WmsHttpSource {
+            client,
+            auth: provider.auth.clone(),
+            req_url,
+            wms_111,
+            inverted_axes,
+        }
     }
-    fn get_map_request(&self, extent: &BoundingBox) -> String {
+    fn get_map_request(&self, extent_info: &QueryExtent) -> String {
+        let extent = &extent_info.extent;
+        let bbox = if self.inverted_axes.contains(&extent_info.srid) {
+            [extent.bottom, extent.left, extent.top, extent.right]
+        } else {
+            [extent.left, extent.bottom, extent.right, extent.top]
+        };
         format!(
-            "{}&BBOX={},{},{},{}",
-            self.req_url, extent.left, extent.bottom, extent.right, extent.top
+            "{}&{}=EPSG:{}&BBOX={},{},{},{}&WIDTH={}&HEIGHT={}",
+            self.req_url,
+            if self.wms_111 { "SRS" } else { "CRS" },
+            extent_info.srid,
+            bbox[0],
+            bbox[1],
+            bbox[2],
+            bbox[3],
+            extent_info.tile_width,
+            extent_info.tile_height
         )
     }
 
     pub async fn get_map_response(
         &self,
-        extent: &BoundingBox,
+        extent_info: &QueryExtent,
     ) -> Result<reqwest::Response, TileSourceError> {
-        let req = self.get_map_request(extent);
+        let req = self.get_map_request(extent_info);
         debug!("Request {req}");
-        self.client.get(req).send().await.map_err(Into::into)
+        let mut request = self.client.get(req);
+        match &self.auth {
+            Some(HttpAuthCfg::Basic { username, password }) => {
+                request = request.basic_auth(username, password.as_ref());
+            }
+            Some(HttpAuthCfg::Bearer { token }) => {
+                request = request.bearer_auth(token);
+            }
+            None => {}
+        }
+        request.send().await.map_err(Into::into)
     }
 
-    async fn bbox_request(&self, extent: &BoundingBox) -> Result<TileResponse, TileSourceError> {
-        let wms_resp = self.get_map_response(extent).await?;
-        let mut response = TileResponse::new();
-        if let Some(content_type) = wms_resp
+    async fn bbox_request(
+        &self,
+        extent_info: &QueryExtent,
+    ) -> Result<TileResponse, TileSourceError> {
+        let wms_resp = self.get_map_response(extent_info).await?;
+        let status = wms_resp.status();
+        let content_type = wms_resp
             .headers()
             .get("content-type")
-            .map(|ct| ct.to_str().expect("invalid content-type"))
-        {
+            .and_then(|ct| ct.to_str().ok())
+            .map(ToString::to_string);
+        let data = wms_resp.bytes().await?;
+        // Servers like MapServer or QGIS Server report errors as XML with HTTP status 200
+        let is_xml = content_type
+            .as_deref()
+            .map_or(false, |ct| ct.contains("xml"))
+            || data.starts_with(b"<?xml");
+        if !status.is_success() || is_xml {
+            let body = String::from_utf8_lossy(&data);
+            let message = service_exception(&body).unwrap_or_else(|| status.to_string());
+            return Err(TileSourceError::WmsServiceError(message));
+        }
+        let mut response = TileResponse::new();
+        if let Some(content_type) = content_type {
             response.set_content_type(content_type);
         }
-        let body = Box::new(Cursor::new(wms_resp.bytes().await?));
+        let body = Box::new(Cursor::new(data));
         Ok(response.with_body(body))
     }
 }
 
+/// Value of URL query parameter (case insensitive)
+fn url_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.eq_ignore_ascii_case(name).then_some(value)
+    })
+}
+
+/// Grid CRS with lat/lon or northing/easting axis order
+fn has_inverted_axes(tms: &Tms) -> bool {
+    if tms.srid() == 4326 {
+        // EPSG:4326 is requested with lat/lon order, also for CRS84 grids
+        return true;
+    }
+    tms.tms.ordered_axes.as_ref().map_or(false, |axes| {
+        matches!(axes[0].to_uppercase().as_str(), "LAT" | "N" | "Y")
+    })
+}
+
+/// Message of WMS ServiceException XML document
+fn service_exception(xml: &str) -> Option<String> {
+    const TAG: &str = "<ServiceException";
+    // Skip `<ServiceExceptionReport>`
+    let (start, _) = xml.match_indices(TAG).find(|(pos, _)| {
+        xml[pos + TAG.len()..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace())
+    })?;
+    let content = &xml[start..];
+    let content = &content[content.find('>')? + 1..];
+    let end = content.find("</ServiceException>").unwrap_or(content.len());
+    Some(content[..end].trim().to_string())
+}
+
 #[async_trait]
 impl TileSource for WmsHttpSource {
     async fn xyz_request(
@@ -80,7 +189,15 @@ impl TileSource for WmsHttpSource {
         _request_params: HttpRequestParams<'_>,
     ) -> Result<TileResponse, TileSourceError> {
         let extent_info = tms.xyz_extent(tile)?;
-        self.bbox_request(&extent_info.extent).await
+        self.bbox_request(&extent_info).await
+    }
+    async fn extent_request(
+        &self,
+        extent_info: &QueryExtent,
+        _format: &Format,
+        _request_params: HttpRequestParams<'_>,
+    ) -> Result<TileResponse, TileSourceError> {
+        self.bbox_request(extent_info).await
     }
     fn source_type(&self) -> SourceType {
         SourceType::Raster
@@ -99,3 +216,72 @@ impl TileSource for WmsHttpSource {
         }])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU16;
+    use tile_grid::{tms, BoundingBox};
+
+    #[test]
+    fn get_map_request() {
+        let provider = WmsHttpSourceProviderCfg {
+            baseurl: "https://example.com/wms?map=world".to_string(),
+            format: "image/png".to_string(),
+            version: None,
+            params: [("TRANSPARENT".to_string(), "true".to_string())].into(),
+            auth: None,
+        };
+        let params = WmsHttpSourceParamsCfg {
+            source: "world".to_string(),
+            layers: "countries".to_string(),
+        };
+        let grid = |id: &str| TileSetGrid {
+            tms: tms().lookup(id).unwrap(),
+            minzoom: 0,
+            maxzoom: 10,
+            overzoom: None,
+            extent: None,
+        };
+        let grids = [grid("WebMercatorQuad"), grid("WorldCRS84Quad")];
+        let source = WmsHttpSource::from_config(&provider, &params, &grids);
+        let extent_info = |srid| QueryExtent {
+            extent: BoundingBox::new(0.0, 10.0, 20.0, 30.0),
+            srid,
+            tile_width: NonZeroU16::new(256).unwrap(),
+            tile_height: NonZeroU16::new(256).unwrap(),
+        };
+        assert_eq!(
+            source.get_map_request(&extent_info(3857)),
+            "https://example.com/wms?map=world&SERVICE=WMS&REQUEST=GetMap&LAYERS=countries&STYLES=&FORMAT=image/png&VERSION=1.3.0&TRANSPARENT=true&CRS=EPSG:3857&BBOX=0,10,20,30&WIDTH=256&HEIGHT=256"
+        );
+        assert!(source
+            .get_map_request(&extent_info(4326))
+            .ends_with("&CRS=EPSG:4326&BBOX=10,0,30,20&WIDTH=256&HEIGHT=256"));
+
+        let provider = WmsHttpSourceProviderCfg {
+            baseurl: "https://example.com/wms?version=1.1.1".to_string(),
+            params: Default::default(),
+            ..provider
+        };
+        let source = WmsHttpSource::from_config(&provider, &params, &grids);
+        assert!(source
+            .get_map_request(&extent_info(4326))
+            .ends_with("&FORMAT=image/png&SRS=EPSG:4326&BBOX=0,10,20,30&WIDTH=256&HEIGHT=256"));
+    }
+
+    #[test]
+    fn exception_report() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ServiceExceptionReport version="1.3.0" xmlns="http://www.opengis.net/ogc">
+  <ServiceException code="LayerNotDefined">
+    Layer 'unknown' not found
+  </ServiceException>
+</ServiceExceptionReport>"#;
+        assert_eq!(
+            service_exception(xml).as_deref(),
+            Some("Layer 'unknown' not found")
+        );
+        assert_eq!(service_exception("<html></html>"), None);
+    }
+}
@@ -0,0 +1,175 @@
+//! Tileset discovery in directories with MBTiles and PMTiles archives.
+
+use crate::config::{SourceParamCfg, TileSetCfg, TilesetsCfg};
+use crate::datasource::{source_config_from_cli_arg, TileSource};
+use crate::mbtiles_ds::MbtilesDatasource;
+use crate::service::{ServiceError, TileSet, TileSetGrid, TmsExtensions};
+use crate::store::metadata_tms_id;
+use crate::store::pmtiles::PmtilesStoreReader;
+use crate::store::TileStoreError;
+use bbox_core::file_search;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tile_grid::{tms, RegistryError, Tms};
+
+/// Tileset created from a tile archive
+#[derive(Clone)]
+pub struct ArchiveTileset {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    pub tileset: Arc<TileSet>,
+}
+
+pub type DiscoveredTilesets = HashMap<String, ArchiveTileset>;
+
+/// Scanner for tile archives in tileset directories
+#[derive(Clone)]
+pub struct TilesetScanner {
+    dirs: Vec<PathBuf>,
+    /// Custom grids
+    grids: Vec<Tms>,
+    /// Names of configured tilesets
+    reserved: HashSet<String>,
+    tilesets: Arc<RwLock<DiscoveredTilesets>>,
+    /// Archives which could not be opened
+    failed: Arc<Mutex<HashMap<PathBuf, Option<SystemTime>>>>,
+}
+
+impl TilesetScanner {
+    pub fn new(cfg: &TilesetsCfg, grids: Vec<Tms>, reserved: HashSet<String>) -> Self {
+        TilesetScanner {
+            dirs: cfg.directory.iter().map(|dir| dir.abs_path()).collect(),
+            grids,
+            reserved,
+            tilesets: Arc::new(RwLock::new(HashMap::new())),
+            failed: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+    /// Discovered tilesets, updated with each scan
+    pub fn tilesets(&self) -> Arc<RwLock<DiscoveredTilesets>> {
+        self.tilesets.clone()
+    }
+    /// Scan directories and replace discovered tilesets
+    pub async fn scan(&self) {
+        let current = self.tilesets.read().unwrap().clone();
+        let mut found = DiscoveredTilesets::new();
+        for dir in &self.dirs {
+            let mut files = file_search::search(dir, "*.mbtiles");
+            files.extend(file_search::search(dir, "*.pmtiles"));
+            files.sort();
+            for path in files {
+                let Some(name) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                    continue;
+                };
+                if self.reserved.contains(&name) || found.contains_key(&name) {
+                    continue;
+                }
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                if let Some(entry) = current.get(&name) {
+                    if entry.path == path && entry.modified == modified {
+                        found.insert(name, entry.clone());
+                        continue;
+                    }
+                }
+                if self.failed.lock().unwrap().get(&path) == Some(&modified) {
+                    continue;
+                }
+                match self.archive_tileset(&name, &path).await {
+                    Ok(tileset) => {
+                        info!("Adding tileset `{name}` from `{}`", path.display());
+                        self.failed.lock().unwrap().remove(&path);
+                        let tileset = Arc::new(tileset);
+                        let entry = ArchiveTileset {
+                            path,
+                            modified,
+                            tileset,
+                        };
+                        found.insert(name, entry);
+                    }
+                    Err(e) => {
+                        warn!("Failed to open tile archive `{}`: {e}", path.display());
+                        self.failed.lock().unwrap().insert(path, modified);
+                    }
+                }
+            }
+        }
+        for name in current.keys().filter(|name| !found.contains_key(*name)) {
+            info!("Removing tileset `{name}`");
+        }
+        *self.tilesets.write().unwrap() = found;
+    }
+    /// Rescan directories periodically
+    pub fn spawn_rescan(self, interval_secs: u64) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            // First tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                self.scan().await;
+            }
+        });
+    }
+    fn lookup_grid(&self, tms_id: &str) -> Result<Tms, RegistryError> {
+        if let Some(grid) = self.grids.iter().find(|grid| grid.id() == tms_id) {
+            Ok(grid.clone())
+        } else {
+            tms().lookup(tms_id)
+        }
+    }
+    async fn archive_tileset(&self, name: &str, path: &Path) -> Result<TileSet, ServiceError> {
+        let Some(source_cfg) = source_config_from_cli_arg(&path.to_string_lossy()) else {
+            return Err(ServiceError::UnknownFormat(path.display().to_string()));
+        };
+        let (source, json, minzoom, maxzoom) = match &source_cfg {
+            SourceParamCfg::Mbtiles(cfg) => {
+                let ds = MbtilesDatasource::from_config(cfg, None)
+                    .await
+                    .map_err(TileStoreError::from)?;
+                let metadata = ds.get_metadata().await.map_err(TileStoreError::from)?;
+                let tilejson = metadata.tilejson;
+                let source: Box<dyn TileSource> = Box::new(ds);
+                (source, metadata.json, tilejson.minzoom, tilejson.maxzoom)
+            }
+            SourceParamCfg::Pmtiles(cfg) => {
+                let reader = PmtilesStoreReader::from_config(cfg).await?;
+                let json = reader
+                    .get_metadata()
+                    .await
+                    .ok()
+                    .and_then(|m| serde_json::from_str::<serde_json::Value>(&m).ok());
+                let zoom = |key: &str| {
+                    json.as_ref()
+                        .and_then(|json| json.get(key))
+                        .and_then(|z| z.as_u64())
+                        .map(|z| z as u8)
+                };
+                let (minzoom, maxzoom) = (zoom("minzoom"), zoom("maxzoom"));
+                let source: Box<dyn TileSource> = Box::new(reader);
+                (source, json, minzoom, maxzoom)
+            }
+            _ => unreachable!(),
+        };
+        let tms_id = json
+            .as_ref()
+            .and_then(metadata_tms_id)
+            .unwrap_or("WebMercatorQuad");
+        let grid = self.lookup_grid(tms_id)?;
+        let grid = TileSetGrid {
+            minzoom: minzoom.unwrap_or(grid.minzoom()),
+            maxzoom: maxzoom.unwrap_or(grid.maxzoom()),
+            overzoom: None,
+            extent: None,
+            tms: grid,
+        };
+        let format = *source.default_format();
+        let config = TileSetCfg::from_source(name.to_string(), source_cfg);
+        let mut tileset = TileSet::new(config, vec![grid], source, format);
+        tileset.setup_extent().await;
+        Ok(tileset)
+    }
+}
@@ -0,0 +1,94 @@
+//! Empty tile detection.
+
+use bbox_core::{Compression, Format};
+use flate2::read::GzDecoder;
+use geozero::mvt::{self, Message};
+use image::ImageFormat;
+use std::io::Read;
+
+/// Empty tiles are small. Larger tiles are not inspected.
+const MAX_EMPTY_TILE_SIZE: usize = 4096;
+
+/// Check for tiles without visible content.
+///
+/// Empty MVT tiles have no features, empty raster tiles are fully transparent.
+pub fn is_empty_tile(data: &[u8], format: &Format, compression: &Compression) -> bool {
+    if data.is_empty() {
+        return true;
+    }
+    if data.len() > MAX_EMPTY_TILE_SIZE {
+        return false;
+    }
+    let mut uncompressed = Vec::new();
+    let data = match compression {
+        Compression::Gzip => {
+            if GzDecoder::new(data).read_to_end(&mut uncompressed).is_err() {
+                return false;
+            }
+            uncompressed.as_slice()
+        }
+        Compression::None => data,
+    };
+    match format {
+        Format::Mvt => is_empty_mvt(data),
+        Format::Png | Format::Png8 => is_transparent_image(data, ImageFormat::Png),
+        Format::Webp => is_transparent_image(data, ImageFormat::WebP),
+        Format::Gif | Format::Jpeg | Format::Json => false,
+    }
+}
+
+fn is_empty_mvt(data: &[u8]) -> bool {
+    match mvt::Tile::decode(data) {
+        Ok(tile) => tile.layers.iter().all(|layer| layer.features.is_empty()),
+        Err(_) => false,
+    }
+}
+
+fn is_transparent_image(data: &[u8], format: ImageFormat) -> bool {
+    match image::load_from_memory_with_format(data, format) {
+        Ok(img) => img.color().has_alpha() && img.to_rgba8().pixels().all(|p| p.0[3] == 0),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+    use std::io::Cursor;
+
+    fn png_tile(pixel: Rgba<u8>) -> Vec<u8> {
+        let img = ImageBuffer::from_pixel(256, 256, pixel);
+        let mut data = Vec::new();
+        img.write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn empty_raster_tiles() {
+        let transparent = png_tile(Rgba([255, 255, 255, 0]));
+        assert!(is_empty_tile(
+            &transparent,
+            &Format::Png,
+            &Compression::None
+        ));
+        let white = png_tile(Rgba([255, 255, 255, 255]));
+        assert!(!is_empty_tile(&white, &Format::Png, &Compression::None));
+    }
+
+    #[test]
+    fn empty_vector_tiles() {
+        assert!(is_empty_tile(&[], &Format::Mvt, &Compression::None));
+        let tile = mvt::Tile {
+            layers: vec![mvt::tile::Layer {
+                version: 2,
+                name: "empty".to_string(),
+                extent: Some(4096),
+                ..Default::default()
+            }],
+        };
+        let data = tile.encode_to_vec();
+        assert!(is_empty_tile(&data, &Format::Mvt, &Compression::None));
+    }
+}
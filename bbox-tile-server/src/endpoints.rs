@@ -1,16 +1,25 @@
+use crate::access::groups_allowed;
 use crate::datasource::wms_fcgi::{HttpRequestParams, WmsMetrics};
+use crate::datasource::TileSourceError;
 use crate::filter_params::FilterParams;
-use crate::service::{ServiceError, TileService, TileSet};
-use actix_web::{guard, http::header, web, Error, FromRequest, HttpRequest, HttpResponse};
+use crate::metrics::tile_metrics;
+use crate::seed::parse_extent;
+use crate::seed_jobs::SeedJobParams;
+use crate::service::{ServiceError, TileService, TileSet, TileSetGrid, TileSetRef, TmsExtensions};
+use crate::stats::{collect_stats, StatsRange};
+use crate::wmts;
+use actix_web::{error, guard, http::header, web, Error, FromRequest, HttpRequest, HttpResponse};
+use bbox_core::auth::Identity;
 use bbox_core::endpoints::{abs_req_baseurl, req_parent_path};
 use bbox_core::service::ServiceEndpoints;
-use bbox_core::{Compression, Format};
+use bbox_core::{Compression, Format, TileResponse};
 use log::error;
 use ogcapi_types::common::Link;
 use ogcapi_types::tiles::{
     DataType, TileMatrixLimits, TileMatrixSetItem, TileMatrixSets, TileSetItem, TileSets,
     TitleDescriptionKeywords,
 };
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tile_grid::{Tms, Xyz};
 
@@ -27,8 +36,23 @@ async fn xyz(
         .tileset(&tileset)
         .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
     let tms = None;
-    let format = Format::from_suffix(&format).unwrap_or(*ts.tile_format());
-    tile_request(ts, tms, x, y, z, &format, metrics, req).await
+    let Some(suffix_format) = suffix_format(&ts, &format) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // A tile format of the `Accept` header takes precedence over the file suffix
+    let format = format_accept_header(&req, &ts)
+        .await
+        .unwrap_or(suffix_format);
+    let Some(filters) = query_params(&req) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+    let response = tile_request(&ts, tms, x, y, z, &format, filters, metrics, req).await?;
+    Ok(vary_accept(response))
+}
+
+/// Tile format of a file suffix, if delivered by the tileset
+fn suffix_format(ts: &TileSet, suffix: &str) -> Option<Format> {
+    Format::from_suffix(suffix).filter(|format| ts.tile_formats().contains(format))
 }
 
 /// XYZ tilejson endpoint
@@ -43,11 +67,13 @@ async fn tilejson(
     let ts = service
         .tileset(&tileset)
         .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    let hidden_layers = check_access(&ts, &req)?;
     let tms = ts.default_grid(0)?;
-    Ok(ts
-        .tilejson(tms, &absurl)
-        .await
-        .map(|tilejson| HttpResponse::Ok().json(tilejson))?)
+    let mut tilejson = ts.tilejson(tms, &absurl).await?;
+    if let Some(layers) = &mut tilejson.vector_layers {
+        layers.retain(|layer| !hidden_layers.contains(&layer.id));
+    }
+    Ok(HttpResponse::Ok().json(tilejson))
 }
 
 /// XYZ style json endpoint
@@ -57,15 +83,155 @@ async fn stylejson(
     tileset: web::Path<String>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let base_url = abs_req_baseurl(&req);
-    let base_path = req_parent_path(&req);
     let ts = service
         .tileset(&tileset)
         .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
-    Ok(ts
-        .stylejson(&base_url, &base_path)
-        .await
-        .map(|stylejson| HttpResponse::Ok().json(stylejson))?)
+    let hidden_layers = check_access(&ts, &req)?;
+    let public_url = public_base_url(&service, &req);
+    let mut stylejson = if let Some(style) = service.styles().tileset_style(&tileset) {
+        style.style_json(&service, &public_url)?
+    } else {
+        let base_url = abs_req_baseurl(&req);
+        let base_path = req_parent_path(&req);
+        let glyphs_url = service.styles().glyphs_url(&public_url);
+        ts.stylejson(&base_url, &base_path, glyphs_url).await?
+    };
+    remove_style_layers(&mut stylejson, &ts.name, &hidden_layers);
+    Ok(HttpResponse::Ok().json(stylejson))
+}
+
+/// Check tileset access of the user logged in with the request session.
+///
+/// Returns the layers which are not accessible by the user.
+fn check_access(ts: &TileSet, req: &HttpRequest) -> Result<Vec<String>, ServiceError> {
+    let identity = Identity::from_request(req);
+    let config = ts.config();
+    if config.is_accessible(identity.as_ref()) {
+        Ok(config.hidden_layers(identity.as_ref()))
+    } else if identity.is_none() {
+        Err(ServiceError::AuthenticationRequired)
+    } else {
+        Err(ServiceError::AccessDenied(ts.name.clone()))
+    }
+}
+
+/// Remove style layers referencing hidden layers of a tileset source
+fn remove_style_layers(stylejson: &mut serde_json::Value, source: &str, hidden_layers: &[String]) {
+    if hidden_layers.is_empty() {
+        return;
+    }
+    if let Some(layers) = stylejson["layers"].as_array_mut() {
+        layers.retain(|layer| {
+            layer["source"].as_str() != Some(source)
+                || !layer["source-layer"].as_str().map_or(false, |name| {
+                    hidden_layers.iter().any(|hidden| hidden == name)
+                })
+        });
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct StyleList {
+    styles: Vec<StyleListItem>,
+}
+
+#[derive(Serialize, Debug)]
+struct StyleListItem {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    links: Vec<Link>,
+}
+
+/// List of available styles (OGC API Styles)
+// styles
+async fn styles_list(service: web::Data<TileService>, req: HttpRequest) -> HttpResponse {
+    let public_url = public_base_url(&service, &req);
+    let identity = Identity::from_request(&req);
+    let styles = service
+        .styles()
+        .styles()
+        .iter()
+        .filter(|style| {
+            service
+                .tileset(&style.tileset)
+                .map_or(true, |ts| ts.config().is_accessible(identity.as_ref()))
+        })
+        .map(|style| StyleListItem {
+            id: style.id.clone(),
+            title: style.title(),
+            links: vec![Link {
+                rel: "stylesheet".to_string(),
+                r#type: Some("application/vnd.mapbox.style+json".to_string()),
+                title: Some("Mapbox Style".to_string()),
+                href: format!("{public_url}/styles/{}", style.id),
+                hreflang: None,
+                length: None,
+            }],
+        })
+        .collect();
+    HttpResponse::Ok().json(StyleList { styles })
+}
+
+/// Style document with URLs referencing this service
+// styles/{styleId}
+async fn style(
+    service: web::Data<TileService>,
+    style_id: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let style = service
+        .styles()
+        .style(&style_id)
+        .ok_or(ServiceError::StyleNotFound(style_id.clone()))?;
+    let mut stylejson = style.style_json(&service, &public_base_url(&service, &req))?;
+    if let Some(ts) = service.tileset(&style.tileset) {
+        let hidden_layers = check_access(&ts, &req)?;
+        remove_style_layers(&mut stylejson, &ts.name, &hidden_layers);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("application/vnd.mapbox.style+json")
+        .json(stylejson))
+}
+
+/// Sprite sheet of a style
+// styles/{styleId}/{file}
+async fn style_sprite(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (style_id, file) = params.into_inner();
+    let Some(path) = service
+        .styles()
+        .style(&style_id)
+        .and_then(|style| style.sprite_file(&file))
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Ok(body) = std::fs::read(path) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let content_type = if file.ends_with(".png") {
+        "image/png"
+    } else {
+        "application/json"
+    };
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+/// Glyph range of a font stack
+// fonts/{fontstack}/{range}.pbf
+async fn glyphs(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (fontstack, range) = params.into_inner();
+    match service.styles().glyphs(&fontstack, &range) {
+        Some(body) => HttpResponse::Ok()
+            .content_type("application/x-protobuf")
+            .body(body),
+        None => HttpResponse::NotFound().finish(),
+    }
 }
 
 /// XYZ MBTiles metadata.json (https://github.com/mapbox/mbtiles-spec/blob/master/1.3/spec.md)
@@ -73,14 +239,83 @@ async fn stylejson(
 async fn metadatajson(
     service: web::Data<TileService>,
     tileset: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let ts = service
+        .tileset(&tileset)
+        .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    let hidden_layers = check_access(&ts, &req)?;
+    let grid = ts.tms.first().ok_or(ServiceError::TilesetGridNotFound)?;
+    let mut metadata = ts.mbtiles_metadata(&grid.tms).await?;
+    if let Some(layers) = &mut metadata.tilejson.vector_layers {
+        layers.retain(|layer| !hidden_layers.contains(&layer.id));
+    }
+    Ok(HttpResponse::Ok().json(metadata))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct StatsParams {
+    pub tms: Option<String>,
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    pub extent: Option<String>,
+    pub top: Option<usize>,
+}
+
+/// Number of zoom levels scanned without `maxzoom` parameter
+const STATS_ZOOM_LEVELS: u8 = 6;
+
+/// Tile cache statistics
+// xyz/{tileset}/stats.json
+async fn statsjson(
+    service: web::Data<TileService>,
+    tileset: web::Path<String>,
+    params: web::Query<StatsParams>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
     let ts = service
         .tileset(&tileset)
         .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
-    Ok(ts
-        .mbtiles_metadata()
+    // Scanning a cache is expensive
+    check_admin(&service, &req)?;
+    let tms = if let Some(tms_id) = &params.tms {
+        ts.grid(tms_id)?
+    } else {
+        &ts.tms.first().ok_or(ServiceError::TilesetGridNotFound)?.tms
+    };
+    let grid = ts.tileset_grid(tms.id())?;
+    let Some(store) = ts.tile_store(tms) else {
+        return Err(ServiceError::CacheNotFound(ts.name.clone()).into());
+    };
+    let Ok(extent) = parse_extent(&params.extent) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+    let minzoom = params.minzoom.unwrap_or(grid.minzoom);
+    let maxzoom = params.maxzoom.unwrap_or(
+        grid.maxzoom
+            .min(minzoom.saturating_add(STATS_ZOOM_LEVELS - 1)),
+    );
+    let range = StatsRange {
+        minzoom: Some(minzoom),
+        maxzoom: Some(maxzoom),
+        extent,
+    };
+    let reader = store
+        .setup_reader(false)
         .await
-        .map(|metadata| HttpResponse::Ok().json(metadata))?)
+        .map_err(ServiceError::from)?;
+    let stats = collect_stats(
+        &ts.name,
+        reader,
+        tms,
+        ts.tile_format(),
+        &range,
+        params.top.unwrap_or(10),
+        num_cpus::get(),
+    )
+    .await
+    .map_err(ServiceError::from)?;
+    Ok(HttpResponse::Ok().json(stats))
 }
 
 /// Map tile endpoint
@@ -91,36 +326,137 @@ async fn map_tile(
     metrics: web::Data<WmsMetrics>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let (tms_id, z, x, y) = params.into_inner();
-    // This endpoint doesn't specify the tileset. Let's take the first dataset of the service.
-    let ts = service
-        .tilesets
-        .values()
-        .collect::<Vec<_>>()
+    let (tms_id, z, row, col) = params.into_inner();
+    // This endpoint doesn't specify the tileset. Let's take the first map tileset with this grid.
+    let identity = Identity::from_request(&req);
+    let mut tilesets = service
+        .tileset_list()
+        .into_iter()
+        .filter(|ts| {
+            !ts.is_vector()
+                && ts.grid(&tms_id).is_ok()
+                && ts.config().is_accessible(identity.as_ref())
+        })
+        .collect::<Vec<_>>();
+    tilesets.sort_by(|a, b| a.name.cmp(&b.name));
+    let ts = tilesets
         .first()
-        .cloned()
-        .ok_or(ServiceError::TilesetNotFound("No tileset found".into()))?;
-    let tms = ts.grid(&tms_id)?;
-    let format = format_accept_header(&req, ts.source.default_format()).await;
-    tile_request(ts, Some(tms), x, y, z, &format, metrics, req).await
+        .ok_or(ServiceError::TilesetNotFound("No map tileset found".into()))?;
+    ogcapi_tile_request(ts, &tms_id, z, row, col, metrics, req).await
 }
 
-async fn format_accept_header(req: &HttpRequest, default: &Format) -> Format {
-    let mut format_mime = web::Header::<header::Accept>::extract(req)
-        .await
-        .map(|accept| accept.preference().to_string())
-        .ok();
-    // override invalid request formats (TODO: check against available formats)
-    if let Some("image/avif") = format_mime.as_deref() {
-        format_mime = None;
-    }
-    let format = format_mime
-        .as_deref()
-        .and_then(Format::from_content_type)
-        .unwrap_or(*default);
-    format
+/// Tileset tile endpoint
+// tiles/{tileset}/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}
+async fn tileset_tile(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String, u8, u64, u64)>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (tileset, tms_id, z, row, col) = params.into_inner();
+    let ts = service
+        .tileset(&tileset)
+        .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    ogcapi_tile_request(&ts, &tms_id, z, row, col, metrics, req).await
+}
+
+/// Collection vector tile endpoint
+// collections/{collectionId}/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}
+async fn collection_tile(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String, u8, u64, u64)>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (collection_id, tms_id, z, row, col) = params.into_inner();
+    let ts = collection_tileset(&service, &collection_id, true)?;
+    ogcapi_tile_request(&ts, &tms_id, z, row, col, metrics, req).await
+}
+
+/// Collection map tile endpoint
+// collections/{collectionId}/map/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}
+async fn collection_map_tile(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String, u8, u64, u64)>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (collection_id, tms_id, z, row, col) = params.into_inner();
+    let ts = collection_tileset(&service, &collection_id, false)?;
+    ogcapi_tile_request(&ts, &tms_id, z, row, col, metrics, req).await
+}
+
+/// Tileset of collection with vector or map tiles
+fn collection_tileset<'a>(
+    service: &'a TileService,
+    collection_id: &str,
+    vector: bool,
+) -> Result<TileSetRef<'a>, ServiceError> {
+    service
+        .tileset(collection_id)
+        .filter(|ts| ts.is_vector() == vector)
+        .ok_or(ServiceError::TilesetNotFound(collection_id.to_string()))
+}
+
+/// OGC API tile request with format from `f` parameter or Accept header
+async fn ogcapi_tile_request(
+    ts: &TileSet,
+    tms_id: &str,
+    z: u8,
+    row: u64,
+    col: u64,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let tms = ts.grid(tms_id)?;
+    let Some(mut filters) = query_params(&req) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+    let format = match filters.remove("f") {
+        Some(suffix) => match Format::from_suffix(&suffix) {
+            Some(format) if ts.tile_formats().contains(&format) => format,
+            _ => return Ok(HttpResponse::NotAcceptable().finish()),
+        },
+        None => format_accept_header(&req, ts)
+            .await
+            .unwrap_or(*ts.tile_format()),
+    };
+    let response = tile_request(ts, Some(tms), col, row, z, &format, filters, metrics, req).await?;
+    Ok(vary_accept(response))
 }
 
+/// Preferred format of the `Accept` header delivered by the tileset
+async fn format_accept_header(req: &HttpRequest, ts: &TileSet) -> Option<Format> {
+    let accept = web::Header::<header::Accept>::extract(req).await.ok()?;
+    let formats = ts.tile_formats();
+    accept
+        .ranked()
+        .iter()
+        .filter_map(|mime| Format::from_content_type(mime.as_ref()))
+        .find(|format| formats.contains(format))
+}
+
+/// Mark response as depending on the `Accept` header
+fn vary_accept(mut response: HttpResponse) -> HttpResponse {
+    response
+        .headers_mut()
+        .append(header::VARY, header::HeaderValue::from_static("Accept"));
+    response
+}
+
+/// Query parameters with lowercase keys
+fn query_params(req: &HttpRequest) -> Option<HashMap<String, String>> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string())
+        .ok()
+        .map(|params| {
+            params
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v))
+                .collect()
+        })
+}
+
+/// Tile response with request metrics
 #[allow(clippy::too_many_arguments)]
 async fn tile_request(
     ts: &TileSet,
@@ -129,21 +465,40 @@ async fn tile_request(
     y: u64,
     z: u8,
     format: &Format,
+    filters: HashMap<String, String>,
     metrics: web::Data<WmsMetrics>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let tile = Xyz::new(x, y, z);
-    let mut filters: HashMap<String, String> =
-        match serde_urlencoded::from_str::<Vec<(String, String)>>(req.query_string()) {
-            Ok(f) => f
-                .iter()
-                .map(|k| (k.0.to_lowercase(), k.1.to_owned()))
-                .collect(),
-            Err(_e) => return Ok(HttpResponse::BadRequest().finish()),
-        };
+    let response = tile_response(ts, tms, x, y, z, format, filters, metrics, req).await;
+    let status = match &response {
+        Ok(resp) => resp.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    tile_metrics()
+        .requests_counter
+        .with_label_values(&[&ts.name, &z.to_string(), status.as_str()])
+        .inc();
+    response
+}
 
+#[allow(clippy::too_many_arguments)]
+async fn tile_response(
+    ts: &TileSet,
+    tms: Option<&Tms>,
+    x: u64,
+    y: u64,
+    z: u8,
+    format: &Format,
+    mut filters: HashMap<String, String>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let hidden_layers = check_access(ts, &req)?;
+    let tile = Xyz::new(x, y, z);
     let datetime = filters.remove("datetime");
     let fp = FilterParams { datetime, filters };
+    fp.validate(&ts.config().query_params())
+        .map_err(|e| ServiceError::TileSourceError(e.into()))?;
     let compression = req
         .headers()
         .get(header::ACCEPT_ENCODING)
@@ -164,10 +519,35 @@ async fn tile_request(
     };
     let tms = tms.unwrap_or(ts.default_grid(z)?);
     match ts
-        .tile_cached(tms, &tile, &fp, format, compression, request_params)
+        .tile_cached(
+            tms,
+            &tile,
+            &fp,
+            format,
+            compression,
+            &hidden_layers,
+            request_params,
+        )
         .await
     {
-        Ok(Some(tile_resp)) => {
+        Ok(Some(mut tile_resp)) => {
+            if ts.config().is_restricted() {
+                private_cache_headers(&mut tile_resp);
+            }
+            if is_not_modified(&req, tile_resp.headers()) {
+                let mut r = HttpResponse::NotModified();
+                for key in [
+                    header::ETAG,
+                    header::LAST_MODIFIED,
+                    header::CACHE_CONTROL,
+                    header::VARY,
+                ] {
+                    if let Some(value) = tile_resp.headers().get(&key) {
+                        r.insert_header((key, value.clone()));
+                    }
+                }
+                return Ok(r.finish());
+            }
             let mut r = HttpResponse::Ok();
             if let Some(content_type) = tile_resp.content_type() {
                 r.content_type(content_type);
@@ -178,7 +558,22 @@ async fn tile_request(
             }
             Ok(r.streaming(tile_resp.into_stream()))
         }
-        Ok(None) => Ok(HttpResponse::NoContent().finish()),
+        Ok(None) => {
+            let mut tile_resp = TileResponse::new();
+            ts.insert_cache_control(&mut tile_resp, z);
+            if ts.config().is_restricted() {
+                private_cache_headers(&mut tile_resp);
+            }
+            let mut r = HttpResponse::NoContent();
+            for (key, value) in tile_resp.headers() {
+                r.insert_header((key, value));
+            }
+            Ok(r.finish())
+        }
+        Err(
+            e @ (ServiceError::TileOutOfBounds
+            | ServiceError::TileSourceError(TileSourceError::InvalidFilterParam(_))),
+        ) => Err(e.into()),
         Err(e) => {
             error!("Tile creation error: {e}");
             Ok(HttpResponse::InternalServerError().finish())
@@ -186,121 +581,394 @@ async fn tile_request(
     }
 }
 
-/// list of available tilesets
-// tiles
-async fn get_tile_sets_list(service: web::Data<TileService>) -> HttpResponse {
-    let href_prefix = service.href_prefix();
-    let tile_set_items: Vec<TileSetItem> = service
-        .tilesets
+/// Check admin access of the user logged in with the request session
+fn check_admin(service: &TileService, req: &HttpRequest) -> Result<(), ServiceError> {
+    let allowed_groups = service
+        .admin
+        .as_ref()
+        .map(|admin| admin.allowed_groups.as_slice())
+        // Empty groups would allow access for everybody
+        .filter(|groups| !groups.is_empty())
+        .ok_or(ServiceError::AccessDenied("admin".to_string()))?;
+    match Identity::from_request(req) {
+        None => Err(ServiceError::AuthenticationRequired),
+        Some(identity) if groups_allowed(allowed_groups, Some(&identity)) => Ok(()),
+        Some(_) => Err(ServiceError::AccessDenied("admin".to_string())),
+    }
+}
+
+/// Start seed job
+// POST /admin/seed
+async fn seed_job_start(
+    service: web::Data<TileService>,
+    params: web::Json<SeedJobParams>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    check_admin(&service, &req)?;
+    let params = params.into_inner();
+    {
+        let ts = service
+            .tileset(&params.tileset)
+            .ok_or(ServiceError::TilesetNotFound(params.tileset.clone()))?;
+        if ts.cache_config().is_none() {
+            return Err(ServiceError::CacheNotFound(params.tileset.clone()).into());
+        }
+        if let Some(tms_id) = &params.tms {
+            ts.grid(tms_id)?;
+        }
+    }
+    parse_extent(&params.extent).map_err(|e| error::ErrorBadRequest(e.to_string()))?;
+    let job = service
+        .seed_jobs
+        .spawn(service.clone().into_inner(), params);
+    Ok(HttpResponse::Created().json(job.info()))
+}
+
+/// List of seed jobs
+// GET /admin/seed
+async fn seed_jobs_list(
+    service: web::Data<TileService>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    check_admin(&service, &req)?;
+    let jobs = service
+        .seed_jobs
+        .jobs()
         .iter()
-        .map(|(ts_name, tileset)| {
-            let tms = tileset.default_grid(0).expect("default grid missing");
-            let tiling_scheme_links = tileset.tms.iter().map(|grid| {
-                let grid_tms = &grid.tms.tms;
-                Link {
-                    rel: "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme".to_string(),
-                    r#type: Some("application/json".to_string()),
-                    title: Some("Tile Matrix Set definition (as JSON)".to_string()),
-                    href: format!("{href_prefix}/tileMatrixSets/{}", &grid_tms.id),
-                    hreflang: None,
-                    length: None,
-                }
-            });
-            TileSetItem {
-                title: Some(ts_name.to_string()),
-                data_type: DataType::Vector,
-                crs: tms.crs().clone(),
-                tile_matrix_set_uri: tms.tms.uri.clone(),
-                links: [
-                    Link {
-                        rel: "self".to_string(),
-                        r#type: Some("application/json".to_string()),
-                        title: Some(format!("Tileset metadata for {ts_name} (as JSON)")),
-                        href: format!("{href_prefix}/tiles/{ts_name}"),
-                        hreflang: None,
-                        length: None,
-                    },
-                    Link {
-                        rel: "self".to_string(),
-                        r#type: Some("application/json+tilejson".to_string()),
-                        title: Some(format!(
-                            "Tileset metadata for {ts_name} (in TileJSON format)"
-                        )),
-                        href: format!("{href_prefix}/xyz/{ts_name}.json"),
-                        hreflang: None,
-                        length: None,
-                    },
-                    Link {
-                        rel: "item".to_string(),
-                        r#type: Some("application/vnd.mapbox-vector-tile".to_string()),
-                        title: Some(format!("Tiles for {ts_name} (as MVT)")),
-                        href: format!(
-                            "{href_prefix}/map/tiles/{}/{{tileMatrix}}/{{tileRow}}/{{tileCol}}",
-                            &tms.tms.id
-                        ),
-                        hreflang: None,
-                        length: None,
-                    },
-                ]
-                .into_iter()
-                .chain(tiling_scheme_links)
-                .collect(),
-            }
-        })
-        .collect();
-    let tilesets = TileSets {
-        tilesets: tile_set_items,
-        links: None,
-    };
-    HttpResponse::Ok().json(tilesets)
+        .map(|job| job.info())
+        .collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(jobs))
 }
 
-/// tileset metadata
-// tiles/{tileMatrixSetId}
-async fn get_tile_set(
+/// Seed job status
+// GET /admin/seed/{jobId}
+async fn seed_job(
+    service: web::Data<TileService>,
+    job_id: web::Path<u64>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    check_admin(&service, &req)?;
+    let job = service
+        .seed_jobs
+        .job(*job_id)
+        .ok_or(error::ErrorNotFound("Seed job not found"))?;
+    Ok(HttpResponse::Ok().json(job.info()))
+}
+
+/// Cancel active seed job or remove completed job
+// DELETE /admin/seed/{jobId}
+async fn seed_job_cancel(
+    service: web::Data<TileService>,
+    job_id: web::Path<u64>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    check_admin(&service, &req)?;
+    let job = service
+        .seed_jobs
+        .remove(*job_id)
+        .ok_or(error::ErrorNotFound("Seed job not found"))?;
+    Ok(HttpResponse::Ok().json(job.info()))
+}
+
+#[derive(Deserialize)]
+struct TruncateParams {
+    tms: Option<String>,
+}
+
+/// Remove cached tiles of a tileset
+// DELETE /admin/cache/{tileset}
+async fn truncate_cache(
     service: web::Data<TileService>,
     tileset: web::Path<String>,
+    params: web::Query<TruncateParams>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
+    check_admin(&service, &req)?;
     let ts = service
         .tileset(&tileset)
         .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
-    let tms = ts.default_grid(0)?;
-    let tile_matrix_set_limits = tms
+    if let Some(tms_id) = &params.tms {
+        ts.grid(tms_id)?;
+    }
+    ts.truncate_cache(params.tms.as_deref()).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Prevent shared caches from storing responses depending on the user identity
+fn private_cache_headers(tile_resp: &mut TileResponse) {
+    let cache_control = match tile_resp
+        .headers()
+        .get(header::CACHE_CONTROL)
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(value) => format!("private, {value}"),
+        None => "private".to_string(),
+    };
+    tile_resp.insert_header((header::CACHE_CONTROL, cache_control));
+    tile_resp.insert_header((header::VARY, "Cookie"));
+}
+
+/// Evaluate conditional request headers (`If-None-Match` and `If-Modified-Since`)
+fn is_not_modified(req: &HttpRequest, resp_headers: &header::HeaderMap) -> bool {
+    let req_headers = req.headers();
+    if let Some(if_none_match) = req_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+    {
+        // If-None-Match takes precedence over If-Modified-Since
+        let Some(etag) = resp_headers.get(header::ETAG).and_then(|v| v.to_str().ok()) else {
+            return false;
+        };
+        return etag_matches(if_none_match, etag);
+    }
+    if let (Some(since), Some(modified)) = (
+        req_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<header::HttpDate>().ok()),
+        resp_headers
+            .get(header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<header::HttpDate>().ok()),
+    ) {
+        return modified <= since;
+    }
+    false
+}
+
+/// Weak comparison of entity tags
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let opaque_tag = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = opaque_tag(etag);
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || opaque_tag(tag) == etag)
+}
+
+/// WMTS capabilities
+// wmts/1.0.0/WMTSCapabilities.xml
+async fn wmts_capabilities(service: web::Data<TileService>, req: HttpRequest) -> HttpResponse {
+    let base_url = public_base_url(&service, &req);
+    HttpResponse::Ok()
+        .content_type("application/xml")
+        .body(wmts::capabilities(
+            &service,
+            &base_url,
+            Identity::from_request(&req).as_ref(),
+        ))
+}
+
+fn public_base_url(service: &TileService, req: &HttpRequest) -> String {
+    let href_prefix = service.href_prefix();
+    if href_prefix.is_empty() {
+        abs_req_baseurl(req)
+    } else {
+        href_prefix.to_string()
+    }
+}
+
+fn wmts_exception(code: &str, locator: &str) -> HttpResponse {
+    HttpResponse::BadRequest()
+        .content_type("application/xml")
+        .body(wmts::exception_report(code, locator))
+}
+
+/// WMTS KVP endpoint
+// wmts?SERVICE=WMTS&REQUEST=GetTile&LAYER={tileset}&TILEMATRIXSET=..&TILEMATRIX=..&TILEROW=..&TILECOL=..&FORMAT=..
+async fn wmts_kvp(
+    service: web::Data<TileService>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let Some(mut params) = query_params(&req) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+    match params.remove("request").as_deref() {
+        Some(r) if r.eq_ignore_ascii_case("GetCapabilities") => {
+            return Ok(wmts_capabilities(service, req).await)
+        }
+        Some(r) if r.eq_ignore_ascii_case("GetTile") => {}
+        Some(_) => return Ok(wmts_exception("OperationNotSupported", "request")),
+        None => return Ok(wmts_exception("MissingParameterValue", "request")),
+    }
+    for key in ["service", "version", "style"] {
+        params.remove(key);
+    }
+    let mut kvp = HashMap::new();
+    for key in ["layer", "tilematrixset", "tilematrix", "tilerow", "tilecol"] {
+        let Some(value) = params.remove(key) else {
+            return Ok(wmts_exception("MissingParameterValue", key));
+        };
+        kvp.insert(key, value);
+    }
+    let Some(ts) = service.tileset(&kvp["layer"]) else {
+        return Ok(wmts_exception("InvalidParameterValue", "layer"));
+    };
+    let Ok(tms) = ts.grid(&kvp["tilematrixset"]) else {
+        return Ok(wmts_exception("InvalidParameterValue", "tilematrixset"));
+    };
+    let Some(z) = wmts::zoom_level(tms, &kvp["tilematrix"]) else {
+        return Ok(wmts_exception("InvalidParameterValue", "tilematrix"));
+    };
+    let Ok(y) = kvp["tilerow"].parse() else {
+        return Ok(wmts_exception("InvalidParameterValue", "tilerow"));
+    };
+    let Ok(x) = kvp["tilecol"].parse() else {
+        return Ok(wmts_exception("InvalidParameterValue", "tilecol"));
+    };
+    let format = match params.remove("format") {
+        Some(mime) => match Format::from_content_type(&mime) {
+            Some(format) if ts.tile_formats().contains(&format) => format,
+            _ => return Ok(wmts_exception("InvalidParameterValue", "format")),
+        },
+        None => *ts.tile_format(),
+    };
+    tile_request(&ts, Some(tms), x, y, z, &format, params, metrics, req).await
+}
+
+/// WMTS RESTful tile endpoint
+// wmts/1.0.0/{layer}/{style}/{tileMatrixSet}/{tileMatrix}/{tileRow}/{tileCol}.{format}
+async fn wmts_tile(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String, String, String, u64, u64, String)>,
+    metrics: web::Data<WmsMetrics>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (tileset, _style, tms_id, tile_matrix, y, x, format) = params.into_inner();
+    let ts = service
+        .tileset(&tileset)
+        .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    let tms = ts.grid(&tms_id)?;
+    let Some(z) = wmts::zoom_level(tms, &tile_matrix) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Some(format) = suffix_format(&ts, &format) else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let Some(filters) = query_params(&req) else {
+        return Ok(HttpResponse::BadRequest().finish());
+    };
+    tile_request(&ts, Some(tms), x, y, z, &format, filters, metrics, req).await
+}
+
+/// OGC API data type of tileset
+fn data_type(ts: &TileSet) -> DataType {
+    if ts.is_vector() {
+        DataType::Vector
+    } else {
+        DataType::Map
+    }
+}
+
+/// Tile matrix limits of configured zoom levels
+fn tile_matrix_set_limits(grid: &TileSetGrid) -> Vec<TileMatrixLimits> {
+    grid.tms
         .tms
         .tile_matrices
         .iter()
+        .filter(|tm| {
+            matches!(tm.id.parse::<u8>(), Ok(z) if z >= grid.minzoom && z <= grid.served_maxzoom())
+        })
         .map(|tm| TileMatrixLimits {
             tile_matrix: tm.id.clone(),
             min_tile_row: 0,
-            max_tile_row: tm.matrix_width.into(),
+            max_tile_row: u64::from(tm.matrix_height) - 1,
             min_tile_col: 0,
-            max_tile_col: tm.matrix_height.into(),
+            max_tile_col: u64::from(tm.matrix_width) - 1,
         })
-        .collect();
+        .collect()
+}
 
-    let href_prefix = service.href_prefix();
-    let tiling_scheme_links = ts.tms.iter().map(|grid| {
-        let grid_tms = &grid.tms.tms;
-        Link {
-            rel: "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme".to_string(),
-            r#type: Some("application/json".to_string()),
-            title: Some("Tile Matrix Set definition (as JSON)".to_string()),
-            href: format!("{href_prefix}/tileMatrixSets/{}", &grid_tms.id),
+fn tiling_scheme_link(href_prefix: &str, grid: &TileSetGrid) -> Link {
+    Link {
+        rel: "http://www.opengis.net/def/rel/ogc/1.0/tiling-scheme".to_string(),
+        r#type: Some("application/json".to_string()),
+        title: Some("Tile Matrix Set definition (as JSON)".to_string()),
+        href: format!("{href_prefix}/tileMatrixSets/{}", grid.tms.id()),
+        hreflang: None,
+        length: None,
+    }
+}
+
+/// Tile links for all formats of a tileset
+fn tile_links(ts: &TileSet, tiles_url: &str) -> Vec<Link> {
+    ts.tile_formats()
+        .iter()
+        .map(|format| Link {
+            rel: "item".to_string(),
+            r#type: Some(format.content_type().to_string()),
+            title: Some(format!(
+                "Tiles for {} (as {})",
+                ts.name,
+                format.file_suffix().to_uppercase()
+            )),
+            href: format!(
+                "{tiles_url}/{{tileMatrix}}/{{tileRow}}/{{tileCol}}?f={}",
+                format.file_suffix()
+            ),
             hreflang: None,
             length: None,
-        }
-    });
+            // TODO: "templated": true
+        })
+        .collect()
+}
 
-    let tileset = ogcapi_types::tiles::TileSet {
+fn tile_set_item(
+    ts: &TileSet,
+    grid: &TileSetGrid,
+    href_prefix: &str,
+    tiles_url: &str,
+) -> TileSetItem {
+    let ts_name = &ts.name;
+    TileSetItem {
+        title: Some(ts_name.to_string()),
+        data_type: data_type(ts),
+        crs: grid.tms.crs().clone(),
+        tile_matrix_set_uri: grid.tms.tms.uri.clone(),
+        links: [
+            Link {
+                rel: "self".to_string(),
+                r#type: Some("application/json".to_string()),
+                title: Some(format!("Tileset metadata for {ts_name} (as JSON)")),
+                href: tiles_url.to_string(),
+                hreflang: None,
+                length: None,
+            },
+            Link {
+                rel: "self".to_string(),
+                r#type: Some("application/json+tilejson".to_string()),
+                title: Some(format!(
+                    "Tileset metadata for {ts_name} (in TileJSON format)"
+                )),
+                href: format!("{href_prefix}/xyz/{ts_name}.json"),
+                hreflang: None,
+                length: None,
+            },
+            tiling_scheme_link(href_prefix, grid),
+        ]
+        .into_iter()
+        .chain(tile_links(ts, tiles_url))
+        .collect(),
+    }
+}
+
+fn tile_set_metadata(
+    ts: &TileSet,
+    grid: &TileSetGrid,
+    href_prefix: &str,
+    tiles_url: &str,
+) -> ogcapi_types::tiles::TileSet {
+    let ts_name = &ts.name;
+    ogcapi_types::tiles::TileSet {
         title_description_keywords: TitleDescriptionKeywords {
-            title: Some(tileset.to_string()),
+            title: Some(ts_name.to_string()),
             description: None,
             keywords: None,
         },
-        data_type: DataType::Vector,
-        tile_matrix_set_uri: tms.tms.uri.clone(),
-        tile_matrix_set_limits: Some(tile_matrix_set_limits),
-        crs: tms.crs().clone(),
+        data_type: data_type(ts),
+        tile_matrix_set_uri: grid.tms.tms.uri.clone(),
+        tile_matrix_set_limits: Some(tile_matrix_set_limits(grid)),
+        crs: grid.tms.crs().clone(),
         epoch: None,
         layers: None,
         bounding_box: None,
@@ -317,28 +985,172 @@ async fn get_tile_set(
             Link {
                 rel: "self".to_string(),
                 r#type: Some("application/json".to_string()),
-                title: Some(format!("Tileset metadata for {tileset} (as JSON)")),
-                href: format!("{href_prefix}/tiles/{tileset}"),
-                hreflang: None,
-                length: None,
-            },
-            Link {
-                rel: "item".to_string(),
-                r#type: Some("application/vnd.mapbox-vector-tile".to_string()),
-                title: Some(format!("Tiles for {tileset} (as MVT)")),
-                href: format!(
-                    "{href_prefix}/xyz/{tileset}/{{tileMatrix}}/{{tileRow}}/{{tileCol}}.mvt"
-                ),
+                title: Some(format!("Tileset metadata for {ts_name} (as JSON)")),
+                href: tiles_url.to_string(),
                 hreflang: None,
                 length: None,
-                // TODO: "templated": true
             },
+            tiling_scheme_link(href_prefix, grid),
         ]
         .into_iter()
-        .chain(tiling_scheme_links)
+        .chain(tile_links(ts, tiles_url))
         .collect(),
+    }
+}
+
+/// list of available tilesets
+// tiles
+async fn get_tile_sets_list(service: web::Data<TileService>, req: HttpRequest) -> HttpResponse {
+    let href_prefix = service.href_prefix();
+    let identity = Identity::from_request(&req);
+    let mut tilesets = service
+        .tileset_list()
+        .into_iter()
+        .filter(|ts| ts.config().is_accessible(identity.as_ref()))
+        .collect::<Vec<_>>();
+    tilesets.sort_by(|a, b| a.name.cmp(&b.name));
+    let tile_set_items: Vec<TileSetItem> = tilesets
+        .iter()
+        .flat_map(|ts| {
+            ts.tms.iter().map(|grid| {
+                let tiles_url = format!("{href_prefix}/tiles/{}/{}", ts.name, grid.tms.id());
+                tile_set_item(ts, grid, href_prefix, &tiles_url)
+            })
+        })
+        .collect();
+    let tilesets = TileSets {
+        tilesets: tile_set_items,
+        links: None,
     };
-    Ok(HttpResponse::Ok().json(tileset))
+    HttpResponse::Ok().json(tilesets)
+}
+
+/// tileset metadata of default grid
+// tiles/{tileset}
+async fn get_tile_set(
+    service: web::Data<TileService>,
+    tileset: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let ts = service
+        .tileset(&tileset)
+        .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    check_access(&ts, &req)?;
+    let tms = ts.default_grid(0)?;
+    let grid = ts.tileset_grid(tms.id())?;
+    let href_prefix = service.href_prefix();
+    let tiles_url = format!("{href_prefix}/tiles/{}/{}", ts.name, grid.tms.id());
+    Ok(HttpResponse::Ok().json(tile_set_metadata(&ts, grid, href_prefix, &tiles_url)))
+}
+
+/// tileset metadata
+// tiles/{tileset}/{tileMatrixSetId}
+async fn get_tile_set_grid(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (tileset, tms_id) = params.into_inner();
+    let ts = service
+        .tileset(&tileset)
+        .ok_or(ServiceError::TilesetNotFound(tileset.clone()))?;
+    check_access(&ts, &req)?;
+    let grid = ts.tileset_grid(&tms_id)?;
+    let href_prefix = service.href_prefix();
+    let tiles_url = format!("{href_prefix}/tiles/{tileset}/{tms_id}");
+    Ok(HttpResponse::Ok().json(tile_set_metadata(&ts, grid, href_prefix, &tiles_url)))
+}
+
+/// Path of vector or map tiles of a collection
+fn collection_tiles_path(collection_id: &str, vector: bool) -> String {
+    if vector {
+        format!("collections/{collection_id}/tiles")
+    } else {
+        format!("collections/{collection_id}/map/tiles")
+    }
+}
+
+async fn collection_tile_sets_list(
+    service: &TileService,
+    collection_id: &str,
+    vector: bool,
+    req: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let ts = collection_tileset(service, collection_id, vector)?;
+    check_access(&ts, req)?;
+    let href_prefix = service.href_prefix();
+    let tiles_path = collection_tiles_path(collection_id, vector);
+    let tile_set_items = ts
+        .tms
+        .iter()
+        .map(|grid| {
+            let tiles_url = format!("{href_prefix}/{tiles_path}/{}", grid.tms.id());
+            tile_set_item(&ts, grid, href_prefix, &tiles_url)
+        })
+        .collect();
+    let tilesets = TileSets {
+        tilesets: tile_set_items,
+        links: None,
+    };
+    Ok(HttpResponse::Ok().json(tilesets))
+}
+
+async fn collection_tile_set(
+    service: &TileService,
+    collection_id: &str,
+    tms_id: &str,
+    vector: bool,
+    req: &HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let ts = collection_tileset(service, collection_id, vector)?;
+    check_access(&ts, req)?;
+    let grid = ts.tileset_grid(tms_id)?;
+    let href_prefix = service.href_prefix();
+    let tiles_path = collection_tiles_path(collection_id, vector);
+    let tiles_url = format!("{href_prefix}/{tiles_path}/{tms_id}");
+    Ok(HttpResponse::Ok().json(tile_set_metadata(&ts, grid, href_prefix, &tiles_url)))
+}
+
+/// list of available vector tilesets of a collection
+// collections/{collectionId}/tiles
+async fn get_collection_tile_sets_list(
+    service: web::Data<TileService>,
+    collection_id: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    collection_tile_sets_list(&service, &collection_id, true, &req).await
+}
+
+/// vector tileset metadata of a collection
+// collections/{collectionId}/tiles/{tileMatrixSetId}
+async fn get_collection_tile_set(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (collection_id, tms_id) = params.into_inner();
+    collection_tile_set(&service, &collection_id, &tms_id, true, &req).await
+}
+
+/// list of available map tilesets of a collection
+// collections/{collectionId}/map/tiles
+async fn get_collection_map_tile_sets_list(
+    service: web::Data<TileService>,
+    collection_id: web::Path<String>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    collection_tile_sets_list(&service, &collection_id, false, &req).await
+}
+
+/// map tileset metadata of a collection
+// collections/{collectionId}/map/tiles/{tileMatrixSetId}
+async fn get_collection_map_tile_set(
+    service: web::Data<TileService>,
+    params: web::Path<(String, String)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let (collection_id, tms_id) = params.into_inner();
+    collection_tile_set(&service, &collection_id, &tms_id, false, &req).await
 }
 
 /// list of available tiling schemes
@@ -374,8 +1186,10 @@ async fn get_tile_matrix_set(
     service: web::Data<TileService>,
     tile_matrix_set_id: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    if let Some(grid) = service.grid(&tile_matrix_set_id) {
-        Ok(HttpResponse::Ok().json(grid.tms.clone()))
+    if let Some(json) = service.grid_definition(&tile_matrix_set_id) {
+        Ok(HttpResponse::Ok().json(json))
+    } else if let Some(grid) = service.grid(&tile_matrix_set_id) {
+        Ok(HttpResponse::Ok().json(grid.tms))
     } else {
         Err(ServiceError::TilesetGridNotFound.into())
     }
@@ -392,16 +1206,70 @@ impl ServiceEndpoints for TileService {
                 ),
             )
             .service(web::resource("/xyz/{tileset}.style.json").route(web::get().to(stylejson)))
+            .service(web::resource("/styles").route(web::get().to(styles_list)))
+            .service(web::resource("/styles/{styleId}").route(web::get().to(style)))
+            .service(web::resource("/styles/{styleId}/{file}").route(web::get().to(style_sprite)))
+            .service(
+                web::resource("/fonts/{fontstack}/{range}.pbf").route(web::get().to(glyphs)),
+            )
             .service(web::resource("/xyz/{tileset}.json").route(web::get().to(tilejson)))
             .service(
                 web::resource("/xyz/{tileset}/metadata.json").route(web::get().to(metadatajson)),
             )
+            .service(web::resource("/xyz/{tileset}/stats.json").route(web::get().to(statsjson)))
+            .service(
+                web::resource("/wmts/1.0.0/WMTSCapabilities.xml")
+                    .route(web::get().to(wmts_capabilities)),
+            )
+            .service(
+                web::resource(
+                    "/wmts/1.0.0/{layer}/{style}/{tileMatrixSet}/{tileMatrix}/{tileRow}/{tileCol}.{format}",
+                )
+                .route(web::get().to(wmts_tile)),
+            )
+            .service(web::resource("/wmts").route(web::get().to(wmts_kvp)))
             .service(
                 web::resource("/map/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}")
                     .route(web::get().to(map_tile)),
             )
-            .service(web::resource("/tiles/{tileMatrixSetId}").route(web::get().to(get_tile_set)))
+            .service(
+                web::resource("/tiles/{tileset}/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}")
+                    .route(web::get().to(tileset_tile)),
+            )
+            .service(
+                web::resource("/tiles/{tileset}/{tileMatrixSetId}")
+                    .route(web::get().to(get_tile_set_grid)),
+            )
+            .service(web::resource("/tiles/{tileset}").route(web::get().to(get_tile_set)))
             .service(web::resource("/tiles").route(web::get().to(get_tile_sets_list)))
+            .service(
+                web::resource(
+                    "/collections/{collectionId}/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}",
+                )
+                .route(web::get().to(collection_tile)),
+            )
+            .service(
+                web::resource("/collections/{collectionId}/tiles/{tileMatrixSetId}")
+                    .route(web::get().to(get_collection_tile_set)),
+            )
+            .service(
+                web::resource("/collections/{collectionId}/tiles")
+                    .route(web::get().to(get_collection_tile_sets_list)),
+            )
+            .service(
+                web::resource(
+                    "/collections/{collectionId}/map/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}",
+                )
+                .route(web::get().to(collection_map_tile)),
+            )
+            .service(
+                web::resource("/collections/{collectionId}/map/tiles/{tileMatrixSetId}")
+                    .route(web::get().to(get_collection_map_tile_set)),
+            )
+            .service(
+                web::resource("/collections/{collectionId}/map/tiles")
+                    .route(web::get().to(get_collection_map_tile_sets_list)),
+            )
             .service(
                 web::resource("/tileMatrixSets").route(web::get().to(get_tile_matrix_sets_list)),
             )
@@ -409,8 +1277,38 @@ impl ServiceEndpoints for TileService {
                 web::resource("/tileMatrixSets/{tileMatrixSetId}")
                     .route(web::get().to(get_tile_matrix_set)),
             );
+        if self.admin.is_some() {
+            cfg.service(
+                web::resource("/admin/seed")
+                    .route(web::get().to(seed_jobs_list))
+                    .route(web::post().to(seed_job_start)),
+            )
+            .service(
+                web::resource("/admin/seed/{jobId}")
+                    .route(web::get().to(seed_job))
+                    .route(web::delete().to(seed_job_cancel)),
+            )
+            .service(
+                web::resource("/admin/cache/{tileset}").route(web::delete().to(truncate_cache)),
+            );
+        }
         if cfg!(not(feature = "map-server")) {
             cfg.app_data(web::Data::new(WmsMetrics::default()));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_comparison() {
+        let etag = r#"W/"af1349b9""#;
+        assert!(etag_matches(r#"W/"af1349b9""#, etag));
+        assert!(etag_matches(r#""af1349b9""#, etag));
+        assert!(etag_matches(r#""xyz", W/"af1349b9""#, etag));
+        assert!(etag_matches("*", etag));
+        assert!(!etag_matches(r#"W/"af1349b8""#, etag));
+    }
+}
@@ -1,4 +1,6 @@
+use crate::config::{QueryParamCfg, QueryParamType, SourceParamCfg, TileSetCfg};
 use serde::Deserialize;
+use serde_json::json;
 use std::collections::HashMap;
 
 #[derive(Debug, Deserialize, Default, Clone)]
@@ -14,6 +16,10 @@ pub enum TemporalType {
 }
 
 impl FilterParams {
+    /// No datetime or other filter parameters given
+    pub fn is_empty(&self) -> bool {
+        self.datetime.is_none() && self.filters.is_empty()
+    }
     pub fn as_args(&self) -> String {
         let mut args = vec![
             Some("".to_string()),
@@ -54,4 +60,248 @@ impl FilterParams {
     pub fn other_params(&self) -> Result<&HashMap<String, String>, Box<dyn std::error::Error>> {
         Ok(&self.filters)
     }
+    /// Check values of declared query parameters
+    pub fn validate(&self, params: &[&QueryParamCfg]) -> Result<(), FilterParamError> {
+        for param in params {
+            match self.filters.get(&param.name) {
+                Some(value) => {
+                    param.parse(value)?;
+                }
+                None if param.default.is_none() => {
+                    return Err(FilterParamError::Missing(param.name.clone()))
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FilterParamError {
+    #[error("Query parameter `{0}` missing")]
+    Missing(String),
+    #[error("Invalid value `{value}` for query parameter `{name}`: {reason}")]
+    InvalidValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+}
+
+/// Typed value of a query parameter
+#[derive(Clone, PartialEq, Debug)]
+pub enum ParamValue {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Date(chrono::NaiveDate),
+    Bbox([f64; 4]),
+}
+
+impl QueryParamCfg {
+    /// Parse and validate parameter value
+    pub fn parse(&self, value: &str) -> Result<ParamValue, FilterParamError> {
+        let invalid = |reason: String| FilterParamError::InvalidValue {
+            name: self.name.clone(),
+            value: value.to_string(),
+            reason,
+        };
+        let bound = |bound: &Option<_>| bound.as_ref().map(ToString::to_string);
+        let param = match self.param_type {
+            QueryParamType::String => ParamValue::Text(value.to_string()),
+            QueryParamType::Enum => {
+                if !self.values.iter().any(|v| v == value) {
+                    return Err(invalid(format!(
+                        "expected one of {}",
+                        self.values.join(", ")
+                    )));
+                }
+                ParamValue::Text(value.to_string())
+            }
+            QueryParamType::Int => {
+                let v = value
+                    .parse::<i64>()
+                    .map_err(|_| invalid("integer expected".to_string()))?;
+                check_range(v as f64, bound(&self.min), bound(&self.max), |b| {
+                    b.parse::<f64>().ok()
+                })
+                .map_err(invalid)?;
+                ParamValue::Int(v)
+            }
+            QueryParamType::Float => {
+                let v = value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or_else(|| invalid("number expected".to_string()))?;
+                check_range(v, bound(&self.min), bound(&self.max), |b| {
+                    b.parse::<f64>().ok()
+                })
+                .map_err(invalid)?;
+                ParamValue::Float(v)
+            }
+            QueryParamType::Date => {
+                let v = value
+                    .parse::<chrono::NaiveDate>()
+                    .map_err(|_| invalid("date `YYYY-MM-DD` expected".to_string()))?;
+                check_range(v, bound(&self.min), bound(&self.max), |b| b.parse().ok())
+                    .map_err(invalid)?;
+                ParamValue::Date(v)
+            }
+            QueryParamType::Bbox => {
+                let coords = value
+                    .split(',')
+                    .map(|v| v.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|c| c.len() == 4 && c[0] <= c[2] && c[1] <= c[3])
+                    .ok_or_else(|| invalid("bbox `minx,miny,maxx,maxy` expected".to_string()))?;
+                ParamValue::Bbox([coords[0], coords[1], coords[2], coords[3]])
+            }
+        };
+        Ok(param)
+    }
+    /// OpenAPI query parameter definition
+    pub fn openapi_parameter(&self) -> serde_json::Value {
+        let mut schema = match self.param_type {
+            QueryParamType::String => json!({"type": "string"}),
+            QueryParamType::Enum => json!({"type": "string", "enum": self.values}),
+            QueryParamType::Int => json!({"type": "integer"}),
+            QueryParamType::Float => json!({"type": "number"}),
+            QueryParamType::Date => json!({"type": "string", "format": "date"}),
+            QueryParamType::Bbox => json!({
+                "type": "array",
+                "items": {"type": "number"},
+                "minItems": 4,
+                "maxItems": 4
+            }),
+        };
+        if matches!(self.param_type, QueryParamType::Int | QueryParamType::Float) {
+            if let Some(min) = &self.min {
+                schema["minimum"] = json!(min);
+            }
+            if let Some(max) = &self.max {
+                schema["maximum"] = json!(max);
+            }
+        }
+        if let Some(default) = &self.default {
+            schema["default"] = json!(default);
+        }
+        let mut param = json!({
+            "name": self.name,
+            "in": "query",
+            "required": self.default.is_none(),
+            "schema": schema,
+        });
+        if self.param_type == QueryParamType::Bbox {
+            param["style"] = "form".into();
+            param["explode"] = false.into();
+        }
+        if let Some(description) = &self.description {
+            param["description"] = description.as_str().into();
+        }
+        param
+    }
+    /// Value of request parameter or default value
+    pub fn value(&self, filter: &FilterParams) -> Result<ParamValue, FilterParamError> {
+        match (filter.filters.get(&self.name), &self.default) {
+            (Some(value), _) => self.parse(value),
+            (None, Some(default)) => self.parse(&default.to_string()),
+            (None, None) => Err(FilterParamError::Missing(self.name.clone())),
+        }
+    }
+}
+
+/// Check value against configured `min` and `max`
+fn check_range<T: PartialOrd + std::fmt::Display>(
+    value: T,
+    min: Option<String>,
+    max: Option<String>,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<(), String> {
+    if let Some(min) = min.as_deref().and_then(&parse) {
+        if value < min {
+            return Err(format!("minimum is {min}"));
+        }
+    }
+    if let Some(max) = max.as_deref().and_then(&parse) {
+        if value > max {
+            return Err(format!("maximum is {max}"));
+        }
+    }
+    Ok(())
+}
+
+impl TileSetCfg {
+    /// Declared query parameters of all layers
+    pub fn query_params(&self) -> Vec<&QueryParamCfg> {
+        let mut params: Vec<&QueryParamCfg> = Vec::new();
+        if let SourceParamCfg::Postgis(cfg) = &self.source {
+            for param in cfg.layers.iter().flat_map(|layer| &layer.params) {
+                if !params.iter().any(|p| p.name == param.name) {
+                    params.push(param);
+                }
+            }
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::QueryParamValueCfg;
+
+    #[test]
+    fn typed_params() {
+        let param = |name: &str, param_type| QueryParamCfg {
+            name: name.to_string(),
+            param_type,
+            default: None,
+            min: None,
+            max: None,
+            values: Vec::new(),
+            srid: None,
+            description: None,
+        };
+        let level = QueryParamCfg {
+            min: Some(QueryParamValueCfg::Number(1.0)),
+            max: Some(QueryParamValueCfg::Number(5.0)),
+            default: Some(QueryParamValueCfg::Number(3.0)),
+            ..param("level", QueryParamType::Int)
+        };
+        assert_eq!(level.parse("2").unwrap(), ParamValue::Int(2));
+        assert!(level.parse("6").is_err());
+        assert!(level.parse("2.5").is_err());
+        let date = QueryParamCfg {
+            min: Some(QueryParamValueCfg::Text("2024-01-01".to_string())),
+            ..param("date", QueryParamType::Date)
+        };
+        assert!(date.parse("2024-11-08").is_ok());
+        assert!(date.parse("2023-12-31").is_err());
+        assert!(date.parse("'; DROP TABLE x").is_err());
+        let class = QueryParamCfg {
+            values: vec!["city".to_string(), "town".to_string()],
+            ..param("class", QueryParamType::Enum)
+        };
+        assert!(class.parse("town").is_ok());
+        assert!(class.parse("village").is_err());
+        let bbox = param("area", QueryParamType::Bbox);
+        assert_eq!(
+            bbox.parse("5.9,45.8,10.5,47.8").unwrap(),
+            ParamValue::Bbox([5.9, 45.8, 10.5, 47.8])
+        );
+        assert!(bbox.parse("10.5,45.8,5.9,47.8").is_err());
+
+        let mut filter = FilterParams::default();
+        assert_eq!(level.value(&filter).unwrap(), ParamValue::Int(3));
+        assert!(filter.validate(&[&level, &date]).is_err());
+        filter
+            .filters
+            .insert("date".to_string(), "2024-11-08".to_string());
+        assert!(filter.validate(&[&level, &date]).is_ok());
+        filter.filters.insert("level".to_string(), "0".to_string());
+        assert!(filter.validate(&[&level, &date]).is_err());
+    }
 }
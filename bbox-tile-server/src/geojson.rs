@@ -0,0 +1,139 @@
+//! GeoJSON output of vector tiles.
+
+use crate::datasource::mvt::json_value;
+use crate::datasource::TileSourceError;
+use crate::overzoom::map_coords;
+use geo_types::Coord;
+use geozero::mvt::{self, Message};
+use geozero::{ToGeo, ToJson};
+use serde_json::{json, Value};
+use tile_grid::{Tms, Xyz};
+
+/// Convert MVT tile into a FeatureCollection with a FeatureCollection per layer.
+///
+/// Coordinates are transformed into longitude/latitude. `layers` is an optional
+/// comma separated list of layer names.
+pub fn mvt_to_geojson(
+    data: &[u8],
+    tms: &Tms,
+    xyz: &Xyz,
+    layers: Option<&str>,
+) -> Result<Vec<u8>, TileSourceError> {
+    let tile = mvt::Tile::decode(data).map_err(|_| TileSourceError::MvtDecodeError)?;
+    let selected: Option<Vec<&str>> = layers.map(|names| names.split(',').map(str::trim).collect());
+    let bounds = tms.xy_bounds(xyz);
+    // Check for supported transformation
+    tms.lnglat(bounds.left, bounds.top, false)
+        .map_err(|_| TileSourceError::TransformError)?;
+    let layer_collections = tile
+        .layers
+        .iter()
+        .filter(|layer| {
+            selected
+                .as_ref()
+                .map_or(true, |names| names.contains(&layer.name.as_str()))
+        })
+        .map(|layer| {
+            let extent = f64::from(layer.extent.unwrap_or(4096));
+            let to_lnglat = |c: Coord| {
+                let x = bounds.left + c.x / extent * (bounds.right - bounds.left);
+                let y = bounds.top - c.y / extent * (bounds.top - bounds.bottom);
+                tms.lnglat(x, y, false)
+                    .map(|lnglat| Coord {
+                        x: lnglat.x,
+                        y: lnglat.y,
+                    })
+                    .unwrap_or(Coord { x, y })
+            };
+            let features = layer
+                .features
+                .iter()
+                .filter_map(|feature| {
+                    let geom = feature.to_geo().ok()?;
+                    let geometry: Value =
+                        serde_json::from_str(&map_coords(&geom, &to_lnglat).to_json().ok()?)
+                            .ok()?;
+                    let properties: serde_json::Map<String, Value> = feature
+                        .tags
+                        .chunks_exact(2)
+                        .filter_map(|tag| {
+                            let key = layer.keys.get(tag[0] as usize)?;
+                            let value = json_value(layer.values.get(tag[1] as usize)?)?;
+                            Some((key.clone(), value))
+                        })
+                        .collect();
+                    let mut feature_json = json!({
+                        "type": "Feature",
+                        "properties": properties,
+                        "geometry": geometry,
+                    });
+                    if let Some(id) = feature.id {
+                        feature_json["id"] = id.into();
+                    }
+                    Some(feature_json)
+                })
+                .collect::<Vec<_>>();
+            json!({
+                "type": "FeatureCollection",
+                "properties": {"layer": layer.name, "extent": extent},
+                "features": features,
+            })
+        })
+        .collect::<Vec<_>>();
+    let collection = json!({
+        "type": "FeatureCollection",
+        "properties": {"zoom": xyz.z, "x": xyz.x, "y": xyz.y},
+        "features": layer_collections,
+    });
+    serde_json::to_vec(&collection).map_err(|_| TileSourceError::GeoJsonEncodeError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datasource::mvt::zigzag;
+    use tile_grid::tms;
+
+    #[test]
+    fn mvt_geojson() {
+        let point = |x: i32, y: i32| mvt::tile::Feature {
+            id: Some(1),
+            r#type: Some(mvt::tile::GeomType::Point as i32),
+            geometry: vec![9, zigzag(x), zigzag(y)],
+            tags: vec![0, 0],
+            ..Default::default()
+        };
+        let layer = |name: &str| mvt::tile::Layer {
+            version: 2,
+            name: name.to_string(),
+            features: vec![point(2048, 2048)],
+            keys: vec!["name".to_string()],
+            values: vec![mvt::tile::Value {
+                string_value: Some("center".to_string()),
+                ..Default::default()
+            }],
+            extent: Some(4096),
+        };
+        let tile = mvt::Tile {
+            layers: vec![layer("places"), layer("labels")],
+        };
+        let tms = tms().lookup("WebMercatorQuad").unwrap();
+        let data = mvt_to_geojson(
+            &tile.encode_to_vec(),
+            &tms,
+            &Xyz::new(0, 0, 0),
+            Some("places"),
+        )
+        .unwrap();
+        let json: Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(json["features"].as_array().unwrap().len(), 1);
+        let layer = &json["features"][0];
+        assert_eq!(layer["properties"]["layer"], "places");
+        let feature = &layer["features"][0];
+        assert_eq!(feature["id"], 1);
+        assert_eq!(feature["properties"]["name"], "center");
+        let coords = feature["geometry"]["coordinates"].as_array().unwrap();
+        assert!(coords[0].as_f64().unwrap().abs() < 1e-6);
+        assert!(coords[1].as_f64().unwrap().abs() < 1e-6);
+    }
+}
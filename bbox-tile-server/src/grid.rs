@@ -0,0 +1,331 @@
+//! Custom grid definitions.
+
+use crate::config::GridCfg;
+use serde_json::{json, Value};
+use std::fs;
+
+/// Meters per degree at the equator
+const METERS_PER_DEGREE: f64 = 2.0 * std::f64::consts::PI * 6378137.0 / 360.0;
+/// Standardized rendering pixel size of 0.28 mm
+const PIXEL_SIZE: f64 = 0.00028;
+
+#[derive(thiserror::Error, Debug)]
+pub enum GridError {
+    #[error("Grid `{0}`: {1}")]
+    InvalidGrid(String, String),
+    #[error("Grid file `{0}`: {1}")]
+    FileError(String, std::io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+fn is_geographic_axis(axis: &str) -> bool {
+    matches!(
+        axis.to_lowercase().as_str(),
+        "lat" | "lon" | "latitude" | "longitude"
+    )
+}
+
+/// First axis is latitude or northing
+fn is_inverted_axis(axis: &str) -> bool {
+    matches!(
+        axis.to_lowercase().as_str(),
+        "lat" | "latitude" | "n" | "northing" | "y"
+    )
+}
+
+impl GridCfg {
+    /// Validated grid definition as OGC TileMatrixSet JSON
+    pub fn tms_json(&self) -> Result<Value, GridError> {
+        let json = if let Some(path) = self.abs_path() {
+            if self.id.is_some() || self.srid.is_some() || self.extent.is_some() {
+                return Err(GridError::InvalidGrid(
+                    path.display().to_string(),
+                    "`json` cannot be combined with an inline definition".to_string(),
+                ));
+            }
+            let content = fs::read_to_string(&path)
+                .map_err(|e| GridError::FileError(path.display().to_string(), e))?;
+            serde_json::from_str(&content)?
+        } else {
+            self.inline_tms_json()?
+        };
+        validate_tms(&json)?;
+        Ok(json)
+    }
+
+    fn inline_tms_json(&self) -> Result<Value, GridError> {
+        let Some(id) = &self.id else {
+            return Err(GridError::InvalidGrid(
+                "(inline)".to_string(),
+                "`json` or `id` required".to_string(),
+            ));
+        };
+        let err = |msg: &str| GridError::InvalidGrid(id.clone(), msg.to_string());
+        let srid = self.srid.ok_or_else(|| err("`srid` missing"))?;
+        let extent = self
+            .extent
+            .as_ref()
+            .ok_or_else(|| err("`extent` missing"))?;
+        if extent.minx >= extent.maxx || extent.miny >= extent.maxy {
+            return Err(err("invalid `extent`"));
+        }
+        let ordered_axes = self.ordered_axes.clone().unwrap_or_else(|| {
+            let axes = if srid == 4326 {
+                ["Lat", "Lon"]
+            } else {
+                ["E", "N"]
+            };
+            axes.map(String::from).to_vec()
+        });
+        if ordered_axes.len() != 2 {
+            return Err(err("`ordered_axes` must contain two axes"));
+        }
+        let meters_per_unit = if ordered_axes.iter().any(|axis| is_geographic_axis(axis)) {
+            METERS_PER_DEGREE
+        } else {
+            1.0
+        };
+        let tile_size = self.tile_size.map_or(256, u16::from);
+        let origin = self.origin.unwrap_or((extent.minx, extent.maxy));
+        let (width, height) = (extent.maxx - origin.0, origin.1 - extent.miny);
+        if width <= 0.0 || height <= 0.0 {
+            return Err(err("`origin` must be the upper left corner of `extent`"));
+        }
+        let resolutions = match (
+            self.resolutions.is_empty(),
+            self.scale_denominators.is_empty(),
+            self.zoom_levels,
+        ) {
+            (false, true, None) => self.resolutions.clone(),
+            (true, false, None) => self
+                .scale_denominators
+                .iter()
+                .map(|scale| scale * PIXEL_SIZE / meters_per_unit)
+                .collect(),
+            (true, true, Some(levels)) => {
+                let res0 = width.max(height) / f64::from(tile_size);
+                (0..levels)
+                    .map(|z| res0 / 2f64.powi(i32::from(z)))
+                    .collect()
+            }
+            _ => {
+                return Err(err(
+                    "one of `resolutions`, `scale_denominators` or `zoom_levels` required",
+                ))
+            }
+        };
+        // Point of origin in CRS axis order
+        let point_of_origin = if is_inverted_axis(&ordered_axes[0]) {
+            [origin.1, origin.0]
+        } else {
+            [origin.0, origin.1]
+        };
+        let matrix_size = |extent: f64, res: f64| {
+            ((extent / (res * f64::from(tile_size)) - 1e-9).ceil() as u64).max(1)
+        };
+        let tile_matrices = resolutions
+            .iter()
+            .enumerate()
+            .map(|(z, res)| {
+                json!({
+                    "id": z.to_string(),
+                    "scaleDenominator": res * meters_per_unit / PIXEL_SIZE,
+                    "cellSize": res,
+                    "pointOfOrigin": point_of_origin,
+                    "tileWidth": tile_size,
+                    "tileHeight": tile_size,
+                    "matrixWidth": matrix_size(width, *res),
+                    "matrixHeight": matrix_size(height, *res),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut tms = json!({
+            "id": id,
+            "crs": format!("http://www.opengis.net/def/crs/EPSG/0/{srid}"),
+            "orderedAxes": ordered_axes,
+            "tileMatrices": tile_matrices,
+        });
+        if let Some(title) = &self.title {
+            tms["title"] = json!(title);
+        }
+        Ok(tms)
+    }
+}
+
+/// Check resolutions, matrix sizes and CRS axis order of a TileMatrixSet
+fn validate_tms(tms: &Value) -> Result<(), GridError> {
+    let id = tms["id"].as_str().unwrap_or("(unknown)");
+    let err = |msg: String| GridError::InvalidGrid(id.to_string(), msg);
+    let matrices = tms["tileMatrices"]
+        .as_array()
+        .filter(|matrices| !matrices.is_empty())
+        .ok_or_else(|| err("no tile matrices defined".to_string()))?;
+    let mut previous: Option<(f64, u64, u64)> = None;
+    for matrix in matrices {
+        let matrix_id = matrix["id"].as_str().unwrap_or("?");
+        let cell_size = matrix["cellSize"]
+            .as_f64()
+            .filter(|res| *res > 0.0)
+            .ok_or_else(|| err(format!("invalid cell size of tile matrix `{matrix_id}`")))?;
+        let size = |key: &str| {
+            matrix[key]
+                .as_u64()
+                .filter(|size| *size > 0)
+                .ok_or_else(|| err(format!("invalid {key} of tile matrix `{matrix_id}`")))
+        };
+        let (width, height) = (size("matrixWidth")?, size("matrixHeight")?);
+        size("tileWidth")?;
+        size("tileHeight")?;
+        if let Some((prev_cell_size, prev_width, prev_height)) = previous {
+            if cell_size >= prev_cell_size {
+                return Err(err(format!(
+                    "resolutions not decreasing at tile matrix `{matrix_id}`"
+                )));
+            }
+            if width < prev_width || height < prev_height {
+                return Err(err(format!(
+                    "matrix size decreasing at tile matrix `{matrix_id}`"
+                )));
+            }
+        }
+        previous = Some((cell_size, width, height));
+    }
+    if let Some(axes) = tms.get("orderedAxes") {
+        let axes = axes
+            .as_array()
+            .filter(|axes| axes.len() == 2)
+            .and_then(|axes| axes.iter().map(Value::as_str).collect::<Option<Vec<_>>>())
+            .ok_or_else(|| err("`orderedAxes` must contain two axes".to_string()))?;
+        if axes[0].eq_ignore_ascii_case(axes[1]) {
+            return Err(err("duplicate axis in `orderedAxes`".to_string()));
+        }
+        let crs = tms["crs"].as_str().unwrap_or_default();
+        let expected = if crs.ends_with("/EPSG/0/4326") {
+            Some(["Lat", "Lon"])
+        } else if crs.ends_with("/OGC/1.3/CRS84") {
+            Some(["Lon", "Lat"])
+        } else {
+            None
+        };
+        if let Some(expected) = expected {
+            if !axes[0].eq_ignore_ascii_case(expected[0])
+                || !axes[1].eq_ignore_ascii_case(expected[1])
+            {
+                return Err(err(format!(
+                    "axis order of {crs} is {}, {}",
+                    expected[0], expected[1]
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ExtentCfg;
+    use ogcapi_types::tiles::TileMatrixSet;
+
+    fn lv95() -> GridCfg {
+        GridCfg {
+            id: Some("LV95".to_string()),
+            srid: Some(2056),
+            extent: Some(ExtentCfg {
+                minx: 2420000.0,
+                miny: 1030000.0,
+                maxx: 2900000.0,
+                maxy: 1350000.0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn inline_grid() {
+        let cfg = GridCfg {
+            resolutions: vec![4000.0, 2000.0, 1000.0],
+            ..lv95()
+        };
+        let json = cfg.tms_json().unwrap();
+        assert_eq!(json["orderedAxes"], json!(["E", "N"]));
+        assert_eq!(json["tileMatrices"][0]["matrixWidth"], 1);
+        assert_eq!(json["tileMatrices"][2]["matrixWidth"], 2);
+        assert_eq!(
+            json["tileMatrices"][0]["pointOfOrigin"],
+            json!([2420000.0, 1350000.0])
+        );
+        let tms: TileMatrixSet = serde_json::from_value(json).unwrap();
+        assert_eq!(tms.tile_matrices.len(), 3);
+
+        let cfg = GridCfg {
+            zoom_levels: Some(4),
+            ..lv95()
+        };
+        let json = cfg.tms_json().unwrap();
+        assert_eq!(json["tileMatrices"][0]["cellSize"], 1875.0);
+        assert_eq!(json["tileMatrices"][3]["matrixWidth"], 8);
+        assert_eq!(json["tileMatrices"][3]["matrixHeight"], 6);
+
+        let cfg = GridCfg {
+            scale_denominators: vec![5000000.0, 2500000.0],
+            ..lv95()
+        };
+        let json = cfg.tms_json().unwrap();
+        let cell_size = json["tileMatrices"][1]["cellSize"].as_f64().unwrap();
+        assert!((cell_size - 700.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn grid_validation() {
+        let cfg = GridCfg {
+            resolutions: vec![4000.0, 5000.0],
+            ..lv95()
+        };
+        assert!(cfg.tms_json().is_err());
+
+        let cfg = GridCfg {
+            resolutions: vec![4000.0],
+            zoom_levels: Some(4),
+            ..lv95()
+        };
+        assert!(cfg.tms_json().is_err());
+
+        let cfg = GridCfg {
+            srid: Some(4326),
+            ordered_axes: Some(vec!["Lon".to_string(), "Lat".to_string()]),
+            extent: Some(ExtentCfg {
+                minx: -180.0,
+                miny: -90.0,
+                maxx: 180.0,
+                maxy: 90.0,
+            }),
+            zoom_levels: Some(4),
+            ..lv95()
+        };
+        assert!(cfg.tms_json().is_err());
+
+        let cfg = GridCfg {
+            ordered_axes: None,
+            ..cfg
+        };
+        let json = cfg.tms_json().unwrap();
+        assert_eq!(
+            json["tileMatrices"][0]["pointOfOrigin"],
+            json!([90.0, -180.0])
+        );
+
+        let cfg = GridCfg {
+            json: Some(
+                concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/../assets/custom-grid-lv95.json"
+                )
+                .to_string(),
+            ),
+            ..Default::default()
+        };
+        assert!(cfg.tms_json().is_ok());
+    }
+}
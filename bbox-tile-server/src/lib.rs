@@ -1,12 +1,26 @@
+mod access;
 pub mod cli;
 pub mod config;
 pub mod config_t_rex;
+mod copy;
 pub mod datasource;
+mod discovery;
+mod empty_tile;
 mod endpoints;
 mod filter_params;
+mod geojson;
+mod grid;
 mod mbtiles_ds;
+mod metatile;
+pub mod metrics;
+mod overzoom;
+mod raster;
 pub mod seed;
+mod seed_jobs;
 pub mod service;
+mod stats;
 pub mod store;
+mod styles;
+mod wmts;
 
 pub use service::*;
@@ -2,7 +2,7 @@ use crate::config::MbtilesStoreCfg;
 use martin_mbtiles::{init_mbtiles_schema, MbtError, MbtResult, MbtType, Mbtiles, Metadata};
 use martin_tile_utils::{Encoding as TileEncoding, Format as TileFormat, TileInfo};
 use serde_json::json;
-use sqlx::{sqlite::SqliteConnectOptions, Connection, Pool, Row, Sqlite, SqlitePool};
+use sqlx::{sqlite::SqliteConnectOptions, Connection, Executor, Pool, Row, Sqlite, SqlitePool};
 use std::path::Path;
 use thiserror::Error;
 
@@ -171,6 +171,17 @@ impl MbtilesDatasource {
         self.mbtiles.get_metadata(&mut *conn).await
     }
 
+    /// Remove all tiles
+    pub async fn truncate(&self) -> Result<()> {
+        let sql = match self.layout {
+            MbtType::Flat => "DELETE FROM tiles",
+            MbtType::FlatWithHash => "DELETE FROM tiles_with_hash",
+            MbtType::Normalized { .. } => "DELETE FROM map; DELETE FROM images",
+        };
+        self.pool.execute(sql).await?;
+        Ok(())
+    }
+
     pub async fn get_tile(&self, z: u8, x: u32, y: u32) -> MbtResult<Option<Vec<u8>>> {
         let mut conn = self.pool.acquire().await?;
         self.mbtiles.get_tile(&mut *conn, z, x, y).await
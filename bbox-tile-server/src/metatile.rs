@@ -0,0 +1,159 @@
+//! Metatile rendering for raster sources.
+
+use crate::config::{ImageEncodingCfg, MetatileCfg};
+use crate::datasource::TileSourceError;
+use crate::raster::{encode_image, image_format};
+use crate::service::{QueryExtent, TmsExtensions};
+use bbox_core::Format;
+use std::num::NonZeroU16;
+use tile_grid::{BoundingBox, Tms, Xyz};
+
+/// Block of tiles rendered with a single map request
+pub struct Metatile {
+    /// Tiles in row-major order
+    pub tiles: Vec<Xyz>,
+    cols: u64,
+    /// Extent including buffer
+    pub extent_info: QueryExtent,
+    buffer: u32,
+    tile_width: u32,
+    tile_height: u32,
+}
+
+impl Metatile {
+    /// Upper left tile of metatile containing `xyz`
+    pub fn origin(xyz: &Xyz, size: u8) -> Xyz {
+        let size = u64::from(size.max(1));
+        Xyz::new(xyz.x / size * size, xyz.y / size * size, xyz.z)
+    }
+
+    /// Metatile containing `xyz`
+    pub fn new(tms: &Tms, xyz: &Xyz, cfg: &MetatileCfg) -> Result<Self, TileSourceError> {
+        let origin = Self::origin(xyz, cfg.size);
+        let tile_info = tms.xyz_extent(&origin)?;
+        let matrix = tms.matrix(xyz.z);
+        let matrix = matrix.as_ref();
+        let size = u64::from(cfg.size.max(1));
+        let cols = size.min(u64::from(matrix.matrix_width) - origin.x);
+        let rows = size.min(u64::from(matrix.matrix_height) - origin.y);
+        let tiles = (0..rows)
+            .flat_map(|row| {
+                (0..cols).map(move |col| Xyz::new(origin.x + col, origin.y + row, origin.z))
+            })
+            .collect::<Vec<_>>();
+
+        let upper_left = tile_info.extent;
+        let lower_right = tms.xy_bounds(&Xyz::new(
+            origin.x + cols - 1,
+            origin.y + rows - 1,
+            origin.z,
+        ));
+        let tile_width = u32::from(tile_info.tile_width.get());
+        let tile_height = u32::from(tile_info.tile_height.get());
+        let buffer = u32::from(cfg.buffer);
+        let res_x = (upper_left.right - upper_left.left) / f64::from(tile_width);
+        let res_y = (upper_left.top - upper_left.bottom) / f64::from(tile_height);
+        let extent = BoundingBox::new(
+            upper_left.left - f64::from(buffer) * res_x,
+            lower_right.bottom - f64::from(buffer) * res_y,
+            lower_right.right + f64::from(buffer) * res_x,
+            upper_left.top + f64::from(buffer) * res_y,
+        );
+        let width = u16::try_from(cols as u32 * tile_width + 2 * buffer)?;
+        let height = u16::try_from(rows as u32 * tile_height + 2 * buffer)?;
+        let extent_info = QueryExtent {
+            extent,
+            srid: tile_info.srid,
+            tile_width: NonZeroU16::new(width).ok_or(TileSourceError::TileXyzError)?,
+            tile_height: NonZeroU16::new(height).ok_or(TileSourceError::TileXyzError)?,
+        };
+        Ok(Metatile {
+            tiles,
+            cols,
+            extent_info,
+            buffer,
+            tile_width,
+            tile_height,
+        })
+    }
+
+    /// Split rendered metatile image into tiles
+    pub fn split(
+        &self,
+        data: &[u8],
+        format: &Format,
+        options: &ImageEncodingCfg,
+    ) -> Result<Vec<(Xyz, Vec<u8>)>, TileSourceError> {
+        let image_format = image_format(format)
+            .ok_or(TileSourceError::TileSourceTypeError("raster".to_string()))?;
+        let img = image::load_from_memory(data)?;
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(|(i, xyz)| {
+                let col = (i as u64 % self.cols) as u32;
+                let row = (i as u64 / self.cols) as u32;
+                let tile = img.crop_imm(
+                    self.buffer + col * self.tile_width,
+                    self.buffer + row * self.tile_height,
+                    self.tile_width,
+                    self.tile_height,
+                );
+                Ok((*xyz, encode_image(tile, image_format, options)?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, ImageFormat, Rgba};
+    use std::io::Cursor;
+    use tile_grid::tms;
+
+    #[test]
+    fn metatile_extent() {
+        let tms = tms().lookup("WebMercatorQuad").unwrap();
+        let cfg = MetatileCfg {
+            size: 4,
+            buffer: 16,
+        };
+        let metatile = Metatile::new(&tms, &Xyz::new(5, 6, 3), &cfg).unwrap();
+        assert_eq!(metatile.tiles.len(), 16);
+        assert_eq!(metatile.tiles[0], Xyz::new(4, 4, 3));
+        assert_eq!(metatile.tiles[15], Xyz::new(7, 7, 3));
+        assert_eq!(metatile.extent_info.tile_width.get(), 4 * 256 + 32);
+
+        // Metatile at zoom level 0 is clipped to the tile matrix
+        let metatile = Metatile::new(&tms, &Xyz::new(0, 0, 0), &cfg).unwrap();
+        assert_eq!(metatile.tiles, vec![Xyz::new(0, 0, 0)]);
+        assert_eq!(metatile.extent_info.tile_height.get(), 256 + 32);
+    }
+
+    #[test]
+    fn split_metatile() {
+        let tms = tms().lookup("WebMercatorQuad").unwrap();
+        let cfg = MetatileCfg { size: 2, buffer: 8 };
+        let metatile = Metatile::new(&tms, &Xyz::new(0, 0, 1), &cfg).unwrap();
+        // Left column blue, right column red
+        let img = ImageBuffer::from_fn(528, 528, |x, _| {
+            if x < 264 {
+                Rgba([0u8, 0, 255, 255])
+            } else {
+                Rgba([255, 0, 0, 255])
+            }
+        });
+        let mut data = Cursor::new(Vec::new());
+        img.write_to(&mut data, ImageFormat::Png).unwrap();
+        let tiles = metatile
+            .split(data.get_ref(), &Format::Png, &ImageEncodingCfg::default())
+            .unwrap();
+        assert_eq!(tiles.len(), 4);
+        let (xyz, tile) = &tiles[1];
+        assert_eq!(xyz, &Xyz::new(1, 0, 1));
+        let tile = image::load_from_memory(tile).unwrap().to_rgba8();
+        assert_eq!(tile.dimensions(), (256, 256));
+        assert_eq!(tile.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    }
+}
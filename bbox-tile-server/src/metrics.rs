@@ -0,0 +1,66 @@
+use once_cell::sync::OnceCell;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Registry};
+
+#[derive(Clone)]
+pub struct TileMetrics {
+    /// Tile requests by tileset, zoom level and HTTP status
+    pub requests_counter: IntCounterVec,
+    /// Tile cache lookups by tileset and result (`hit` or `miss`)
+    pub cache_counter: IntCounterVec,
+    /// Tile generation time of source requests
+    pub source_seconds: HistogramVec,
+    /// Size of delivered tiles
+    pub tile_size_bytes: HistogramVec,
+    /// Tiles generated by seeding
+    pub seeded_tiles_counter: IntCounterVec,
+}
+
+pub fn tile_metrics() -> &'static TileMetrics {
+    static METRICS: OnceCell<TileMetrics> = OnceCell::new();
+    METRICS.get_or_init(|| {
+        let opts = prometheus::opts!("requests_total", "Total number of tile requests")
+            .namespace("bbox_tiles");
+        let requests_counter = IntCounterVec::new(opts, &["tileset", "zoom", "status"]).unwrap();
+        let opts =
+            prometheus::opts!("cache_requests_total", "Tile cache lookups").namespace("bbox_tiles");
+        let cache_counter = IntCounterVec::new(opts, &["tileset", "result"]).unwrap();
+        let opts = HistogramOpts::new("source_seconds", "Tile generation time of source")
+            .namespace("bbox_tiles")
+            .buckets(vec![
+                0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+            ]);
+        let source_seconds = HistogramVec::new(opts, &["tileset"]).unwrap();
+        let opts = HistogramOpts::new("tile_size_bytes", "Size of delivered tiles")
+            .namespace("bbox_tiles")
+            .buckets(prometheus::exponential_buckets(256.0, 4.0, 8).unwrap());
+        let tile_size_bytes = HistogramVec::new(opts, &["tileset", "format"]).unwrap();
+        let opts = prometheus::opts!("seeded_tiles_total", "Tiles generated by seeding")
+            .namespace("bbox_tiles");
+        let seeded_tiles_counter = IntCounterVec::new(opts, &["tileset"]).unwrap();
+        TileMetrics {
+            requests_counter,
+            cache_counter,
+            source_seconds,
+            tile_size_bytes,
+            seeded_tiles_counter,
+        }
+    })
+}
+
+pub fn register_metrics(prometheus: &Registry, metrics: &TileMetrics) {
+    prometheus
+        .register(Box::new(metrics.requests_counter.clone()))
+        .unwrap();
+    prometheus
+        .register(Box::new(metrics.cache_counter.clone()))
+        .unwrap();
+    prometheus
+        .register(Box::new(metrics.source_seconds.clone()))
+        .unwrap();
+    prometheus
+        .register(Box::new(metrics.tile_size_bytes.clone()))
+        .unwrap();
+    prometheus
+        .register(Box::new(metrics.seeded_tiles_counter.clone()))
+        .unwrap();
+}
@@ -0,0 +1,381 @@
+//! Server-side overzooming of tiles above the source maxzoom.
+
+use crate::config::ImageEncodingCfg;
+use crate::datasource::TileSourceError;
+use crate::raster::{encode_image, image_format};
+use bbox_core::Format;
+use geo_types::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Point, Polygon,
+};
+use geozero::mvt::{self, Message};
+use geozero::{ToGeo, ToMvt};
+use image::{imageops::FilterType, ImageFormat};
+use tile_grid::{Tms, Xyz};
+
+/// Buffer around clipped vector tiles in units of a 4096 tile extent.
+const MVT_BUFFER: f64 = 64.0;
+
+/// Each tile of the zoom levels from `minzoom` to `maxzoom` is divided into 2x2 child tiles.
+///
+/// Overzooming derives tiles from their ancestor at `minzoom` and requires a quadtree grid.
+pub fn is_quadtree(tms: &Tms, minzoom: u8, maxzoom: u8) -> bool {
+    (minzoom..maxzoom).all(|zoom| {
+        let parent = tms.matrix(zoom);
+        let child = tms.matrix(zoom + 1);
+        let (parent, child) = (parent.as_ref(), child.as_ref());
+        (parent.cell_size / child.cell_size - 2.0).abs() < 1e-9
+            && parent.point_of_origin == child.point_of_origin
+            && parent.tile_width == child.tile_width
+            && parent.tile_height == child.tile_height
+    })
+}
+
+/// Derive a tile from its ancestor tile by clipping and rescaling.
+pub fn overzoom_tile(
+    data: &[u8],
+    format: &Format,
+    ancestor: &Xyz,
+    xyz: &Xyz,
+    options: &ImageEncodingCfg,
+) -> Result<Vec<u8>, TileSourceError> {
+    let scale = 1u64
+        .checked_shl(u32::from(xyz.z - ancestor.z))
+        .ok_or(TileSourceError::TileXyzError)?;
+    // Position of the requested tile within the ancestor tile
+    let col = xyz.x - ancestor.x * scale;
+    let row = xyz.y - ancestor.y * scale;
+    if *format == Format::Mvt {
+        return overzoom_mvt(data, scale, col, row);
+    }
+    match image_format(format) {
+        Some(image_format) => overzoom_image(data, image_format, scale, col, row, options),
+        None => Err(TileSourceError::OverzoomFormatError(
+            format.file_suffix().to_string(),
+        )),
+    }
+}
+
+fn overzoom_mvt(data: &[u8], scale: u64, col: u64, row: u64) -> Result<Vec<u8>, TileSourceError> {
+    let mut tile = mvt::Tile::decode(data).map_err(|_| TileSourceError::MvtDecodeError)?;
+    for layer in tile.layers.iter_mut() {
+        let extent = f64::from(layer.extent.unwrap_or(4096));
+        let buffer = MVT_BUFFER * extent / 4096.0;
+        let clip = ClipRect {
+            min: -buffer,
+            max: extent + buffer,
+        };
+        let (scale, dx, dy) = (scale as f64, col as f64 * extent, row as f64 * extent);
+        let transform = |c: Coord| Coord {
+            x: c.x * scale - dx,
+            y: c.y * scale - dy,
+        };
+        let features = std::mem::take(&mut layer.features);
+        for feature in features {
+            let Ok(geom) = feature.to_geo() else {
+                continue;
+            };
+            if let Some(geom) = clip.clip_geometry(&map_coords(&geom, &transform)) {
+                let mut clipped = geom.to_mvt_unscaled()?;
+                clipped.id = feature.id;
+                clipped.tags = feature.tags;
+                layer.features.push(clipped);
+            }
+        }
+    }
+    Ok(tile.encode_to_vec())
+}
+
+fn overzoom_image(
+    data: &[u8],
+    format: ImageFormat,
+    scale: u64,
+    col: u64,
+    row: u64,
+    options: &ImageEncodingCfg,
+) -> Result<Vec<u8>, TileSourceError> {
+    let img = image::load_from_memory_with_format(data, format)?;
+    let (width, height) = (img.width(), img.height());
+    let crop_width = (u64::from(width) / scale).max(1) as u32;
+    let crop_height = (u64::from(height) / scale).max(1) as u32;
+    let x = (col * u64::from(width) / scale) as u32;
+    let y = (row * u64::from(height) / scale) as u32;
+    let img = img.crop_imm(x, y, crop_width, crop_height).resize_exact(
+        width,
+        height,
+        FilterType::CatmullRom,
+    );
+    encode_image(img, format, options)
+}
+
+pub(crate) fn map_coords(geom: &Geometry, f: &impl Fn(Coord) -> Coord) -> Geometry {
+    let line = |l: &LineString| LineString::new(l.coords().map(|c| f(*c)).collect());
+    let polygon =
+        |p: &Polygon| Polygon::new(line(p.exterior()), p.interiors().iter().map(line).collect());
+    match geom {
+        Geometry::Point(p) => Point::from(f(p.0)).into(),
+        Geometry::MultiPoint(mp) => {
+            MultiPoint::new(mp.iter().map(|p| Point::from(f(p.0))).collect()).into()
+        }
+        Geometry::LineString(l) => line(l).into(),
+        Geometry::MultiLineString(ml) => MultiLineString::new(ml.iter().map(line).collect()).into(),
+        Geometry::Polygon(p) => polygon(p).into(),
+        Geometry::MultiPolygon(mp) => MultiPolygon::new(mp.iter().map(polygon).collect()).into(),
+        Geometry::GeometryCollection(gc) => {
+            GeometryCollection(gc.iter().map(|g| map_coords(g, f)).collect()).into()
+        }
+        // Not returned by the MVT reader
+        Geometry::Line(_) | Geometry::Rect(_) | Geometry::Triangle(_) => geom.clone(),
+    }
+}
+
+/// Square clipping area in tile coordinates
+struct ClipRect {
+    min: f64,
+    max: f64,
+}
+
+impl ClipRect {
+    fn contains(&self, c: &Coord) -> bool {
+        c.x >= self.min && c.x <= self.max && c.y >= self.min && c.y <= self.max
+    }
+    fn clip_geometry(&self, geom: &Geometry) -> Option<Geometry> {
+        match geom {
+            Geometry::Point(p) => self.contains(&p.0).then(|| geom.clone()),
+            Geometry::MultiPoint(mp) => {
+                let points: Vec<Point> =
+                    mp.iter().filter(|p| self.contains(&p.0)).cloned().collect();
+                (!points.is_empty()).then(|| MultiPoint::new(points).into())
+            }
+            Geometry::LineString(l) => self.clip_lines(std::slice::from_ref(l)),
+            Geometry::MultiLineString(ml) => self.clip_lines(&ml.0),
+            Geometry::Polygon(p) => self.clip_polygons(std::slice::from_ref(p)),
+            Geometry::MultiPolygon(mp) => self.clip_polygons(&mp.0),
+            Geometry::GeometryCollection(gc) => {
+                let parts: Vec<Geometry> =
+                    gc.iter().filter_map(|g| self.clip_geometry(g)).collect();
+                (!parts.is_empty()).then(|| GeometryCollection(parts).into())
+            }
+            Geometry::Line(_) | Geometry::Rect(_) | Geometry::Triangle(_) => None,
+        }
+    }
+    fn clip_lines(&self, lines: &[LineString]) -> Option<Geometry> {
+        let mut parts: Vec<LineString> = lines.iter().flat_map(|l| self.clip_line(l)).collect();
+        match parts.len() {
+            0 => None,
+            1 => parts.pop().map(Geometry::from),
+            _ => Some(MultiLineString::new(parts).into()),
+        }
+    }
+    /// Clip line string segment by segment, splitting it when leaving the clip area
+    fn clip_line(&self, line: &LineString) -> Vec<LineString> {
+        let mut parts = Vec::new();
+        let mut current: Vec<Coord> = Vec::new();
+        for segment in line.lines() {
+            if let Some((start, end)) = self.clip_segment(segment.start, segment.end) {
+                if current.last() != Some(&start) {
+                    if current.len() > 1 {
+                        parts.push(LineString::new(std::mem::take(&mut current)));
+                    }
+                    current = vec![start];
+                }
+                current.push(end);
+            }
+        }
+        if current.len() > 1 {
+            parts.push(LineString::new(current));
+        }
+        parts
+    }
+    /// Liang-Barsky segment clipping
+    fn clip_segment(&self, a: Coord, b: Coord) -> Option<(Coord, Coord)> {
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let (mut t0, mut t1) = (0.0_f64, 1.0_f64);
+        for (p, q) in [
+            (-dx, a.x - self.min),
+            (dx, self.max - a.x),
+            (-dy, a.y - self.min),
+            (dy, self.max - a.y),
+        ] {
+            if p == 0.0 {
+                if q < 0.0 {
+                    return None;
+                }
+            } else {
+                let r = q / p;
+                if p < 0.0 {
+                    if r > t1 {
+                        return None;
+                    }
+                    t0 = t0.max(r);
+                } else {
+                    if r < t0 {
+                        return None;
+                    }
+                    t1 = t1.min(r);
+                }
+            }
+        }
+        Some((
+            Coord {
+                x: a.x + t0 * dx,
+                y: a.y + t0 * dy,
+            },
+            Coord {
+                x: a.x + t1 * dx,
+                y: a.y + t1 * dy,
+            },
+        ))
+    }
+    fn clip_polygons(&self, polygons: &[Polygon]) -> Option<Geometry> {
+        let mut parts: Vec<Polygon> = polygons
+            .iter()
+            .filter_map(|p| {
+                let exterior = self.clip_ring(p.exterior())?;
+                let interiors = p
+                    .interiors()
+                    .iter()
+                    .filter_map(|r| self.clip_ring(r))
+                    .collect();
+                Some(Polygon::new(exterior, interiors))
+            })
+            .collect();
+        match parts.len() {
+            0 => None,
+            1 => parts.pop().map(Geometry::from),
+            _ => Some(MultiPolygon::new(parts).into()),
+        }
+    }
+    /// Sutherland-Hodgman ring clipping
+    fn clip_ring(&self, ring: &LineString) -> Option<LineString> {
+        let mut points = ring.0.clone();
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        for edge in 0..4 {
+            let input = std::mem::take(&mut points);
+            let mut prev = *input.last()?;
+            for cur in input {
+                match (self.inside(edge, &prev), self.inside(edge, &cur)) {
+                    (true, true) => points.push(cur),
+                    (false, true) => {
+                        points.push(self.intersection(edge, prev, cur));
+                        points.push(cur);
+                    }
+                    (true, false) => points.push(self.intersection(edge, prev, cur)),
+                    (false, false) => {}
+                }
+                prev = cur;
+            }
+        }
+        if points.len() < 3 {
+            return None;
+        }
+        points.push(points[0]);
+        Some(LineString::new(points))
+    }
+    fn inside(&self, edge: usize, c: &Coord) -> bool {
+        match edge {
+            0 => c.x >= self.min,
+            1 => c.x <= self.max,
+            2 => c.y >= self.min,
+            _ => c.y <= self.max,
+        }
+    }
+    /// Intersection of segment with clip edge
+    fn intersection(&self, edge: usize, a: Coord, b: Coord) -> Coord {
+        match edge {
+            0 | 1 => {
+                let x = if edge == 0 { self.min } else { self.max };
+                let t = (x - a.x) / (b.x - a.x);
+                Coord {
+                    x,
+                    y: a.y + t * (b.y - a.y),
+                }
+            }
+            _ => {
+                let y = if edge == 2 { self.min } else { self.max };
+                let t = (y - a.y) / (b.y - a.y);
+                Coord {
+                    x: a.x + t * (b.x - a.x),
+                    y,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{line_string, polygon};
+    use image::{ImageBuffer, Rgba};
+    use std::io::Cursor;
+
+    #[test]
+    fn quadtree_grids() {
+        let tms = tile_grid::tms().lookup("WebMercatorQuad").unwrap();
+        assert!(is_quadtree(&tms, 0, 24));
+        let lv95: ogcapi_types::tiles::TileMatrixSet = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../assets/custom-grid-lv95.json"
+        )))
+        .unwrap();
+        let mut grids = tile_grid::tms().clone();
+        grids.register(vec![lv95], true).unwrap();
+        let lv95 = grids.lookup("LV95").unwrap();
+        assert!(!is_quadtree(&lv95, 10, 12));
+    }
+
+    #[test]
+    fn clip_geometries() {
+        let clip = ClipRect {
+            min: 0.0,
+            max: 10.0,
+        };
+        let line: Geometry = line_string![(x: -5.0, y: 5.0), (x: 15.0, y: 5.0)].into();
+        assert_eq!(
+            clip.clip_geometry(&line),
+            Some(line_string![(x: 0.0, y: 5.0), (x: 10.0, y: 5.0)].into())
+        );
+        let outside: Geometry = line_string![(x: 20.0, y: 5.0), (x: 25.0, y: 5.0)].into();
+        assert_eq!(clip.clip_geometry(&outside), None);
+        let poly: Geometry =
+            polygon![(x: 5.0, y: 5.0), (x: 15.0, y: 5.0), (x: 15.0, y: 15.0), (x: 5.0, y: 15.0)]
+                .into();
+        assert_eq!(
+            clip.clip_geometry(&poly),
+            Some(
+                polygon![(x: 5.0, y: 10.0), (x: 5.0, y: 5.0), (x: 10.0, y: 5.0), (x: 10.0, y: 10.0)]
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn overzoom_raster() {
+        // Left half red, right half blue
+        let img = ImageBuffer::from_fn(256, 256, |x, _| {
+            if x < 128 {
+                Rgba([255u8, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        let mut data = Cursor::new(Vec::new());
+        img.write_to(&mut data, ImageFormat::Png).unwrap();
+        let data = overzoom_tile(
+            data.get_ref(),
+            &Format::Png,
+            &Xyz::new(0, 0, 0),
+            &Xyz::new(1, 1, 1),
+            &ImageEncodingCfg::default(),
+        )
+        .unwrap();
+        let img = image::load_from_memory_with_format(&data, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (256, 256));
+        assert_eq!(img.get_pixel(128, 128), &Rgba([0, 0, 255, 255]));
+    }
+}
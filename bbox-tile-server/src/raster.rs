@@ -0,0 +1,166 @@
+//! Raster tile image processing.
+
+use crate::config::ImageEncodingCfg;
+use crate::datasource::TileSourceError;
+use bbox_core::Format;
+use color_quant::NeuQuant;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+
+const DEFAULT_JPEG_QUALITY: u8 = 85;
+
+/// Image format of raster tile format
+pub fn image_format(format: &Format) -> Option<ImageFormat> {
+    match format {
+        Format::Png | Format::Png8 => Some(ImageFormat::Png),
+        Format::Jpeg => Some(ImageFormat::Jpeg),
+        Format::Webp => Some(ImageFormat::WebP),
+        Format::Gif | Format::Mvt | Format::Json => None,
+    }
+}
+
+/// Encode image in tile format
+pub fn encode_image(
+    img: DynamicImage,
+    format: ImageFormat,
+    options: &ImageEncodingCfg,
+) -> Result<Vec<u8>, TileSourceError> {
+    let mut buf = Cursor::new(Vec::new());
+    match format {
+        ImageFormat::Png if options.png8 => return encode_png8(&img),
+        ImageFormat::Jpeg => {
+            let quality = options
+                .jpeg_quality
+                .unwrap_or(DEFAULT_JPEG_QUALITY)
+                .clamp(1, 100);
+            let encoder = JpegEncoder::new_with_quality(&mut buf, quality);
+            // JPEG encoder doesn't support an alpha channel
+            DynamicImage::from(img.into_rgb8()).write_with_encoder(encoder)?;
+        }
+        ImageFormat::WebP => {
+            // Lossless only, no quality option
+            let encoder = WebPEncoder::new_lossless(&mut buf);
+            img.write_with_encoder(encoder)?;
+        }
+        _ => img.write_to(&mut buf, format)?,
+    }
+    Ok(buf.into_inner())
+}
+
+/// Convert raster tile into `format`
+///
+/// Returns `None` if the tile is already encoded as requested.
+pub fn transcode_image(
+    data: &[u8],
+    format: &Format,
+    options: &ImageEncodingCfg,
+) -> Result<Option<Vec<u8>>, TileSourceError> {
+    let Some(target) = image_format(format) else {
+        return Ok(None);
+    };
+    let png8 = *format == Format::Png8 || (*format == Format::Png && options.png8);
+    if image::guess_format(data).ok() == Some(target)
+        && (target != ImageFormat::Png || !png8 || is_palette_png(data))
+    {
+        return Ok(None);
+    }
+    let img = image::load_from_memory(data)?;
+    if png8 {
+        return encode_png8(&img).map(Some);
+    }
+    encode_image(img, target, options).map(Some)
+}
+
+/// Quantize image to a 8-bit palette PNG
+fn encode_png8(img: &DynamicImage) -> Result<Vec<u8>, TileSourceError> {
+    let rgba = img.to_rgba8();
+    let quant = NeuQuant::new(10, 256, rgba.as_raw());
+    let indices: Vec<u8> = rgba
+        .pixels()
+        .map(|pixel| quant.index_of(&pixel.0) as u8)
+        .collect();
+    let color_map = quant.color_map_rgba();
+    let palette: Vec<u8> = color_map
+        .chunks_exact(4)
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    let transparency: Vec<u8> = color_map.chunks_exact(4).map(|c| c[3]).collect();
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, rgba.width(), rgba.height());
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(palette);
+    encoder.set_trns(transparency);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&indices)?;
+    writer.finish()?;
+    Ok(buf)
+}
+
+/// Check PNG color type in IHDR chunk
+fn is_palette_png(data: &[u8]) -> bool {
+    const COLOR_TYPE_OFFSET: usize = 25;
+    const COLOR_TYPE_INDEXED: u8 = 3;
+    data.get(COLOR_TYPE_OFFSET) == Some(&COLOR_TYPE_INDEXED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn png_tile() -> Vec<u8> {
+        let img = ImageBuffer::from_fn(256, 256, |x, y| Rgba([x as u8, y as u8, 128, 255]));
+        let mut data = Cursor::new(Vec::new());
+        img.write_to(&mut data, ImageFormat::Png).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn transcode_formats() {
+        let png = png_tile();
+        let options = ImageEncodingCfg::default();
+        assert!(transcode_image(&png, &Format::Png, &options)
+            .unwrap()
+            .is_none());
+        let jpeg = transcode_image(&png, &Format::Jpeg, &options)
+            .unwrap()
+            .unwrap();
+        assert_eq!(image::guess_format(&jpeg).unwrap(), ImageFormat::Jpeg);
+        let webp = transcode_image(&jpeg, &Format::Webp, &options)
+            .unwrap()
+            .unwrap();
+        assert_eq!(image::guess_format(&webp).unwrap(), ImageFormat::WebP);
+    }
+
+    #[test]
+    fn quantize_png() {
+        let png = png_tile();
+        let options = ImageEncodingCfg {
+            png8: true,
+            ..Default::default()
+        };
+        let png8 = transcode_image(&png, &Format::Png, &options)
+            .unwrap()
+            .unwrap();
+        assert!(is_palette_png(&png8));
+        let img = image::load_from_memory(&png8).unwrap();
+        assert_eq!((img.width(), img.height()), (256, 256));
+        // Palette PNGs are not quantized again
+        assert!(transcode_image(&png8, &Format::Png, &options)
+            .unwrap()
+            .is_none());
+
+        // Requested 8-bit PNG format
+        let options = ImageEncodingCfg::default();
+        let png8 = transcode_image(&png, &Format::Png8, &options)
+            .unwrap()
+            .unwrap();
+        assert!(is_palette_png(&png8));
+        assert!(transcode_image(&png8, &Format::Png8, &options)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -1,20 +1,25 @@
 use crate::cli::*;
 use crate::config::TileStoreCfg;
+use crate::empty_tile::is_empty_tile;
 use crate::filter_params::FilterParams;
+use crate::metatile::Metatile;
+use crate::metrics::tile_metrics;
 use crate::service::{ServiceError, TileService};
-use crate::store::{s3putfiles, CacheLayout, TileWriter};
+use crate::store::{s3putfiles, CacheLayout, TileData, TileWriter};
 use indicatif::{ProgressBar, ProgressStyle};
-use log::info;
-use pumps::{Concurrency, Pump};
+use log::{info, warn};
+use pumps::{Concurrency, Pipeline, Pump};
+use std::collections::HashSet;
 use std::path::PathBuf;
-use std::sync::Arc;
-use tile_grid::{BoundingBox, TileIterator, Xyz};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tile_grid::{BoundingBox, TileIterator};
 use tokio::{
     sync::mpsc::{self, Receiver},
     task::JoinHandle,
 };
 
-fn progress_bar() -> ProgressBar {
+pub(crate) fn progress_bar() -> ProgressBar {
     let progress = ProgressBar::new_spinner();
     progress.set_style(
         ProgressStyle::default_spinner()
@@ -23,6 +28,143 @@ fn progress_bar() -> ProgressBar {
     progress
 }
 
+/// Parse extent argument `minx,miny,maxx,maxy`
+pub(crate) fn parse_extent(extent: &Option<String>) -> anyhow::Result<Option<BoundingBox>> {
+    let Some(numlist) = extent else {
+        return Ok(None); // tms.xy_bbox()
+    };
+    let Ok(arr) = numlist
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<Vec<f64>, _>>()
+    else {
+        anyhow::bail!("Error parsing 'extent' as list of float values");
+    };
+    if arr.len() != 4 {
+        anyhow::bail!("Invalid extent (minx,miny,maxx,maxy)");
+    }
+    Ok(Some(BoundingBox::new(arr[0], arr[1], arr[2], arr[3])))
+}
+
+/// First error of a tile pipeline, stopping the processing of further tiles
+#[derive(Clone, Default)]
+pub(crate) struct PipelineError(Arc<Mutex<Option<String>>>);
+
+impl PipelineError {
+    pub fn set(&self, error: String) {
+        let mut first = self.0.lock().unwrap();
+        if first.is_none() {
+            *first = Some(error);
+        }
+    }
+    pub fn is_set(&self) -> bool {
+        let first = self.0.lock().unwrap();
+        first.is_some()
+    }
+    pub fn take(&self) -> Option<String> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// Pipeline stage writing batches of tiles into a tile store
+struct TileBatchWriterPump {
+    writer: Arc<Box<dyn TileWriter>>,
+    error: PipelineError,
+}
+
+impl Pump<Vec<Vec<TileData>>, ()> for TileBatchWriterPump {
+    fn spawn(
+        mut self,
+        mut input_receiver: Receiver<Vec<Vec<TileData>>>,
+    ) -> (Receiver<()>, JoinHandle<()>) {
+        let (output_sender, output_receiver) = mpsc::channel(1);
+
+        let h = tokio::spawn(async move {
+            let writer = Arc::get_mut(&mut self.writer).unwrap();
+            while let Some(batch) = input_receiver.recv().await {
+                let batch = batch.into_iter().flatten().collect::<Vec<_>>();
+                if let Err(e) = writer.put_tiles(&batch).await {
+                    warn!("Writing tiles failed: {e}");
+                    self.error.set(format!("writing tiles: {e}"));
+                }
+                if let Err(_e) = output_sender.send(()).await {
+                    break;
+                }
+            }
+            if let Err(e) = writer.finalize() {
+                warn!("Finalizing tile store failed: {e}");
+                self.error.set(format!("finalizing tile store: {e}"));
+            }
+        });
+
+        (output_receiver, h)
+    }
+}
+
+/// Append tile store writer stage to a pipeline of tiles
+///
+/// Write errors are recorded in `error`.
+pub(crate) fn write_tiles(
+    pipeline: Pipeline<Vec<TileData>>,
+    store_cfg: &TileStoreCfg,
+    tile_writer: Arc<Box<dyn TileWriter>>,
+    tiles_per_request: usize,
+    threads: usize,
+    tasks: Option<usize>,
+    error: PipelineError,
+) -> Pipeline<()> {
+    match store_cfg {
+        TileStoreCfg::Files(_cfg) => pipeline.map(
+            move |tiles| put_tiles(tile_writer.clone(), tiles, error.clone()),
+            Concurrency::concurrent_unordered(threads),
+        ),
+        TileStoreCfg::S3(cfg) => {
+            info!("Writing tiles to {}", &cfg.path);
+            let s3_writer_thread_count = tasks.unwrap_or(256);
+            pipeline.map(
+                move |tiles| put_tiles(tile_writer.clone(), tiles, error.clone()),
+                Concurrency::concurrent_unordered(s3_writer_thread_count),
+            )
+        }
+        TileStoreCfg::Mbtiles(_) => {
+            let batch_size = 200; // For MBTiles, create the largest prepared statement supported by SQLite (999 parameters)
+            pipeline
+                .batch((batch_size / tiles_per_request).max(1))
+                .pump(TileBatchWriterPump {
+                    writer: tile_writer,
+                    error,
+                })
+        }
+        TileStoreCfg::Pmtiles(_) => {
+            pipeline
+                .batch((50 / tiles_per_request).max(1))
+                .pump(TileBatchWriterPump {
+                    writer: tile_writer,
+                    error,
+                })
+        }
+        TileStoreCfg::NoStore => pipeline.map(|_| async {}, Concurrency::serial()),
+    }
+}
+
+async fn put_tiles(
+    tile_writer: Arc<Box<dyn TileWriter>>,
+    tiles: Vec<TileData>,
+    error: PipelineError,
+) {
+    for TileData { xyz, data, empty } in tiles {
+        let result = if empty {
+            tile_writer.put_empty_tile(&xyz, data).await
+        } else {
+            tile_writer.put_tile(&xyz, data).await
+        };
+        if let Err(e) = result {
+            warn!("Writing tile {xyz:?} failed: {e}");
+            error.set(format!("writing tile {xyz:?}: {e}"));
+        }
+    }
+}
+
 /*
 
 # Tile seeder workflows
@@ -55,7 +197,17 @@ By-Feature (https://github.com/onthegomap/planetiler/blob/main/ARCHITECTURE.md):
 
 impl TileService {
     pub async fn seed_by_grid(&self, args: &SeedArgs) -> anyhow::Result<()> {
-        let progress = progress_bar();
+        self.seed_with_progress(args, progress_bar(), Arc::new(AtomicBool::new(false)))
+            .await
+    }
+
+    /// Seed tiles reporting to `progress`, until finished or `cancelled` is set
+    pub(crate) async fn seed_with_progress(
+        &self,
+        args: &SeedArgs,
+        progress: ProgressBar,
+        cancelled: Arc<AtomicBool>,
+    ) -> anyhow::Result<()> {
         let progress_main = progress.clone();
 
         let tileset = self
@@ -73,40 +225,44 @@ impl TileService {
         );
         let format = *tileset.tile_format();
 
-        let bbox = if let Some(numlist) = &args.extent {
-            let arr: Vec<f64> = numlist
-                .split(',')
-                .map(|v| {
-                    v.parse()
-                        .expect("Error parsing 'extent' as list of float values")
-                })
-                .collect();
-            if arr.len() != 4 {
-                anyhow::bail!("Invalid extent (minx,miny,maxx,maxy)");
+        // Tiles outside of the tileset zoom limits and data extent are skipped
+        let grid = tileset.tileset_grid(tms.id())?;
+        let bbox = match (parse_extent(&args.extent)?, &grid.extent) {
+            (Some(bbox), Some(extent)) => {
+                let bbox = BoundingBox::new(
+                    bbox.left.max(extent.left),
+                    bbox.bottom.max(extent.bottom),
+                    bbox.right.min(extent.right),
+                    bbox.top.min(extent.top),
+                );
+                if bbox.left >= bbox.right || bbox.bottom >= bbox.top {
+                    anyhow::bail!("Extent outside of tileset extent");
+                }
+                Some(bbox)
             }
-            Some(BoundingBox::new(arr[0], arr[1], arr[2], arr[3]))
-        } else {
-            None // tms.xy_bbox()
+            (bbox, extent) => bbox.or(extent.clone()),
         };
 
         // Number of worker threads (size >= #cores).
         let threads = args.threads.unwrap_or(num_cpus::get());
 
-        let minzoom = args.minzoom.unwrap_or(0);
-        let maxzoom = args.maxzoom.unwrap_or(tms.maxzoom());
+        let minzoom = args.minzoom.unwrap_or(0).max(grid.minzoom);
+        // Overzoomed tiles are derived on request and not seeded
+        let maxzoom = args.maxzoom.unwrap_or(grid.maxzoom).min(grid.maxzoom);
         let griditer: Box<dyn TileIterator + Send> = if let Some(bbox) = bbox {
             Box::new(tms.xyz_iterator(&bbox, minzoom, maxzoom))
         } else {
             Box::new(tms.hilbert_iterator(minzoom, maxzoom))
         };
 
+        let metatile_size = tileset.metatile_size();
         let ts = tileset.clone();
         let Some(cache_cfg) = &ts.cache_config() else {
             return Err(
                 ServiceError::TilesetNotFound("Cache configuration not found".to_string()).into(),
             );
         };
-        let Some(tile_store) = tileset.tile_store else {
+        let Some(tile_store) = tileset.tile_store(&tms) else {
             return Err(ServiceError::TilesetNotFound(
                 "Tile store configuration not found".to_string(),
             )
@@ -127,104 +283,102 @@ impl TileService {
             TileStoreCfg::Pmtiles { .. } => Concurrency::serial(),
             _ => Concurrency::concurrent_unordered(threads),
         };
-        let iter = griditer.inspect(move |xyz| {
-            let path = CacheLayout::Zxy.path_string(&PathBuf::new(), xyz, &format);
-            progress.set_message(path.clone());
-            progress.inc(1);
-        });
-        let pipeline = pumps::Pipeline::from_iter(iter)
+        let empty_count = Arc::new(AtomicU64::new(0));
+        let empty_count_main = empty_count.clone();
+        let pipeline_error = PipelineError::default();
+        let pipeline_error_main = pipeline_error.clone();
+        let iter_error = pipeline_error.clone();
+        // Request each metatile only once
+        let mut metatiles = HashSet::new();
+        let mut metatile_zoom = None;
+        let iter = griditer
+            .take_while(move |_| !cancelled.load(Ordering::Relaxed) && !iter_error.is_set())
+            .inspect(move |xyz| {
+                let path = CacheLayout::Zxy.path_string(&PathBuf::new(), xyz, &format);
+                progress.set_message(path.clone());
+                progress.inc(1);
+            })
+            .filter(move |xyz| {
+                let Some(size) = metatile_size else {
+                    return true;
+                };
+                if metatile_zoom != Some(xyz.z) {
+                    metatiles.clear();
+                    metatile_zoom = Some(xyz.z);
+                }
+                let origin = Metatile::origin(xyz, size);
+                metatiles.insert((origin.x, origin.y))
+            });
+        let pipeline = Pipeline::from_iter(iter)
             .map(
                 move |xyz| {
                     let tileset = tileset_arc.clone();
                     let tms = tms.clone(); // TODO: tileset.default_grid(xyz.z)
                     let filter = FilterParams::default();
                     let compression = compression.clone();
+                    let empty_count = empty_count.clone();
+                    let pipeline_error = pipeline_error.clone();
                     async move {
-                        let tile = tileset
-                            .read_tile(&tms, &xyz, &filter, &format, compression)
+                        let tiles = match tileset
+                            .read_metatile(&tms, &xyz, &filter, &format, compression.clone())
                             .await
-                            .unwrap();
-                        (xyz, tile)
+                        {
+                            Ok(tiles) => tiles,
+                            Err(e) => {
+                                warn!("Seeding tile {xyz:?} failed: {e}");
+                                pipeline_error.set(format!("tile {xyz:?}: {e}"));
+                                return Vec::new();
+                            }
+                        };
+                        tile_metrics()
+                            .seeded_tiles_counter
+                            .with_label_values(&[&tileset.name])
+                            .inc_by(tiles.len() as u64);
+                        tiles
+                            .into_iter()
+                            .map(|(xyz, data)| {
+                                let empty = is_empty_tile(&data, &format, &compression);
+                                if empty {
+                                    empty_count.fetch_add(1, Ordering::Relaxed);
+                                }
+                                TileData { xyz, data, empty }
+                            })
+                            .collect()
                     }
                 },
                 read_concurrency,
             )
             .backpressure(100);
 
-        pub struct TileBatchWriterPump {
-            writer: Arc<Box<dyn TileWriter>>,
-        }
-
-        impl Pump<Vec<(Xyz, Vec<u8>)>, ()> for TileBatchWriterPump {
-            fn spawn(
-                mut self,
-                mut input_receiver: Receiver<Vec<(Xyz, Vec<u8>)>>,
-            ) -> (Receiver<()>, JoinHandle<()>) {
-                let (output_sender, output_receiver) = mpsc::channel(1);
-
-                let h = tokio::spawn(async move {
-                    let writer = Arc::get_mut(&mut self.writer).unwrap();
-                    while let Some(batch) = input_receiver.recv().await {
-                        let batch = batch
-                            .into_iter()
-                            .map(|(xyz, tile)| (xyz.z, xyz.x as u32, xyz.y as u32, tile))
-                            .collect::<Vec<_>>();
-                        let _ = writer.put_tiles(&batch).await;
-                        if let Err(_e) = output_sender.send(()).await {
-                            break;
-                        }
-                    }
-                    let _ = writer.finalize();
-                });
-
-                (output_receiver, h)
-            }
-        }
-
-        let pipeline = match cache_cfg {
-            TileStoreCfg::Files(_cfg) => pipeline.map(
-                move |(xyz, tile)| {
-                    let tile_writer = tile_writer.clone(); // TODO: init once per thread
-                    async move {
-                        let _ = tile_writer.put_tile(&xyz, tile).await;
-                    }
-                },
-                Concurrency::concurrent_unordered(threads),
-            ),
-            TileStoreCfg::S3(cfg) => {
-                info!("Writing tiles to {}", &cfg.path);
-                let s3_writer_thread_count = args.tasks.unwrap_or(256);
-                pipeline.map(
-                    move |(xyz, tile)| {
-                        let s3_writer = tile_writer.clone(); // TODO: init once per thread
-                        async move {
-                            let _ = s3_writer.put_tile(&xyz, tile).await;
-                        }
-                    },
-                    Concurrency::concurrent_unordered(s3_writer_thread_count),
-                )
-            }
-            TileStoreCfg::Mbtiles(_) => {
-                let batch_size = 200; // For MBTiles, create the largest prepared statement supported by SQLite (999 parameters)
-                pipeline.batch(batch_size).pump(TileBatchWriterPump {
-                    writer: tile_writer,
-                })
-            }
-            TileStoreCfg::Pmtiles(_) => pipeline.batch(50).pump(TileBatchWriterPump {
-                writer: tile_writer,
-            }),
-            TileStoreCfg::NoStore => pipeline.map(|_| async {}, Concurrency::serial()),
-        };
+        let tiles_per_request = metatile_size
+            .map(|size| usize::from(size).pow(2))
+            .unwrap_or(1);
+        let pipeline = write_tiles(
+            pipeline,
+            cache_cfg,
+            tile_writer,
+            tiles_per_request,
+            threads,
+            args.tasks,
+            pipeline_error_main.clone(),
+        );
 
         let (mut output_receiver, _join_handle) = pipeline.build();
         while let Some(_output) = output_receiver.recv().await {}
 
+        if let Some(error) = pipeline_error_main.take() {
+            progress_main.abandon_with_message(format!("Seeding failed: {error}"));
+            anyhow::bail!("Seeding failed at {error}");
+        }
         progress_main.set_style(
             ProgressStyle::default_spinner().template("{elapsed_precise} ({per_sec}) {msg}"),
         );
         let cnt = progress_main.position() + 1;
         let elapsed = progress_main.elapsed().as_millis() as f64 / 1000.0;
-        progress_main.finish_with_message(format!("{cnt} tiles generated in {elapsed:.2}s"));
+        let empty = empty_count_main.load(Ordering::Relaxed);
+        progress_main.finish_with_message(format!(
+            "{cnt} tiles generated in {elapsed:.2}s ({empty} empty tiles)"
+        ));
 
         Ok(())
     }
@@ -0,0 +1,294 @@
+//! Seed jobs running within the tile server.
+
+use crate::cli::SeedArgs;
+use crate::service::TileService;
+use indicatif::ProgressBar;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+/// Seed job parameters
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SeedJobParams {
+    pub tileset: String,
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    /// Tile matrix set id (Default: first grid of tileset)
+    pub tms: Option<String>,
+    /// Extent `minx,miny,maxx,maxy` (in grid reference system)
+    pub extent: Option<String>,
+    /// Number of concurrent tile requests (Default: number of logical cores)
+    pub threads: Option<usize>,
+}
+
+impl SeedJobParams {
+    fn seed_args(&self) -> SeedArgs {
+        SeedArgs {
+            tileset: self.tileset.clone(),
+            minzoom: self.minzoom,
+            maxzoom: self.maxzoom,
+            tms: self.tms.clone(),
+            extent: self.extent.clone(),
+            tile_path: None,
+            s3_path: None,
+            mb_path: None,
+            pm_path: None,
+            no_store: false,
+            threads: self.threads,
+            tasks: None,
+            overwrite: None,
+            file_or_url: None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+pub struct SeedJob {
+    pub id: u64,
+    params: SeedJobParams,
+    progress: ProgressBar,
+    cancelled: Arc<AtomicBool>,
+    state: Mutex<JobState>,
+}
+
+struct JobState {
+    status: JobStatus,
+    error: Option<String>,
+    /// Duration of completed job
+    elapsed: Option<Duration>,
+}
+
+/// Seed job status for API responses
+#[derive(Serialize, Debug)]
+pub struct SeedJobInfo {
+    pub id: u64,
+    #[serde(flatten)]
+    pub params: SeedJobParams,
+    pub status: JobStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Number of processed tiles
+    pub tiles: u64,
+    pub elapsed_secs: f64,
+    pub tiles_per_sec: f64,
+}
+
+impl SeedJob {
+    fn new(id: u64, params: SeedJobParams) -> Self {
+        SeedJob {
+            id,
+            params,
+            progress: ProgressBar::hidden(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            state: Mutex::new(JobState {
+                status: JobStatus::Queued,
+                error: None,
+                elapsed: None,
+            }),
+        }
+    }
+    pub fn status(&self) -> JobStatus {
+        self.state.lock().unwrap().status
+    }
+    /// Set final job status
+    fn set_status(&self, status: JobStatus, error: Option<String>) {
+        let elapsed = Some(self.progress.elapsed());
+        *self.state.lock().unwrap() = JobState {
+            status,
+            error,
+            elapsed,
+        };
+    }
+    pub fn info(&self) -> SeedJobInfo {
+        let state = self.state.lock().unwrap();
+        let tiles = self.progress.position();
+        let elapsed_secs = match state.status {
+            JobStatus::Queued => 0.0,
+            JobStatus::Running => self.progress.elapsed().as_secs_f64(),
+            _ => state.elapsed.unwrap_or_default().as_secs_f64(),
+        };
+        let tiles_per_sec = if elapsed_secs > 0.0 {
+            tiles as f64 / elapsed_secs
+        } else {
+            0.0
+        };
+        SeedJobInfo {
+            id: self.id,
+            params: self.params.clone(),
+            status: state.status,
+            error: state.error.clone(),
+            tiles,
+            elapsed_secs,
+            tiles_per_sec,
+        }
+    }
+    /// Stop running job. Queued jobs are cancelled immediately.
+    pub fn cancel(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.cancelled.store(true, Ordering::Relaxed);
+        if state.status == JobStatus::Queued {
+            state.status = JobStatus::Cancelled;
+            state.elapsed = Some(Duration::ZERO);
+        }
+    }
+    /// Set queued job to running, unless it was cancelled
+    fn start(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.status != JobStatus::Queued {
+            return false;
+        }
+        self.progress.reset_elapsed();
+        state.status = JobStatus::Running;
+        true
+    }
+    fn finish(&self, result: anyhow::Result<()>) {
+        match result {
+            Ok(()) if self.cancelled.load(Ordering::Relaxed) => {
+                self.set_status(JobStatus::Cancelled, None)
+            }
+            Ok(()) => self.set_status(JobStatus::Finished, None),
+            Err(e) => {
+                warn!("Seed job {} failed: {e}", self.id);
+                self.set_status(JobStatus::Failed, Some(e.to_string()))
+            }
+        }
+    }
+    fn is_active(&self) -> bool {
+        matches!(self.status(), JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+/// Seed jobs executed by a limited number of workers
+pub struct SeedJobs {
+    jobs: Mutex<Vec<Arc<SeedJob>>>,
+    next_id: AtomicU64,
+    workers: Arc<Semaphore>,
+}
+
+impl SeedJobs {
+    pub fn new(workers: usize) -> Self {
+        SeedJobs {
+            jobs: Mutex::new(Vec::new()),
+            next_id: AtomicU64::new(1),
+            workers: Arc::new(Semaphore::new(workers.max(1))),
+        }
+    }
+    pub fn jobs(&self) -> Vec<Arc<SeedJob>> {
+        self.jobs.lock().unwrap().clone()
+    }
+    pub fn job(&self, id: u64) -> Option<Arc<SeedJob>> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.iter().find(|job| job.id == id).cloned()
+    }
+    /// Cancel active job or remove completed job
+    pub fn remove(&self, id: u64) -> Option<Arc<SeedJob>> {
+        let job = self.job(id)?;
+        if job.is_active() {
+            job.cancel();
+        } else {
+            self.jobs.lock().unwrap().retain(|job| job.id != id);
+        }
+        Some(job)
+    }
+    fn push(&self, params: SeedJobParams) -> Arc<SeedJob> {
+        let job = Arc::new(SeedJob::new(
+            self.next_id.fetch_add(1, Ordering::Relaxed),
+            params,
+        ));
+        self.jobs.lock().unwrap().push(job.clone());
+        job
+    }
+    /// Queue seed job, which is started when a worker is available
+    pub fn spawn(&self, service: Arc<TileService>, params: SeedJobParams) -> Arc<SeedJob> {
+        let job = self.push(params);
+        let workers = self.workers.clone();
+        let seed_job = job.clone();
+        actix_web::rt::spawn(async move {
+            let Ok(_permit) = workers.acquire_owned().await else {
+                return;
+            };
+            if !seed_job.start() {
+                return;
+            }
+            info!("Starting seed job {}", seed_job.id);
+            let result = service
+                .seed_with_progress(
+                    &seed_job.params.seed_args(),
+                    seed_job.progress.clone(),
+                    seed_job.cancelled.clone(),
+                )
+                .await;
+            seed_job.finish(result);
+        });
+        job
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> SeedJobParams {
+        SeedJobParams {
+            tileset: "ne_extracts".to_string(),
+            minzoom: None,
+            maxzoom: Some(2),
+            tms: None,
+            extent: None,
+            threads: None,
+        }
+    }
+
+    #[test]
+    fn job_states() {
+        let jobs = SeedJobs::new(1);
+        let job = jobs.push(params());
+        assert_eq!(job.status(), JobStatus::Queued);
+        assert!(job.start());
+        assert_eq!(job.status(), JobStatus::Running);
+        job.finish(Ok(()));
+        assert_eq!(job.status(), JobStatus::Finished);
+
+        let job = jobs.push(params());
+        assert!(job.start());
+        job.finish(Err(anyhow::anyhow!("WMS request failed")));
+        let info = job.info();
+        assert_eq!(info.status, JobStatus::Failed);
+        assert_eq!(info.error.as_deref(), Some("WMS request failed"));
+
+        // Running job is cancelled when seeding stops
+        let job = jobs.push(params());
+        assert!(job.start());
+        job.cancel();
+        assert_eq!(job.status(), JobStatus::Running);
+        job.finish(Ok(()));
+        assert_eq!(job.status(), JobStatus::Cancelled);
+    }
+
+    #[test]
+    fn cancel_queued_job() {
+        let jobs = SeedJobs::new(1);
+        let job = jobs.push(params());
+        assert!(jobs.remove(job.id).is_some());
+        assert_eq!(job.status(), JobStatus::Cancelled);
+        assert!(!job.start());
+        assert_eq!(jobs.jobs().len(), 1);
+        // Completed jobs are removed
+        assert!(jobs.remove(job.id).is_some());
+        assert!(jobs.jobs().is_empty());
+        assert!(jobs.remove(job.id).is_none());
+    }
+}
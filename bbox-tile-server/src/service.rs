@@ -1,34 +1,83 @@
+use crate::access::remove_mvt_layers;
 use crate::cli::Commands;
 use crate::config::*;
+use crate::config_t_rex::convert_config_cmd;
+use crate::datasource::vector_render::VectorRenderSource;
 use crate::datasource::wms_fcgi::{HttpRequestParams, MapService};
 use crate::datasource::{Datasources, SourceType, TileSource, TileSourceError};
+use crate::discovery::{DiscoveredTilesets, TilesetScanner};
+use crate::empty_tile::is_empty_tile;
 use crate::filter_params::FilterParams;
-use crate::store::{tile_store_from_config, TileReader, TileStore, TileStoreError, TileWriter};
+use crate::geojson::mvt_to_geojson;
+use crate::metatile::Metatile;
+use crate::metrics::{register_metrics, tile_metrics, TileMetrics};
+use crate::overzoom::{is_quadtree, overzoom_tile};
+use crate::raster::{image_format, transcode_image};
+use crate::seed_jobs::SeedJobs;
+use crate::store::{
+    insert_validators, metadata_with_tms, tile_encoding, tile_etag, tile_store_from_config,
+    TileReader, TileStore, TileStoreError, TileWriter,
+};
+use crate::styles::Styles;
+use actix_web::http::header;
+use actix_web::http::StatusCode;
 use async_trait::async_trait;
-use bbox_core::config::{error_exit, CoreServiceCfg};
-use bbox_core::metrics::{no_metrics, NoMetrics};
+use bbox_core::config::{app_dir, config_error_exit, error_exit, CoreServiceCfg};
 use bbox_core::ogcapi::ApiLink;
 use bbox_core::service::OgcApiService;
-use bbox_core::{Compression, Format, TileResponse};
+use bbox_core::{Compression, Format, TileResponse, TileResponseData};
 use clap::{ArgMatches, Args, FromArgMatches};
-use log::debug;
+use log::{debug, info, warn};
 use martin_mbtiles::Metadata;
 use ogcapi_types::tiles::TileMatrixSet;
+use prometheus::Registry;
 use serde_json::json;
-use std::collections::HashMap;
+use std::collections::{hash_map::Entry, HashMap};
+use std::fs;
+use std::io::Cursor;
 use std::num::NonZeroU16;
+use std::ops::Deref;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
 use tile_grid::{tms, BoundingBox, RegistryError, TileMatrixSetOps, Tms, Xyz};
 use tilejson::TileJSON;
+use tokio::sync::OnceCell;
 
 #[derive(Clone)]
 pub struct TileService {
     pub(crate) tilesets: Tilesets,
+    /// Tilesets discovered in tileset directories
+    discovered: Arc<RwLock<DiscoveredTilesets>>,
+    /// Configured style documents
+    styles: Arc<Styles>,
     base_url: String,
+    /// OpenAPI document including declared query parameters
+    openapi: String,
+    /// Seeding and cache administration
+    pub(crate) admin: Option<TileAdminCfg>,
+    pub(crate) seed_jobs: Arc<SeedJobs>,
+    /// Custom grids as defined in the configuration
+    grid_definitions: Arc<HashMap<String, serde_json::Value>>,
 }
 
 pub type Tilesets = HashMap<String, TileSet>;
 
+/// Configured or discovered tileset
+pub enum TileSetRef<'a> {
+    Configured(&'a TileSet),
+    Discovered(Arc<TileSet>),
+}
+
+impl Deref for TileSetRef<'_> {
+    type Target = TileSet;
+    fn deref(&self) -> &TileSet {
+        match self {
+            TileSetRef::Configured(ts) => ts,
+            TileSetRef::Discovered(ts) => ts,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct TileSet {
     pub name: String,
@@ -36,15 +85,42 @@ pub struct TileSet {
     pub tms: Vec<TileSetGrid>,
     pub source: Box<dyn TileSource>,
     format: Format,
-    pub(crate) tile_store: Option<Box<dyn TileStore>>,
-    /// Store reader for web service
-    cache_reader: Option<Box<dyn TileReader>>,
-    /// Store writer for web service
-    cache_writer: Option<Box<dyn TileWriter>>,
+    /// Tile stores by TMS id
+    pub(crate) tile_stores: HashMap<String, Box<dyn TileStore>>,
+    /// Store readers for web service by TMS id
+    cache_readers: HashMap<String, Box<dyn TileReader>>,
+    /// Store writers for web service by TMS id
+    cache_writers: HashMap<String, Box<dyn TileWriter>>,
     config: TileSetCfg,
     cache_cfg: Option<TileStoreCfg>,
     cache_limits: Option<CacheLimitCfg>,
     cache_control: Vec<CacheControlCfg>,
+    /// Tile delivered for empty tiles
+    empty_tile_fallback: Option<Vec<u8>>,
+    /// Metatiles currently rendered
+    metatile_renders: Arc<Mutex<HashMap<String, MetatileRender>>>,
+}
+
+/// Tiles of a metatile, shared between concurrent requests
+type MetatileRender = Arc<OnceCell<Arc<Vec<(Xyz, Vec<u8>)>>>>;
+
+/// Removes a metatile render entry when its creating request is finished or dropped
+struct MetatileRenderGuard<'a> {
+    renders: &'a Mutex<HashMap<String, MetatileRender>>,
+    key: String,
+    render: MetatileRender,
+}
+
+impl Drop for MetatileRenderGuard<'_> {
+    fn drop(&mut self) {
+        let mut renders = self.renders.lock().unwrap();
+        if renders
+            .get(&self.key)
+            .map_or(false, |render| Arc::ptr_eq(render, &self.render))
+        {
+            renders.remove(&self.key);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -54,6 +130,58 @@ pub struct TileSetGrid {
     pub minzoom: u8,
     /// Maximum zoom level.
     pub maxzoom: u8,
+    /// Maximum zoom level for overzoomed tiles.
+    pub overzoom: Option<u8>,
+    /// Data extent in grid coordinates (Default: full grid)
+    pub extent: Option<BoundingBox>,
+}
+
+impl TileSetGrid {
+    /// Maximum zoom level including overzoomed levels.
+    pub fn served_maxzoom(&self) -> u8 {
+        self.overzoom.unwrap_or(self.maxzoom).max(self.maxzoom)
+    }
+    /// Set data extent from WGS84 bounds
+    pub fn set_lnglat_extent(&mut self, bounds: &BoundingBox) {
+        let corners = [
+            (bounds.left, bounds.bottom),
+            (bounds.left, bounds.top),
+            (bounds.right, bounds.bottom),
+            (bounds.right, bounds.top),
+        ];
+        let coords = corners
+            .iter()
+            .map(|(lng, lat)| self.tms.xy(*lng, *lat))
+            .collect::<Result<Vec<_>, _>>();
+        match coords {
+            Ok(coords) => {
+                let xs = coords.iter().map(|c| c.x);
+                let ys = coords.iter().map(|c| c.y);
+                self.extent = Some(BoundingBox::new(
+                    xs.clone().fold(f64::INFINITY, f64::min),
+                    ys.clone().fold(f64::INFINITY, f64::min),
+                    xs.fold(f64::NEG_INFINITY, f64::max),
+                    ys.fold(f64::NEG_INFINITY, f64::max),
+                ));
+            }
+            Err(e) => warn!("Extent of grid `{}` not restricted: {e}", self.tms.id()),
+        }
+    }
+    /// Zoom level within grid limits (including overzoomed levels)
+    pub fn contains_zoom(&self, zoom: u8) -> bool {
+        zoom >= self.minzoom && zoom <= self.served_maxzoom()
+    }
+    /// Tile intersects data extent
+    pub fn intersects_extent(&self, tile: &Xyz) -> bool {
+        let Some(extent) = &self.extent else {
+            return true;
+        };
+        let bounds = self.tms.xy_bounds(tile);
+        bounds.left < extent.right
+            && bounds.right > extent.left
+            && bounds.bottom < extent.top
+            && bounds.top > extent.bottom
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -66,17 +194,41 @@ pub enum ServiceError {
     UnknownFormat(String),
     #[error("Tileset grid not found")] // default grid missing or z out of range
     TilesetGridNotFound,
+    #[error("Tile out of bounds")]
+    TileOutOfBounds,
     #[error(transparent)]
     TileRegistryError(#[from] RegistryError),
     #[error(transparent)]
     TileSourceError(#[from] TileSourceError),
     #[error(transparent)]
     TileStoreError(#[from] TileStoreError),
+    #[error("Style `{0}` not found")]
+    StyleNotFound(String),
+    #[error("Authentication required")]
+    AuthenticationRequired,
+    #[error("Access to `{0}` denied")]
+    AccessDenied(String),
+    #[error("Invalid style JSON `{0}`: {1}")]
+    StyleError(String, serde_json::Error),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
 }
 
-impl actix_web::error::ResponseError for ServiceError {}
+impl actix_web::error::ResponseError for ServiceError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServiceError::AuthenticationRequired => StatusCode::UNAUTHORIZED,
+            ServiceError::AccessDenied(_) => StatusCode::FORBIDDEN,
+            ServiceError::TileSourceError(TileSourceError::InvalidFilterParam(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            ServiceError::TilesetGridNotFound | ServiceError::TileOutOfBounds => {
+                StatusCode::NOT_FOUND
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
 
 pub trait SourceLookup {
     fn source(&self, tileset: &str) -> Option<&dyn TileSource>;
@@ -102,16 +254,29 @@ impl OgcApiService for TileService {
     type Config = TileServiceCfg;
     type CliCommands = Commands;
     type CliArgs = ServiceArgs;
-    type Metrics = NoMetrics;
+    type Metrics = TileMetrics;
 
     async fn create(config: &Self::Config, core_cfg: &CoreServiceCfg) -> Self {
         let mut tilesets = HashMap::new();
 
+        if config
+            .admin
+            .as_ref()
+            .map_or(false, |admin| admin.allowed_groups.is_empty())
+        {
+            config_error_exit("`tileadmin.allowed_groups` must contain at least one group");
+        }
+
         // Register custom grids
         let mut grids = tms().clone();
+        let mut custom_ids = Vec::new();
+        let mut grid_definitions = HashMap::new();
         for grid in &config.grids {
-            let custom = TileMatrixSet::from_json_file(&grid.abs_path().to_string_lossy())
-                .unwrap_or_else(error_exit);
+            let json = grid.tms_json().unwrap_or_else(error_exit);
+            let custom: TileMatrixSet =
+                serde_json::from_value(json.clone()).unwrap_or_else(error_exit);
+            custom_ids.push(custom.id.clone());
+            grid_definitions.insert(custom.id.clone(), json);
             grids
                 .register(vec![custom], true)
                 .unwrap_or_else(error_exit);
@@ -126,12 +291,49 @@ impl OgcApiService for TileService {
             .map(|cfg| (cfg.name.clone(), cfg))
             .collect();
 
-        for ts in &config.tilesets {
+        // Add tilesets of PostGIS geometry tables
+        let mut ts_configs = config.tilesets.clone();
+        for pg_cfg in &config.tileset_discovery.postgis {
+            for ts in datasources.postgis_tilesets(pg_cfg).await {
+                if ts_configs.iter().any(|cfg| cfg.name == ts.name) {
+                    warn!(
+                        "Skipping PostGIS tileset `{}` - name already in use",
+                        ts.name
+                    );
+                } else {
+                    ts_configs.push(ts);
+                }
+            }
+        }
+
+        // Rendered tilesets use vector tilesets set up before
+        ts_configs.sort_by_key(|ts| matches!(ts.source, SourceParamCfg::VectorRender(_)));
+        let styles = Styles::from_config(config);
+
+        for ts in &ts_configs {
+            if let Some(metatile) = &ts.metatile {
+                if !matches!(
+                    ts.source,
+                    SourceParamCfg::WmsHttp(_) | SourceParamCfg::WmsFcgi(_)
+                ) {
+                    config_error_exit(format!(
+                        "Tileset `{}`: metatiles require a `wms_proxy` or `map_service` source",
+                        ts.name
+                    ));
+                }
+                if metatile.size == 0 {
+                    config_error_exit(format!(
+                        "Tileset `{}`: metatile size must be at least 1",
+                        ts.name
+                    ));
+                }
+            }
             let ts_grids_cfg = if ts.tms.is_empty() {
                 vec![TilesetTmsCfg {
                     id: "WebMercatorQuad".to_string(),
                     minzoom: None,
                     maxzoom: None,
+                    overzoom: None,
                 }]
             } else {
                 ts.tms.clone()
@@ -144,22 +346,62 @@ impl OgcApiService for TileService {
                         tms: grid.clone(),
                         minzoom: cfg.minzoom.unwrap_or(grid.minzoom()),
                         maxzoom: cfg.maxzoom.unwrap_or(grid.maxzoom()),
+                        overzoom: cfg.overzoom.map(|z| z.min(grid.maxzoom())),
+                        extent: None,
                     }
                 })
                 .collect::<Vec<_>>();
+            for grid in &ts_grids {
+                if let Some(overzoom) = grid.overzoom.filter(|z| *z > grid.maxzoom) {
+                    if !is_quadtree(&grid.tms, grid.maxzoom, overzoom) {
+                        config_error_exit(format!(
+                            "Tileset `{}`: overzooming requires a quadtree grid, `{}` is not subdivided into 2x2 tiles",
+                            ts.name,
+                            grid.tms.id()
+                        ));
+                    }
+                }
+            }
             ts_grids.sort_by_key(|tsg| tsg.minzoom);
-            let source = datasources
-                .setup_tile_source(&ts.source, &ts_grids, &ts_grids_cfg)
-                .await;
+            let mut ts_cfg = ts.clone();
+            let source = if let SourceParamCfg::VectorRender(render_cfg) = &ts.source {
+                let vector_ts = tilesets.get(&render_cfg.tileset).unwrap_or_else(|| {
+                    error_exit(ServiceError::TilesetNotFound(render_cfg.tileset.clone()))
+                });
+                if vector_ts.source.source_type() != SourceType::Vector {
+                    config_error_exit(format!(
+                        "Tileset `{}`: rendered tileset `{}` is not a vector tileset",
+                        ts.name, vector_ts.name
+                    ));
+                }
+                for grid in &ts_grids {
+                    if vector_ts.tileset_grid(grid.tms.id()).is_err() {
+                        config_error_exit(format!(
+                            "Tileset `{}`: grid `{}` is not served by vector tileset `{}`",
+                            ts.name,
+                            grid.tms.id(),
+                            vector_ts.name
+                        ));
+                    }
+                }
+                ts_cfg
+                    .inherit_access(vector_ts.config())
+                    .unwrap_or_else(|e| config_error_exit(format!("Tileset `{}`: {e}", ts.name)));
+                let style = styles
+                    .render_style(render_cfg, vector_ts)
+                    .await
+                    .unwrap_or_else(error_exit);
+                Box::new(VectorRenderSource::new(vector_ts, &style))
+            } else {
+                datasources
+                    .setup_tile_source(&ts.source, &ts_grids, &ts_grids_cfg)
+                    .await
+            };
             let format = ts
                 .cache_format
                 .as_ref()
                 .and_then(|suffix| Format::from_suffix(suffix))
                 .unwrap_or(*source.default_format()); // TODO: emit warning or error
-            let metadata = source
-                .mbtiles_metadata(ts, &format)
-                .await
-                .unwrap_or_else(error_exit);
             let cache_cfg = stores
                 .get("<cli>")
                 .or(ts
@@ -172,35 +414,70 @@ impl OgcApiService for TileService {
                     })
                     .as_ref())
                 .cloned();
-            let tile_store = if let Some(config) = &cache_cfg {
-                Some(
-                    tile_store_from_config(
+            let mut tile_stores = HashMap::new();
+            if let Some(config) = &cache_cfg {
+                // Tiles of the first grid are stored at the configured location,
+                // additional grids in a separate location named by grid id.
+                for (i, grid_cfg) in ts_grids_cfg.iter().enumerate() {
+                    let namespace = (i > 0).then_some(grid_cfg.id.as_str());
+                    let grid = ts_grids
+                        .iter()
+                        .find(|grid| grid.tms.id() == grid_cfg.id)
+                        .expect("tileset grid");
+                    let metadata = source
+                        .mbtiles_metadata(ts, grid, &format)
+                        .await
+                        .unwrap_or_else(error_exit);
+                    let store = tile_store_from_config(
                         &config.cache,
                         &ts.name,
+                        namespace,
                         &format,
                         &config.compression,
-                        metadata,
+                        metadata_with_tms(metadata, &grid.tms),
                     )
-                    .await,
+                    .await;
+                    tile_stores.insert(grid_cfg.id.clone(), store);
+                }
+            }
+            let empty_tile_fallback = if let EmptyTileCfg::Fallback(path) = &ts.empty_tile {
+                let path = app_dir(path);
+                Some(
+                    fs::read(&path)
+                        .map_err(|e| TileStoreError::FileError(path, e))
+                        .unwrap_or_else(error_exit),
                 )
             } else {
                 None
             };
-            let tileset = TileSet {
-                name: ts.name.clone(),
-                tms: ts_grids,
-                source,
-                format,
-                tile_store,
-                cache_reader: None,
-                cache_writer: None,
-                config: ts.clone(),
+            let mut tileset = TileSet {
+                tile_stores,
                 cache_cfg: cache_cfg.map(|cfg| cfg.cache),
-                cache_limits: ts.cache_limits.clone(),
-                cache_control: ts.cache_control.clone(),
+                empty_tile_fallback,
+                ..TileSet::new(ts_cfg, ts_grids, source, format)
             };
+            tileset.setup_extent().await;
             tilesets.insert(ts.name.clone(), tileset);
         }
+
+        // Discover tilesets in directories
+        let custom_grids = custom_ids
+            .iter()
+            .filter_map(|id| grids.lookup(id).ok())
+            .collect();
+        let scanner = TilesetScanner::new(
+            &config.tileset_discovery,
+            custom_grids,
+            tilesets.keys().cloned().collect(),
+        );
+        if !config.tileset_discovery.directory.is_empty() {
+            scanner.scan().await;
+            let interval = config.tileset_discovery.scan_interval();
+            if interval > 0 {
+                scanner.clone().spawn_rescan(interval);
+            }
+        }
+
         let base_url = format!(
             "{}/",
             core_cfg
@@ -209,7 +486,19 @@ impl OgcApiService for TileService {
                 .unwrap_or("")
                 .trim_end_matches('/')
         );
-        TileService { tilesets, base_url }
+        let openapi = openapi_yaml(&tilesets);
+        TileService {
+            tilesets,
+            discovered: scanner.tilesets(),
+            styles: Arc::new(styles),
+            base_url,
+            openapi,
+            admin: config.admin.clone(),
+            seed_jobs: Arc::new(SeedJobs::new(
+                config.admin.as_ref().map_or(1, |admin| admin.seed_workers),
+            )),
+            grid_definitions: Arc::new(grid_definitions),
+        }
     }
 
     async fn cli_run(&self, cli: &ArgMatches) -> bool {
@@ -224,12 +513,26 @@ impl OgcApiService for TileService {
                 self.upload(&uploadargs).await.unwrap_or_else(error_exit);
                 true
             }
+            Ok(Commands::Copy(copyargs)) => {
+                self.copy_tiles(&copyargs).await.unwrap_or_else(error_exit);
+                true
+            }
+            Ok(Commands::Stats(statsargs)) => {
+                self.print_stats(&statsargs)
+                    .await
+                    .unwrap_or_else(error_exit);
+                true
+            }
+            Ok(Commands::ConvertConfig(args)) => {
+                convert_config_cmd(&args).unwrap_or_else(error_exit);
+                true
+            }
             _ => false,
         }
     }
 
     fn landing_page_links(&self, api_base: &str) -> Vec<ApiLink> {
-        if self.tilesets.is_empty() {
+        let mut links = if self.tileset_list().is_empty() {
             vec![]
         } else {
             vec![
@@ -250,20 +553,31 @@ impl OgcApiService for TileService {
                     length: None,
                 },
             ]
+        };
+        if !self.styles.is_empty() {
+            links.push(ApiLink {
+                href: format!("{api_base}/styles"),
+                rel: Some("http://www.opengis.net/def/rel/ogc/1.0/styles".to_string()),
+                type_: Some("application/json".to_string()),
+                title: Some("List of available styles".to_string()),
+                hreflang: None,
+                length: None,
+            });
         }
+        links
     }
     fn conformance_classes(&self) -> Vec<String> {
-        vec![
+        let mut classes = vec![
             // Core
             "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/core".to_string(),
             // TileSet
-            // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tileset".to_string(),
+            "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tileset".to_string(),
             // Tilesets list
-            // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tilesets-list".to_string(),
+            "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/tilesets-list".to_string(),
             // Dataset tilesets
             // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/dataset-tilesets".to_string(),
             // Geodata tilesets
-            // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/geodata-tilesets".to_string(),
+            "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/geodata-tilesets".to_string(),
             // Collections selection
             // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/collections-selection".to_string(),
             // DateTime
@@ -284,16 +598,66 @@ impl OgcApiService for TileService {
             // "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/geojson".to_string(),
             // Mapbox Vector Tiles
             "http://www.opengis.net/spec/ogcapi-tiles-1/1.0/conf/mvt".to_string(),
-        ]
+        ];
+        if !self.styles.is_empty() {
+            classes.extend([
+                // Styles core
+                "http://www.opengis.net/spec/ogcapi-styles-1/1.0/conf/core".to_string(),
+                // Mapbox Style
+                "http://www.opengis.net/spec/ogcapi-styles-1/1.0/conf/mapbox-styles".to_string(),
+            ]);
+        }
+        classes
     }
     fn openapi_yaml(&self) -> Option<&str> {
-        Some(include_str!("openapi.yaml"))
+        Some(&self.openapi)
+    }
+    fn add_metrics(&self, prometheus: &Registry) {
+        register_metrics(prometheus, self.metrics());
     }
     fn metrics(&self) -> &'static Self::Metrics {
-        no_metrics()
+        tile_metrics()
     }
 }
 
+/// OpenAPI document with tile endpoints of tilesets with declared query parameters
+fn openapi_yaml(tilesets: &Tilesets) -> String {
+    const OPENAPI_YAML: &str = include_str!("openapi.yaml");
+    const TILE_PATH: &str =
+        "/collections/{collectionId}/tiles/{tileMatrixSetId}/{tileMatrix}/{tileRow}/{tileCol}";
+    let mut names = tilesets
+        .values()
+        .filter(|ts| !ts.config.query_params().is_empty())
+        .map(|ts| &ts.name)
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        return OPENAPI_YAML.to_string();
+    }
+    names.sort();
+    let mut doc: serde_yaml::Value = serde_yaml::from_str(OPENAPI_YAML).expect("openapi.yaml");
+    let template = doc["paths"][TILE_PATH].clone();
+    for name in names {
+        let ts = &tilesets[name];
+        let mut path = template.clone();
+        let op = &mut path["get"];
+        op["summary"] = format!("Retrieve a vector tile from collection `{name}`.").into();
+        op["operationId"] = format!(".collection.{name}.vector.getTile").into();
+        if let Some(parameters) = op["parameters"].as_sequence_mut() {
+            // Collection is part of the path
+            parameters.retain(|param| {
+                param["$ref"].as_str() != Some("#/components/parameters/collectionId-vectorTiles")
+            });
+            for param in ts.config.query_params() {
+                if let Ok(param) = serde_yaml::to_value(param.openapi_parameter()) {
+                    parameters.push(param);
+                }
+            }
+        }
+        doc["paths"][TILE_PATH.replace("{collectionId}", name).as_str()] = path;
+    }
+    serde_yaml::to_string(&doc).expect("openapi.yaml")
+}
+
 pub struct QueryExtent {
     pub extent: BoundingBox,
     pub srid: i32,
@@ -311,32 +675,63 @@ impl TileService {
         for (_, ts) in self.tilesets.iter_mut() {
             ts.setup_tile_store().await?;
         }
+        // Rendered tilesets read vector tiles through the cache of the vector tileset
+        let tilesets = self.tilesets.clone();
+        for (_, ts) in self.tilesets.iter_mut() {
+            ts.source.set_tilesets(&tilesets);
+        }
         Ok(())
     }
-    pub fn tileset(&self, tileset: &str) -> Option<&TileSet> {
-        self.tilesets.get(tileset)
+    pub fn tileset(&self, tileset: &str) -> Option<TileSetRef<'_>> {
+        if let Some(ts) = self.tilesets.get(tileset) {
+            return Some(TileSetRef::Configured(ts));
+        }
+        let discovered = self.discovered.read().unwrap();
+        let ts = discovered
+            .get(tileset)
+            .map(|entry| TileSetRef::Discovered(entry.tileset.clone()));
+        ts
     }
-    pub fn grids(&self) -> Vec<&Tms> {
-        self.tilesets
+    /// Configured and discovered tilesets
+    pub fn tileset_list(&self) -> Vec<TileSetRef<'_>> {
+        let discovered = self.discovered.read().unwrap();
+        let tilesets = self
+            .tilesets
             .values()
+            .map(TileSetRef::Configured)
+            .chain(
+                discovered
+                    .values()
+                    .map(|entry| TileSetRef::Discovered(entry.tileset.clone())),
+            )
+            .collect();
+        tilesets
+    }
+    pub fn grids(&self) -> Vec<Tms> {
+        self.tileset_list()
+            .iter()
             .flat_map(|ts| &ts.tms)
             // remove duplicates
-            .map(|grid| (&grid.tms.tms.id, &grid.tms))
-            .collect::<HashMap<&String, &Tms>>()
+            .map(|grid| (grid.tms.tms.id.clone(), grid.tms.clone()))
+            .collect::<HashMap<String, Tms>>()
             .into_values()
             .collect()
     }
-    pub fn grid(&self, tms_id: &str) -> Option<&Tms> {
-        for ts in self.tilesets.values() {
-            if let Ok(grid) = ts.grid(tms_id) {
-                return Some(grid);
-            }
-        }
-        None
+    /// TileMatrixSet JSON of custom grid as defined in the configuration
+    pub fn grid_definition(&self, tms_id: &str) -> Option<&serde_json::Value> {
+        self.grid_definitions.get(tms_id)
+    }
+    pub fn grid(&self, tms_id: &str) -> Option<Tms> {
+        self.tileset_list()
+            .iter()
+            .find_map(|ts| ts.grid(tms_id).ok().cloned())
     }
     pub fn base_url(&self) -> &str {
         &self.base_url
     }
+    pub fn styles(&self) -> &Styles {
+        &self.styles
+    }
 
     pub fn href_prefix(&self) -> &str {
         self.base_url.trim_end_matches('/')
@@ -344,32 +739,134 @@ impl TileService {
 }
 
 impl TileSet {
+    /// Tileset without tile cache
+    pub fn new(
+        config: TileSetCfg,
+        tms: Vec<TileSetGrid>,
+        source: Box<dyn TileSource>,
+        format: Format,
+    ) -> Self {
+        TileSet {
+            name: config.name.clone(),
+            tms,
+            source,
+            format,
+            tile_stores: HashMap::new(),
+            cache_readers: HashMap::new(),
+            cache_writers: HashMap::new(),
+            cache_cfg: None,
+            cache_limits: config.cache_limits.clone(),
+            cache_control: config.cache_control.clone(),
+            empty_tile_fallback: None,
+            metatile_renders: Arc::new(Mutex::new(HashMap::new())),
+            config,
+        }
+    }
+    /// Set data extent of grids from configuration or source metadata
+    pub async fn setup_extent(&mut self) {
+        let bounds = if let Some(extent) = &self.config.extent {
+            Some(BoundingBox::new(
+                extent.minx,
+                extent.miny,
+                extent.maxx,
+                extent.maxy,
+            ))
+        } else if let Some(grid) = self.tms.first() {
+            self.source
+                .tilejson(&grid.tms, &self.format)
+                .await
+                .ok()
+                .and_then(|tilejson| tilejson.bounds)
+                .map(|b| BoundingBox::new(b.left, b.bottom, b.right, b.top))
+        } else {
+            None
+        };
+        if let Some(bounds) = bounds {
+            for grid in &mut self.tms {
+                grid.set_lnglat_extent(&bounds);
+            }
+        }
+    }
     pub async fn setup_tile_store(&mut self) -> Result<(), TileStoreError> {
-        if let Some(ts) = &self.tile_store {
-            self.cache_writer = Some(ts.setup_writer(false).await?);
-            self.cache_reader = Some(ts.setup_reader(false).await?);
+        for (tms_id, ts) in &self.tile_stores {
+            self.cache_writers
+                .insert(tms_id.clone(), ts.setup_writer(false).await?);
+            self.cache_readers
+                .insert(tms_id.clone(), ts.setup_reader(false).await?);
         }
         Ok(())
     }
     pub fn grid(&self, tms_id: &str) -> Result<&Tms, ServiceError> {
+        self.tileset_grid(tms_id).map(|g| &g.tms)
+    }
+    pub fn tileset_grid(&self, tms_id: &str) -> Result<&TileSetGrid, ServiceError> {
         self.tms
             .iter()
-            .map(|g| &g.tms)
-            .find(|tms| tms.id() == tms_id)
+            .find(|g| g.tms.id() == tms_id)
             .ok_or(RegistryError::TmsNotFound(tms_id.to_string()).into())
     }
     pub fn default_grid(&self, zoom: u8) -> Result<&Tms, ServiceError> {
         self.tms
             .iter()
             .find(|grid| zoom >= grid.minzoom && zoom <= grid.maxzoom)
+            .or_else(|| {
+                self.tms.iter().find(|grid| {
+                    zoom > grid.maxzoom && zoom <= grid.overzoom.unwrap_or(grid.maxzoom)
+                })
+            })
             .map(|grid| &grid.tms)
             .ok_or(ServiceError::TilesetGridNotFound)
     }
+    /// Check tile against zoom limits and data extent of grid.
+    ///
+    /// Returns `false` for tiles outside of the data extent.
+    pub fn check_bounds(&self, tms: &Tms, tile: &Xyz) -> Result<bool, ServiceError> {
+        let Some(grid) = self.tms.iter().find(|grid| grid.tms.id() == tms.id()) else {
+            return Ok(true);
+        };
+        if !grid.contains_zoom(tile.z) {
+            return Err(ServiceError::TileOutOfBounds);
+        }
+        Ok(grid.intersects_extent(tile))
+    }
+    /// Zoom level of ancestor tiles for overzoomed tiles
+    fn overzoom_source_level(&self, tms: &Tms, zoom: u8) -> Option<u8> {
+        self.tms
+            .iter()
+            .find(|grid| grid.tms.id() == tms.id())
+            .filter(|grid| zoom > grid.maxzoom && zoom <= grid.overzoom.unwrap_or(grid.maxzoom))
+            .map(|grid| grid.maxzoom)
+    }
     pub fn tile_format(&self) -> &Format {
         &self.format
     }
+    pub fn config(&self) -> &TileSetCfg {
+        &self.config
+    }
+    /// Formats offered for this tileset. Raster tiles are transcoded into all supported formats.
+    pub fn tile_formats(&self) -> Vec<Format> {
+        let format = self.format;
+        if self.source.source_type() == SourceType::Raster {
+            let mut formats = vec![format];
+            formats.extend(
+                [Format::Png, Format::Png8, Format::Jpeg, Format::Webp]
+                    .into_iter()
+                    .filter(|f| *f != format),
+            );
+            formats
+        } else if format == Format::Mvt {
+            // GeoJSON is decoded from MVT tiles
+            vec![format, Format::Json]
+        } else {
+            vec![format]
+        }
+    }
+    /// Vector (or map) tileset
+    pub fn is_vector(&self) -> bool {
+        matches!(self.format, Format::Mvt | Format::Json)
+    }
     pub fn is_cachable_at(&self, zoom: u8) -> bool {
-        if self.cache_reader.is_none() {
+        if self.cache_readers.is_empty() {
             return false;
         }
         match self.cache_limits {
@@ -383,13 +880,37 @@ impl TileSet {
         });
         entry.map(|e| e.max_age)
     }
+    /// Set `Cache-Control` header of tile response at `zoom`
+    pub fn insert_cache_control(&self, response: &mut TileResponse, zoom: u8) {
+        if let Some(cache_max_age) = self.cache_control_max_age(zoom) {
+            response.insert_header(("Cache-Control", format!("max-age={}", cache_max_age)));
+        }
+    }
     pub fn cache_config(&self) -> Option<&TileStoreCfg> {
         self.cache_cfg.as_ref()
     }
+    /// Remove cached tiles of grid `tms_id` or of all grids
+    pub async fn truncate_cache(&self, tms_id: Option<&str>) -> Result<(), ServiceError> {
+        if self.tile_stores.is_empty() {
+            return Err(ServiceError::CacheNotFound(self.name.clone()));
+        }
+        for (id, store) in &self.tile_stores {
+            if tms_id.map_or(true, |tms_id| tms_id == id) {
+                info!("Truncating cache of tileset `{}` grid `{id}`", self.name);
+                store.truncate().await?;
+            }
+        }
+        Ok(())
+    }
+    /// Tile store of grid
+    pub fn tile_store(&self, tms: &Tms) -> Option<&dyn TileStore> {
+        self.tile_stores.get(tms.id()).map(|s| s.as_ref())
+    }
     /// Compression of stored tiles
     pub fn cache_compression(&self) -> Compression {
-        self.tile_store
-            .as_ref()
+        self.tile_stores
+            .values()
+            .next()
             .map(|s| s.compression())
             .unwrap_or(Compression::None)
     }
@@ -403,23 +924,164 @@ impl TileSet {
         format: &Format,
         compression: Compression,
     ) -> Result<Vec<u8>, ServiceError> {
-        let metrics = self.source.wms_metrics();
-        let request_params = HttpRequestParams {
-            scheme: "http",
-            host: "localhost",
-            req_path: "/",
-            metrics,
-        };
         let tile = self
             .source
-            .xyz_request(tms, xyz, filter, format, request_params)
+            .xyz_request(
+                tms,
+                xyz,
+                filter,
+                self.source_format(format),
+                self.seed_request_params(),
+            )
             .await?;
-        let data = tile.read_bytes(&compression)?;
+        let mut data = tile.read_bytes(&compression)?;
+        self.transcode(&mut data, format)?;
         Ok(data.body)
     }
+    /// Request of all tiles in metatile containing `xyz` (single tile without metatiling)
+    // Used for seeding, compresses tiles according to target store
+    pub async fn read_metatile(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        filter: &FilterParams,
+        format: &Format,
+        compression: Compression,
+    ) -> Result<Vec<(Xyz, Vec<u8>)>, ServiceError> {
+        let Some(cfg) = self.config.metatile.as_ref().filter(|_| filter.is_empty()) else {
+            let tile = self
+                .read_tile(tms, xyz, filter, format, compression)
+                .await?;
+            return Ok(vec![(*xyz, tile)]);
+        };
+        let tiles = self
+            .metatile_tiles(tms, xyz, cfg, self.seed_request_params(), false)
+            .await?;
+        tiles
+            .iter()
+            .filter(|(xyz, _)| self.check_bounds(tms, xyz).unwrap_or(false))
+            .map(|(xyz, data)| {
+                let mut tile = TileResponse::new()
+                    .with_body(Box::new(Cursor::new(data.clone())))
+                    .read_bytes(&Compression::None)?;
+                self.transcode(&mut tile, format)?;
+                let data = tile
+                    .as_response(&compression)
+                    .read_bytes(&Compression::None)?;
+                Ok((*xyz, data.body))
+            })
+            .collect()
+    }
+    fn seed_request_params(&self) -> HttpRequestParams<'static> {
+        HttpRequestParams {
+            scheme: "http",
+            host: "localhost",
+            req_path: "/",
+            metrics: self.source.wms_metrics(),
+        }
+    }
+    /// Number of tiles in each direction of a metatile
+    pub fn metatile_size(&self) -> Option<u8> {
+        self.config.metatile.as_ref().map(|cfg| cfg.size)
+    }
     /// Get tile with cache lookup
     // Used for serving
+    /// Tile from cache or source. `hidden_layers` are removed from MVT tiles.
+    #[allow(clippy::too_many_arguments)]
     pub async fn tile_cached(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        filter: &FilterParams,
+        format: &Format,
+        compression: Compression,
+        hidden_layers: &[String],
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<Option<TileResponse>, ServiceError> {
+        if !self.check_bounds(tms, xyz)? {
+            return Ok(self.fallback_tile_response(xyz.z));
+        }
+        if self.format == Format::Mvt && (*format == Format::Json || !hidden_layers.is_empty()) {
+            return self
+                .processed_mvt_tile(
+                    tms,
+                    xyz,
+                    filter,
+                    format,
+                    compression,
+                    hidden_layers,
+                    request_params,
+                )
+                .await;
+        }
+        self.tile_in_format(tms, xyz, filter, format, compression, request_params)
+            .await
+    }
+    /// MVT tile with removed layers or decoded into GeoJSON.
+    ///
+    /// The tile is read with all layers, so cached tiles are never stored with user specific content.
+    #[allow(clippy::too_many_arguments)]
+    async fn processed_mvt_tile(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        filter: &FilterParams,
+        format: &Format,
+        compression: Compression,
+        hidden_layers: &[String],
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<Option<TileResponse>, ServiceError> {
+        let Some(tile) = self
+            .tile_in_format(
+                tms,
+                xyz,
+                filter,
+                &Format::Mvt,
+                Compression::None,
+                request_params,
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut response_data = tile.read_bytes(&Compression::None)?;
+        if !hidden_layers.is_empty() {
+            response_data.body = remove_mvt_layers(&response_data.body, hidden_layers)?;
+        }
+        if *format == Format::Json {
+            let layers = filter.filters.get("layers").map(String::as_str);
+            response_data.body = mvt_to_geojson(&response_data.body, tms, xyz, layers)?;
+            response_data.insert_header((header::CONTENT_TYPE, "application/geo+json"));
+        }
+        insert_etag(&mut response_data);
+        Ok(Some(response_data.as_response(&compression)))
+    }
+    async fn tile_in_format(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        filter: &FilterParams,
+        format: &Format,
+        compression: Compression,
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<Option<TileResponse>, ServiceError> {
+        if let Some(maxzoom) = self.overzoom_source_level(tms, xyz.z) {
+            return self
+                .tile_overzoomed(
+                    tms,
+                    xyz,
+                    maxzoom,
+                    filter,
+                    format,
+                    compression,
+                    request_params,
+                )
+                .await;
+        }
+        self.tile_from_cache_or_source(tms, xyz, filter, format, compression, request_params)
+            .await
+    }
+    async fn tile_from_cache_or_source(
         &self,
         tms: &Tms,
         xyz: &Xyz,
@@ -429,40 +1091,296 @@ impl TileSet {
         request_params: HttpRequestParams<'_>,
     ) -> Result<Option<TileResponse>, ServiceError> {
         let tileset = self;
-        if let Some(cache) = &tileset.cache_reader {
+        let metrics = tile_metrics();
+        if let Some(cache) = tileset.cache_readers.get(tms.id()) {
             if tileset.is_cachable_at(xyz.z) {
-                // TODO: support separate caches for different grids
-                if let Some(tile) = cache.get_tile(xyz).await? {
+                let cached = cache.get_stored_tile(xyz).await?;
+                let result = if cached.is_some() { "hit" } else { "miss" };
+                metrics
+                    .cache_counter
+                    .with_label_values(&[&tileset.name, result])
+                    .inc();
+                if let Some(tile) = cached {
                     debug!("Delivering tile from cache @ {xyz:?}");
-                    let response = tile.with_compression(&compression);
-                    //TODO: check returned format
-                    return Ok(Some(response));
+                    let mut response_data = tile.response.read_bytes(&Compression::None)?;
+                    tileset.transcode(&mut response_data, format)?;
+                    if !response_data.headers().contains_key(header::ETAG) {
+                        insert_etag(&mut response_data);
+                    }
+                    // Empty tiles are stored as references to a shared empty tile
+                    if tile.empty {
+                        return Ok(tileset.empty_tile_response(response_data, xyz.z, &compression));
+                    }
+                    tileset.observe_tile_size(&response_data, format);
+                    return Ok(Some(response_data.as_response(&compression)));
                 }
             }
         }
         // Request tile and write into cache
         debug!("Request tile from source @ {xyz:?}");
-        let mut tiledata = tileset
-            .source
-            .xyz_request(tms, xyz, filter, format, request_params)
-            .await?;
-        // TODO: if tiledata.empty() { return Ok(None) }
-        if let Some(cache_max_age) = tileset.cache_control_max_age(xyz.z) {
-            tiledata.insert_header(("Cache-Control", format!("max-age={}", cache_max_age)));
-        }
-        if tileset.is_cachable_at(xyz.z) {
+        let timer = metrics
+            .source_seconds
+            .with_label_values(&[&tileset.name])
+            .start_timer();
+        // Metatiles are shared between requests, so filtered tiles are requested individually
+        let metatile_cfg = tileset
+            .config
+            .metatile
+            .as_ref()
+            .filter(|_| filter.is_empty());
+        let (mut tiledata, source_format) = if let Some(metatile_cfg) = metatile_cfg {
+            let tiledata = tileset
+                .metatile_request(tms, xyz, metatile_cfg, request_params)
+                .await?;
+            (tiledata, &tileset.format)
+        } else {
+            let source_format = tileset.source_format(format);
+            let tiledata = tileset
+                .source
+                .xyz_request(tms, xyz, filter, source_format, request_params)
+                .await?;
+            (tiledata, source_format)
+        };
+        timer.observe_duration();
+        tileset.insert_cache_control(&mut tiledata, xyz.z);
+        let (mut response_data, empty) = if tileset.is_cachable_at(xyz.z) {
             debug!("Writing tile into cache @ {xyz:?}");
             // Read tile into memory
-            let response_data = tiledata.read_bytes(&tileset.cache_compression())?;
-            if let Some(cache) = &tileset.cache_writer {
-                cache.put_tile(xyz, response_data.body.clone()).await?;
+            let mut response_data = tiledata.read_bytes(&tileset.cache_compression())?;
+            tileset.transcode(&mut response_data, &tileset.format)?;
+            let empty = is_empty_tile(
+                &response_data.body,
+                &tileset.format,
+                &response_data.compression(),
+            );
+            if let Some(cache) = tileset.cache_writers.get(tms.id()) {
+                let data = response_data.body.clone();
+                if empty {
+                    cache.put_empty_tile(xyz, data).await?;
+                } else {
+                    cache.put_tile(xyz, data).await?;
+                }
             }
-            let response = response_data.as_response(&compression);
-            Ok(Some(response))
+            (response_data, empty)
         } else {
-            let response = tiledata.with_compression(&compression);
-            Ok(Some(response))
+            // Read tile into memory for computing the ETag
+            let response_data = tiledata.read_bytes(&Compression::None)?;
+            let empty = is_empty_tile(
+                &response_data.body,
+                source_format,
+                &response_data.compression(),
+            );
+            (response_data, empty)
+        };
+        tileset.transcode(&mut response_data, format)?;
+        insert_etag(&mut response_data);
+        if empty {
+            debug!("Empty tile @ {xyz:?}");
+            return Ok(tileset.empty_tile_response(response_data, xyz.z, &compression));
         }
+        tileset.observe_tile_size(&response_data, format);
+        Ok(Some(response_data.as_response(&compression)))
+    }
+    fn observe_tile_size(&self, response_data: &TileResponseData, format: &Format) {
+        tile_metrics()
+            .tile_size_bytes
+            .with_label_values(&[&self.name, format.file_suffix()])
+            .observe(response_data.body.len() as f64);
+    }
+    /// Tile from metatile, writing all other tiles of the metatile into the cache
+    async fn metatile_request(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        cfg: &MetatileCfg,
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<TileResponse, ServiceError> {
+        let tiles = self
+            .metatile_tiles(tms, xyz, cfg, request_params, true)
+            .await?;
+        let data = tiles
+            .iter()
+            .find(|(tile, _)| tile == xyz)
+            .map(|(_, data)| data.clone())
+            .ok_or(TileSourceError::TileXyzError)?;
+        let mut response = TileResponse::new();
+        response.set_content_type(self.format.content_type());
+        Ok(response.with_body(Box::new(Cursor::new(data))))
+    }
+    /// Render metatile containing `xyz`, sharing the result with concurrent requests
+    ///
+    /// Tiles are encoded in the tileset format. With `cache_neighbours`, the other tiles
+    /// of the metatile are written into the cache.
+    async fn metatile_tiles(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        cfg: &MetatileCfg,
+        request_params: HttpRequestParams<'_>,
+        cache_neighbours: bool,
+    ) -> Result<Arc<Vec<(Xyz, Vec<u8>)>>, ServiceError> {
+        let metatile = Metatile::new(tms, xyz, cfg)?;
+        let origin = metatile.tiles[0];
+        let key = format!("{}/{}/{}/{}", tms.id(), origin.z, origin.x, origin.y);
+        let (render, _guard) = {
+            let mut renders = self.metatile_renders.lock().unwrap();
+            match renders.entry(key.clone()) {
+                Entry::Occupied(entry) => (entry.get().clone(), None),
+                Entry::Vacant(entry) => {
+                    let render = entry.insert(MetatileRender::default()).clone();
+                    // The creating request removes the entry when finished
+                    let guard = MetatileRenderGuard {
+                        renders: &self.metatile_renders,
+                        key,
+                        render: render.clone(),
+                    };
+                    (render, Some(guard))
+                }
+            }
+        };
+        let tiles = render
+            .get_or_try_init(move || async move {
+                debug!("Rendering metatile @ {origin:?}");
+                let response = self
+                    .source
+                    .extent_request(
+                        &metatile.extent_info,
+                        self.source.default_format(),
+                        request_params,
+                    )
+                    .await?;
+                let data = response.read_bytes(&Compression::None)?.body;
+                let tiles = metatile.split(&data, &self.format, &self.config.image_encoding)?;
+                if cache_neighbours && self.is_cachable_at(xyz.z) {
+                    if let Some(cache) = self.cache_writers.get(tms.id()) {
+                        let neighbours = tiles.iter().filter(|(tile, _)| {
+                            tile != xyz && self.check_bounds(tms, tile).unwrap_or(false)
+                        });
+                        for (tile, data) in neighbours {
+                            let empty = is_empty_tile(data, &self.format, &Compression::None);
+                            let data = TileResponse::new()
+                                .with_body(Box::new(Cursor::new(data.clone())))
+                                .read_bytes(&self.cache_compression())?
+                                .body;
+                            if empty {
+                                cache.put_empty_tile(tile, data).await?;
+                            } else {
+                                cache.put_tile(tile, data).await?;
+                            }
+                        }
+                    }
+                }
+                Ok::<_, ServiceError>(Arc::new(tiles))
+            })
+            .await
+            .cloned();
+        tiles
+    }
+    /// Derive tile above source maxzoom from its ancestor tile
+    #[allow(clippy::too_many_arguments)]
+    async fn tile_overzoomed(
+        &self,
+        tms: &Tms,
+        xyz: &Xyz,
+        maxzoom: u8,
+        filter: &FilterParams,
+        format: &Format,
+        compression: Compression,
+        request_params: HttpRequestParams<'_>,
+    ) -> Result<Option<TileResponse>, ServiceError> {
+        let dz = xyz.z - maxzoom;
+        let ancestor = Xyz::new(xyz.x >> dz, xyz.y >> dz, maxzoom);
+        debug!("Overzooming tile @ {xyz:?} from {ancestor:?}");
+        let Some(tile) = self
+            .tile_from_cache_or_source(
+                tms,
+                &ancestor,
+                filter,
+                format,
+                Compression::None,
+                request_params,
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        let ancestor_data = tile.read_bytes(&Compression::None)?;
+        let data = overzoom_tile(
+            &ancestor_data.body,
+            format,
+            &ancestor,
+            xyz,
+            &self.config.image_encoding,
+        )?;
+        let mut response = TileResponse::new();
+        response.set_content_type(format.content_type());
+        self.insert_cache_control(&mut response, xyz.z);
+        insert_validators(&mut response, &data, None);
+        // Overzoomed tile is as old as its ancestor tile
+        if let Some(modified) = ancestor_data.headers().get(header::LAST_MODIFIED) {
+            response.insert_header((header::LAST_MODIFIED, modified));
+        }
+        let response = response.with_body(Box::new(Cursor::new(data)));
+        Ok(Some(response.with_compression(&compression)))
+    }
+    /// Format requested from source
+    ///
+    /// Raster sources render in their default format, which is transcoded into the requested format.
+    fn source_format<'a>(&'a self, format: &'a Format) -> &'a Format {
+        if self.source.source_type() == SourceType::Raster {
+            self.source.default_format()
+        } else {
+            format
+        }
+    }
+    /// Convert uncompressed raster tile into `format`
+    fn transcode(
+        &self,
+        response_data: &mut TileResponseData,
+        format: &Format,
+    ) -> Result<(), ServiceError> {
+        if self.source.source_type() != SourceType::Raster
+            || response_data.compression() != Compression::None
+        {
+            return Ok(());
+        }
+        if let Some(data) =
+            transcode_image(&response_data.body, format, &self.config.image_encoding)?
+        {
+            response_data.body = data;
+            insert_etag(response_data);
+        }
+        if image_format(format).is_some() {
+            // PNG and 8-bit PNG tiles are delivered unchanged with the requested content type
+            response_data.insert_header((header::CONTENT_TYPE, format.content_type()));
+        }
+        Ok(())
+    }
+    /// Response for tile without content
+    fn empty_tile_response(
+        &self,
+        response_data: TileResponseData,
+        zoom: u8,
+        compression: &Compression,
+    ) -> Option<TileResponse> {
+        match &self.config.empty_tile {
+            EmptyTileCfg::NoContent => None,
+            EmptyTileCfg::Deliver => {
+                let mut response = response_data.as_response(compression);
+                self.insert_cache_control(&mut response, zoom);
+                Some(response)
+            }
+            EmptyTileCfg::Fallback(_) => self.fallback_tile_response(zoom),
+        }
+    }
+    /// Configured fallback tile. Tiles outside of the data extent get this response or `204 No Content`.
+    fn fallback_tile_response(&self, zoom: u8) -> Option<TileResponse> {
+        let data = self.empty_tile_fallback.clone()?;
+        let mut response = TileResponse::new();
+        response.set_content_type(self.format.content_type());
+        self.insert_cache_control(&mut response, zoom);
+        insert_validators(&mut response, &data, None);
+        Some(response.with_body(Box::new(Cursor::new(data))))
     }
     /// TileJSON layer metadata (<https://github.com/mapbox/tilejson-spec>)
     pub async fn tilejson(&self, tms: &Tms, base_url: &str) -> Result<TileJSON, ServiceError> {
@@ -479,15 +1397,38 @@ impl TileSet {
             tileset = &self.name,
             format = format.file_suffix()
         ));
+        if let Some(extent) = &self.config.extent {
+            tilejson.bounds = Some(tilejson::Bounds {
+                left: extent.minx,
+                bottom: extent.miny,
+                right: extent.maxx,
+                top: extent.maxy,
+            });
+        }
+        let query_params = self.config.query_params();
+        if !query_params.is_empty() {
+            tilejson.other.insert(
+                "query_params".to_string(),
+                query_params
+                    .iter()
+                    .map(|param| param.openapi_parameter())
+                    .collect(),
+            );
+        }
         Ok(tilejson)
     }
 
-    /// MBTiles metadata.json
-    pub async fn mbtiles_metadata(&self) -> Result<Metadata, ServiceError> {
-        Ok(self
+    /// MBTiles metadata.json of a tileset grid
+    pub async fn mbtiles_metadata(&self, tms: &Tms) -> Result<Metadata, ServiceError> {
+        let grid = self.tileset_grid(tms.id())?;
+        let mut metadata = self
             .source
-            .mbtiles_metadata(&self.config, &self.format)
-            .await?)
+            .mbtiles_metadata(&self.config, grid, &self.format)
+            .await?;
+        if let Some(store) = self.tile_stores.get(tms.id()) {
+            metadata.tile_info.encoding = tile_encoding(&store.compression());
+        }
+        Ok(metadata_with_tms(metadata, tms))
     }
 
     /// Autogenerated Style JSON (<https://www.mapbox.com/mapbox-gl-style-spec/>)
@@ -495,6 +1436,7 @@ impl TileSet {
         &self,
         base_url: &str,
         base_path: &str,
+        glyphs_url: Option<String>,
     ) -> Result<serde_json::Value, ServiceError> {
         let ts = self;
         let tileset = &self.name;
@@ -587,8 +1529,9 @@ impl TileSet {
             "metadata": {
                 "maputnik:renderer": "mbgljs"
             },
-            "glyphs": "https://go-spatial.github.io/carto-assets/fonts/{fontstack}/{range}.pbf",
-            // "glyphs": format!("{base_url}/fonts/{{fontstack}}/{{range}}.pbf"),
+            "glyphs": glyphs_url.unwrap_or(
+                "https://go-spatial.github.io/carto-assets/fonts/{fontstack}/{range}.pbf".to_string()
+            ),
             "sources": {
                 tileset: ts_source
             },
@@ -598,6 +1541,12 @@ impl TileSet {
     }
 }
 
+/// Set `ETag` header from tile content
+fn insert_etag(response_data: &mut TileResponseData) {
+    let etag = tile_etag(&response_data.body);
+    response_data.insert_header((header::ETAG, etag));
+}
+
 pub trait TmsExtensions {
     fn id(&self) -> &str;
     fn srid(&self) -> i32;
@@ -628,3 +1577,29 @@ impl TmsExtensions for Tms {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_bounds() {
+        let mut grid = TileSetGrid {
+            tms: tms().lookup("WebMercatorQuad").unwrap(),
+            minzoom: 2,
+            maxzoom: 10,
+            overzoom: Some(12),
+            extent: None,
+        };
+        assert!(grid.intersects_extent(&Xyz::new(0, 0, 2)));
+        assert!(!grid.contains_zoom(1));
+        assert!(grid.contains_zoom(12));
+        assert!(!grid.contains_zoom(13));
+
+        // Liechtenstein
+        grid.set_lnglat_extent(&BoundingBox::new(9.47, 47.05, 9.64, 47.27));
+        assert!(grid.intersects_extent(&Xyz::new(8625, 5756, 14)));
+        assert!(grid.intersects_extent(&Xyz::new(0, 0, 0)));
+        assert!(!grid.intersects_extent(&Xyz::new(0, 0, 2)));
+    }
+}
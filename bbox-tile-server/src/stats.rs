@@ -0,0 +1,328 @@
+//! Tile store statistics for tuning tileset configurations.
+use crate::cli::StatsArgs;
+use crate::copy::recompress;
+use crate::datasource::mvt::{mvt_layer_stats, MvtLayerStats};
+use crate::seed::parse_extent;
+use crate::service::TileService;
+use crate::store::{stored_tiles, TileReader, TileStoreError};
+use bbox_core::{Compression, Format};
+use geozero::mvt::{self, Message};
+use pumps::Concurrency;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+use tile_grid::{BoundingBox, Tms, Xyz};
+
+/// Tile statistics of a tile store
+#[derive(Serialize, Debug)]
+pub struct TileStoreStats {
+    pub tileset: String,
+    pub tms: String,
+    pub zoom_levels: Vec<ZoomStats>,
+    pub largest_tiles: Vec<TileSize>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ZoomStats {
+    pub zoom: u8,
+    pub tile_count: usize,
+    pub total_bytes: u64,
+    pub size: SizeStats,
+    /// MVT layers, largest first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub layers: Vec<LayerStats>,
+}
+
+/// Stored tile size percentiles in bytes
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SizeStats {
+    pub min: usize,
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
+    pub mean: usize,
+}
+
+#[derive(Serialize, Debug)]
+pub struct LayerStats {
+    pub name: String,
+    pub feature_count: u64,
+    /// Uncompressed layer size
+    pub bytes: u64,
+    /// Share of uncompressed tile size
+    pub byte_share: f64,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TileSize {
+    pub z: u8,
+    pub x: u64,
+    pub y: u64,
+    pub bytes: usize,
+}
+
+/// Grid range for collecting statistics
+///
+/// The zoom range is required for stores which can't list their tiles.
+pub struct StatsRange {
+    pub minzoom: Option<u8>,
+    pub maxzoom: Option<u8>,
+    pub extent: Option<BoundingBox>,
+}
+
+struct TileSample {
+    xyz: Xyz,
+    size: usize,
+    /// Uncompressed size of MVT tile
+    decoded_size: usize,
+    layers: Vec<MvtLayerStats>,
+}
+
+#[derive(Default)]
+struct ZoomCollector {
+    sizes: Vec<usize>,
+    /// Feature count and bytes per layer
+    layers: BTreeMap<String, (u64, u64)>,
+    decoded_bytes: u64,
+}
+
+struct StatsCollector {
+    zooms: BTreeMap<u8, ZoomCollector>,
+    largest: BinaryHeap<Reverse<(usize, u8, u64, u64)>>,
+    top: usize,
+}
+
+impl StatsCollector {
+    fn new(top: usize) -> Self {
+        Self {
+            zooms: BTreeMap::new(),
+            largest: BinaryHeap::new(),
+            top,
+        }
+    }
+    fn add(&mut self, sample: TileSample) {
+        let xyz = sample.xyz;
+        let zoom = self.zooms.entry(xyz.z).or_default();
+        zoom.sizes.push(sample.size);
+        zoom.decoded_bytes += sample.decoded_size as u64;
+        for layer in sample.layers {
+            let entry = zoom.layers.entry(layer.name).or_default();
+            entry.0 += layer.features as u64;
+            entry.1 += layer.bytes as u64;
+        }
+        self.largest
+            .push(Reverse((sample.size, xyz.z, xyz.x, xyz.y)));
+        if self.largest.len() > self.top {
+            self.largest.pop();
+        }
+    }
+    fn finish(self, tileset: &str, tms: &Tms) -> TileStoreStats {
+        let zoom_levels = self
+            .zooms
+            .into_iter()
+            .map(|(zoom, mut zc)| {
+                zc.sizes.sort_unstable();
+                let total_bytes = zc.sizes.iter().map(|size| *size as u64).sum::<u64>();
+                let tile_count = zc.sizes.len();
+                let mut layers = zc
+                    .layers
+                    .into_iter()
+                    .map(|(name, (feature_count, bytes))| LayerStats {
+                        name,
+                        feature_count,
+                        bytes,
+                        byte_share: bytes as f64 / zc.decoded_bytes.max(1) as f64,
+                    })
+                    .collect::<Vec<_>>();
+                layers.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+                ZoomStats {
+                    zoom,
+                    tile_count,
+                    total_bytes,
+                    size: SizeStats {
+                        min: percentile(&zc.sizes, 0.0),
+                        p50: percentile(&zc.sizes, 50.0),
+                        p90: percentile(&zc.sizes, 90.0),
+                        p99: percentile(&zc.sizes, 99.0),
+                        max: percentile(&zc.sizes, 100.0),
+                        mean: (total_bytes / tile_count.max(1) as u64) as usize,
+                    },
+                    layers,
+                }
+            })
+            .collect();
+        let largest_tiles = self
+            .largest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse((bytes, z, x, y))| TileSize { z, x, y, bytes })
+            .collect();
+        TileStoreStats {
+            tileset: tileset.to_string(),
+            tms: tms.tms.id.clone(),
+            zoom_levels,
+            largest_tiles,
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[usize], p: f64) -> usize {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+async fn read_tile_sample(reader: &dyn TileReader, xyz: Xyz, is_mvt: bool) -> Option<TileSample> {
+    let tile = reader.get_tile(&xyz).await.ok()??;
+    let data = tile.read_bytes(&Compression::None).ok()?.body;
+    let mut sample = TileSample {
+        xyz,
+        size: data.len(),
+        decoded_size: 0,
+        layers: Vec::new(),
+    };
+    if is_mvt {
+        if let Some(tile) = recompress(data, &Compression::None)
+            .ok()
+            .and_then(|raw| mvt::Tile::decode(raw.as_slice()).ok())
+        {
+            sample.decoded_size = tile.encoded_len();
+            sample.layers = mvt_layer_stats(&tile);
+        }
+    }
+    Some(sample)
+}
+
+/// Collect statistics of stored tiles
+pub(crate) async fn collect_stats(
+    tileset: &str,
+    reader: Box<dyn TileReader>,
+    tms: &Tms,
+    format: &Format,
+    range: &StatsRange,
+    top: usize,
+    threads: usize,
+) -> Result<TileStoreStats, TileStoreError> {
+    let tiles = stored_tiles(
+        reader.as_ref(),
+        tms,
+        range.minzoom,
+        range.maxzoom,
+        range.extent.as_ref(),
+    )
+    .await?;
+    let reader = Arc::new(reader);
+    let is_mvt = *format == Format::Mvt;
+    let (mut output_receiver, _join_handle) = pumps::Pipeline::from_iter(tiles)
+        .map(
+            move |xyz| {
+                let reader = reader.clone();
+                async move { read_tile_sample(reader.as_ref().as_ref(), xyz, is_mvt).await }
+            },
+            Concurrency::concurrent_unordered(threads),
+        )
+        .backpressure(100)
+        .build();
+    let mut collector = StatsCollector::new(top);
+    while let Some(sample) = output_receiver.recv().await {
+        if let Some(sample) = sample {
+            collector.add(sample);
+        }
+    }
+    Ok(collector.finish(tileset, tms))
+}
+
+impl TileService {
+    /// Print tile statistics as JSON
+    pub async fn print_stats(&self, args: &StatsArgs) -> anyhow::Result<()> {
+        let source = self
+            .store_source(
+                args.src.as_deref(),
+                args.tileset.as_deref(),
+                args.tms.as_deref(),
+                args.format.as_deref(),
+            )
+            .await?;
+        let range = StatsRange {
+            minzoom: args.minzoom.or(source.metadata.tilejson.minzoom),
+            maxzoom: args.maxzoom.or(source.metadata.tilejson.maxzoom),
+            extent: parse_extent(&args.extent)?,
+        };
+        let threads = args.threads.unwrap_or(num_cpus::get());
+        let stats = collect_stats(
+            &source.metadata.id,
+            source.reader,
+            &source.tms,
+            &source.format,
+            &range,
+            args.top,
+            threads,
+        )
+        .await?;
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_percentiles() {
+        let sizes = (1..=100).collect::<Vec<_>>();
+        assert_eq!(percentile(&sizes, 0.0), 1);
+        assert_eq!(percentile(&sizes, 50.0), 50);
+        assert_eq!(percentile(&sizes, 99.0), 99);
+        assert_eq!(percentile(&sizes, 100.0), 100);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+
+    #[test]
+    fn collect_tile_stats() {
+        let tms = tile_grid::tms().lookup("WebMercatorQuad").unwrap();
+        let mut collector = StatsCollector::new(2);
+        for (x, size) in [(0, 100), (1, 300), (2, 200), (3, 400)] {
+            collector.add(TileSample {
+                xyz: Xyz::new(x, 0, 2),
+                size,
+                decoded_size: 2 * size,
+                layers: vec![MvtLayerStats {
+                    name: "country".to_string(),
+                    bytes: 2 * size,
+                    features: 1,
+                }],
+            });
+        }
+        let stats = collector.finish("countries", &tms);
+        let zoom = &stats.zoom_levels[0];
+        assert_eq!(zoom.tile_count, 4);
+        assert_eq!(zoom.total_bytes, 1000);
+        assert_eq!(zoom.size.p50, 200);
+        assert_eq!(zoom.size.mean, 250);
+        assert_eq!(zoom.layers[0].feature_count, 4);
+        assert_eq!(zoom.layers[0].byte_share, 1.0);
+        assert_eq!(
+            stats.largest_tiles,
+            vec![
+                TileSize {
+                    z: 2,
+                    x: 3,
+                    y: 0,
+                    bytes: 400
+                },
+                TileSize {
+                    z: 2,
+                    x: 1,
+                    y: 0,
+                    bytes: 300
+                }
+            ]
+        );
+    }
+}
@@ -1,17 +1,19 @@
 use crate::config::{FileDedupCfg, FileStoreCfg, StoreCompressionCfg};
 use crate::store::{
-    CacheLayout, StoreFromConfig, TileReader, TileStore, TileStoreError, TileWriter,
+    grid_dir_name, insert_last_modified, insert_validators, CacheLayout, StoreFromConfig,
+    StoredTile, TileList, TileReader, TileStore, TileStoreError, TileWriter,
 };
 use async_trait::async_trait;
 use bbox_core::{Compression, Format, TileResponse};
-use log::debug;
+use log::{debug, warn};
 use martin_mbtiles::Metadata;
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{self, BufReader, BufWriter};
-use std::path::PathBuf;
+use std::io::{self, BufWriter, Cursor, Read};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use tile_grid::Xyz;
+use std::time::UNIX_EPOCH;
+use tile_grid::{BoundingBox, Tms, Xyz};
 
 #[derive(Clone)]
 pub struct FileStore {
@@ -89,11 +91,14 @@ impl StoreFromConfig for FileStoreCfg {
     fn to_store(
         &self,
         tileset_name: &str,
+        grid_namespace: Option<&str>,
         format: &Format,
         compression: &Option<StoreCompressionCfg>,
         _metadata: Metadata,
     ) -> Box<dyn TileStore> {
-        let base_dir = self.abs_path().join(PathBuf::from(tileset_name));
+        let base_dir = self
+            .abs_path()
+            .join(grid_dir_name(tileset_name, grid_namespace));
         let compression = compression.clone().unwrap_or(StoreCompressionCfg::None);
         Box::new(FileStore {
             base_dir,
@@ -148,6 +153,15 @@ impl TileStore for FileStore {
         };
         Ok(Box::new(writer))
     }
+    async fn truncate(&self) -> Result<(), TileStoreError> {
+        // Other grids of the tileset are stored in separate directories
+        if !self.base_dir.is_dir() {
+            return Ok(());
+        }
+        debug!("Removing {}", self.base_dir.display());
+        fs::remove_dir_all(&self.base_dir)
+            .map_err(|e| TileStoreError::FileError(self.base_dir.clone(), e))
+    }
 }
 
 #[async_trait]
@@ -164,15 +178,7 @@ impl TileWriter for FileStoreReaderWriter {
             let mut shared_path = self.layout.shared_path(&self.base_dir);
             shared_path.push(hash.to_hex().as_str());
             shared_path.set_extension(self.format.file_suffix());
-            if !shared_path.exists() {
-                // Write shared file
-                let mut writer = BufWriter::new(
-                    create_file_with_dir(&shared_path)
-                        .map_err(|e| TileStoreError::FileError(shared_path.clone(), e))?,
-                );
-                io::copy(&mut data.as_slice(), &mut writer)
-                    .map_err(|e| TileStoreError::FileError(shared_path.clone(), e))?;
-            }
+            write_shared_file(&shared_path, &data)?;
             // Write Hardlink or Symlink
             create_link_with_dir(&fullpath, &shared_path)
                 .map_err(|e| TileStoreError::FileError(fullpath.clone(), e))?;
@@ -186,6 +192,29 @@ impl TileWriter for FileStoreReaderWriter {
         };
         Ok(())
     }
+    async fn put_empty_tile(&self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError> {
+        // Empty tiles are links to the first stored empty tile
+        let fullpath = self.layout.path(&self.base_dir, xyz, &self.format);
+        debug!("Writing empty tile {}", fullpath.display());
+        let empty_path = self.layout.empty_tile_path(&self.base_dir, &self.format);
+        write_shared_file(&empty_path, &data)?;
+        create_link_with_dir(&fullpath, &empty_path)
+            .map_err(|e| TileStoreError::FileError(fullpath.clone(), e))?;
+        Ok(())
+    }
+}
+
+/// Write shared file, if not existing
+fn write_shared_file(shared_path: &PathBuf, data: &[u8]) -> Result<(), TileStoreError> {
+    if !shared_path.exists() {
+        let mut writer = BufWriter::new(
+            create_file_with_dir(shared_path)
+                .map_err(|e| TileStoreError::FileError(shared_path.clone(), e))?,
+        );
+        io::copy(&mut &data[..], &mut writer)
+            .map_err(|e| TileStoreError::FileError(shared_path.clone(), e))?;
+    }
+    Ok(())
 }
 
 fn create_file_with_dir(fullpath: &PathBuf) -> Result<File, io::Error> {
@@ -210,6 +239,11 @@ fn create_link_with_dir(fullpath: &PathBuf, srcpath: &PathBuf) -> Result<(), io:
             fs::create_dir_all(p.parent().unwrap())?;
             fs::hard_link(srcpath, fullpath)
         }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            // Replace existing tile
+            fs::remove_file(fullpath)?;
+            fs::hard_link(srcpath, fullpath)
+        }
         Err(e) => Err(e),
     }
 }
@@ -217,16 +251,120 @@ fn create_link_with_dir(fullpath: &PathBuf, srcpath: &PathBuf) -> Result<(), io:
 #[async_trait]
 impl TileReader for FileStoreReaderWriter {
     async fn get_tile(&self, xyz: &Xyz) -> Result<Option<TileResponse>, TileStoreError> {
+        let tile = self.get_stored_tile(xyz).await?;
+        Ok(tile.map(|tile| tile.response))
+    }
+    async fn get_stored_tile(&self, xyz: &Xyz) -> Result<Option<StoredTile>, TileStoreError> {
         let p = self.layout.path(&self.base_dir, xyz, &self.format);
-        if let Ok(f) = File::open(p) {
+        if let Ok(mut f) = File::open(&p) {
             let mut response = TileResponse::new();
             if self.compression == StoreCompressionCfg::Gzip {
                 response.insert_header(("Content-Encoding", "gzip"));
             }
             // TODO: Set content_type from `format`
-            Ok(Some(response.with_body(Box::new(BufReader::new(f)))))
+            // Tiles are small, so we read them into memory
+            let mut data = Vec::new();
+            f.read_to_end(&mut data)
+                .map_err(|e| TileStoreError::FileError(p.clone(), e))?;
+            let metadata = f
+                .metadata()
+                .map_err(|e| TileStoreError::FileError(p.clone(), e))?;
+            let empty = self.is_empty_tile_link(&metadata);
+            let modified = metadata.modified().ok();
+            if let Some(etag) = file_etag(&metadata) {
+                response.insert_header(("ETag", etag));
+                insert_last_modified(&mut response, modified);
+            } else {
+                insert_validators(&mut response, &data, modified);
+            }
+            Ok(Some(StoredTile {
+                response: response.with_body(Box::new(Cursor::new(data))),
+                empty,
+            }))
         } else {
             Ok(None)
         }
     }
+    async fn tile_list(
+        &self,
+        _tms: &Tms,
+        minzoom: u8,
+        maxzoom: u8,
+        _extent: Option<&BoundingBox>,
+    ) -> Result<Option<TileList>, TileStoreError> {
+        // Walk `{z}/{x}/{y}.{format}` directory tree
+        if !self.base_dir.is_dir() {
+            return Err(TileStoreError::FileError(
+                self.base_dir.clone(),
+                io::ErrorKind::NotFound.into(),
+            ));
+        }
+        let suffix = format!(".{}", self.format.file_suffix());
+        let tiles = numbered_entries(&self.base_dir, "")
+            .into_iter()
+            .filter(move |(z, _)| (u64::from(minzoom)..=u64::from(maxzoom)).contains(z))
+            .flat_map(move |(z, zdir)| {
+                let suffix = suffix.clone();
+                numbered_entries(&zdir, "")
+                    .into_iter()
+                    .flat_map(move |(x, xdir)| {
+                        numbered_entries(&xdir, &suffix)
+                            .into_iter()
+                            .map(move |(y, _)| Xyz::new(x, y, z as u8))
+                    })
+            });
+        Ok(Some(Box::new(tiles)))
+    }
+}
+
+impl FileStoreReaderWriter {
+    /// Check whether tile file is a link to the shared empty tile
+    fn is_empty_tile_link(&self, metadata: &fs::Metadata) -> bool {
+        let empty_path = self.layout.empty_tile_path(&self.base_dir, &self.format);
+        match fs::metadata(empty_path) {
+            Ok(empty) => same_file(metadata, &empty),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Entity tag from file size and modification time, avoiding to hash the content on every read
+fn file_etag(metadata: &fs::Metadata) -> Option<String> {
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    Some(format!(
+        "W/\"{:x}-{:x}\"",
+        metadata.len(),
+        modified.as_nanos()
+    ))
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    // Hard links share size and modification time
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+/// Directory entries named `{number}{suffix}`
+fn numbered_entries(dir: &Path, suffix: &str) -> Vec<(u64, PathBuf)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            warn!("Reading {} failed: {e}", dir.display());
+            return Vec::new();
+        }
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let number = name.strip_suffix(suffix)?.parse().ok()?;
+            Some((number, path))
+        })
+        .collect()
 }
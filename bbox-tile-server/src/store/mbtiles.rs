@@ -1,16 +1,20 @@
 use crate::config::{MbtilesStoreCfg, StoreCompressionCfg};
 use crate::mbtiles_ds::{mbtiles_from_path, MbtilesDatasource};
-use crate::store::{StoreFromConfig, TileReader, TileStore, TileStoreError, TileWriter};
+use crate::store::{
+    extent_tile_limits, grid_file_path, insert_validators, StoreFromConfig, StoredTile, TileData,
+    TileList, TileReader, TileStore, TileStoreError, TileWriter,
+};
 use async_trait::async_trait;
 use bbox_core::{Compression, Format, TileResponse};
+use futures::TryStreamExt;
 use log::info;
-use martin_mbtiles::{invert_y_value, Metadata};
-use martin_tile_utils::Format as TileFormat;
-use sqlx::{Acquire, Executor, Statement};
+use martin_mbtiles::{invert_y_value, MbtType, Metadata};
+use martin_tile_utils::{Encoding, Format as TileFormat};
+use sqlx::{Acquire, Executor, Row, Statement};
 use std::ffi::OsStr;
 use std::io::Cursor;
 use std::path::{Path, PathBuf};
-use tile_grid::Xyz;
+use tile_grid::{BoundingBox, Tms, Xyz};
 
 #[derive(Clone)]
 pub struct MbtilesStore {
@@ -22,13 +26,20 @@ impl StoreFromConfig for MbtilesStoreCfg {
     fn to_store(
         &self,
         _tileset_name: &str,
+        grid_namespace: Option<&str>,
         _format: &Format,
-        _compression: &Option<StoreCompressionCfg>,
+        compression: &Option<StoreCompressionCfg>,
         metadata: Metadata,
     ) -> Box<dyn TileStore> {
+        let mut metadata = metadata;
+        // MBTiles vector tiles are gzip compressed by default
+        metadata.tile_info.encoding = match (compression, metadata.tile_info.format) {
+            (Some(StoreCompressionCfg::Gzip), _) | (None, TileFormat::Mvt) => Encoding::Gzip,
+            (Some(StoreCompressionCfg::None), _) | (None, _) => Encoding::Uncompressed,
+        };
         Box::new(MbtilesStore {
-            path: self.abs_path(),
-            metadata: metadata.clone(),
+            path: grid_file_path(&self.abs_path(), grid_namespace),
+            metadata,
         })
     }
 }
@@ -50,8 +61,8 @@ impl MbtilesStore {
 #[async_trait]
 impl TileStore for MbtilesStore {
     fn compression(&self) -> Compression {
-        match self.metadata.tile_info.format {
-            TileFormat::Mvt => Compression::Gzip,
+        match self.metadata.tile_info.encoding {
+            Encoding::Gzip => Compression::Gzip,
             _ => Compression::None,
         }
     }
@@ -69,6 +80,16 @@ impl TileStore for MbtilesStore {
         .await?;
         Ok(Box::new(mbt))
     }
+    async fn truncate(&self) -> Result<(), TileStoreError> {
+        info!("Removing tiles from {}", &self.path.display());
+        let mbt = MbtilesDatasource::new_pool(
+            mbtiles_from_path(self.path.clone())?,
+            Some(self.metadata.clone()),
+        )
+        .await?;
+        mbt.truncate().await?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -80,92 +101,114 @@ impl TileWriter for MbtilesDatasource {
         }
     }
     async fn put_tile(&self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError> {
+        let tile = TileData {
+            xyz: *xyz,
+            data,
+            empty: false,
+        };
+        self.insert_tiles(&[tile]).await
+    }
+    async fn put_empty_tile(&self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError> {
+        let tile = TileData {
+            xyz: *xyz,
+            data,
+            empty: true,
+        };
+        self.insert_tiles(&[tile]).await
+    }
+    async fn put_tiles(&mut self, tiles: &[TileData]) -> Result<(), TileStoreError> {
+        self.insert_tiles(tiles).await
+    }
+}
+
+/// Metadata entry with the `tile_id` of the shared empty tile
+const EMPTY_TILE_ID: &str = "empty_tile_id";
+
+impl MbtilesDatasource {
+    /// Insert tiles into normalized MBTiles
+    ///
+    /// Empty tiles reference the first stored empty tile image.
+    async fn insert_tiles(&self, tiles: &[TileData]) -> Result<(), TileStoreError> {
         let mut conn = self.pool.acquire().await?;
+        // Why have to use our own SQL functions with a precomputed hash,
+        // because extension functions (md5) are registered only when using
+        // Mbtiles::open but not via SqlitePool.
         // self.mbtiles
-        //     .insert_tiles(
-        //         &mut conn,
-        //         self.layout,
-        //         CopyDuplicateMode::Override,
-        //         &[(xyz.z, xyz.x as u32, xyz.y as u32, data)],
-        //     )
+        //     .insert_tiles(&mut conn, self.layout, CopyDuplicateMode::Override, tiles)
         //     .await?;
-        debug_assert_eq!(
-            self.layout,
-            martin_mbtiles::MbtType::Normalized { hash_view: true }
-        );
-        // TODO: common code with put_tiles
+        debug_assert_eq!(self.layout, MbtType::Normalized { hash_view: true });
         let mut tx = conn.begin().await?;
-        let sql2 = tx
+        let insert_image = tx
             .prepare(
                 "INSERT OR IGNORE INTO images (tile_id, tile_data)
                 VALUES (?1, ?2);",
             )
             .await?;
-        let sql1 = tx
+        let insert_map = tx
             .prepare(
                 "INSERT OR REPLACE INTO map (zoom_level, tile_column, tile_row, tile_id)
                 VALUES (?1, ?2, ?3, ?4);",
             )
             .await?;
-        let (z, x, y, tile_data) = (&xyz.z, &(xyz.x as u32), &(xyz.y as u32), &data);
-        let hash = blake3::hash(tile_data).to_hex();
-        sql2.query()
-            .bind(hash.as_str())
-            .bind(tile_data)
-            .execute(&mut *tx)
+        let register_empty = tx
+            .prepare("INSERT OR IGNORE INTO metadata (name, value) VALUES (?1, ?2);")
             .await?;
-
-        let y = invert_y_value(*z, *y);
-        sql1.query()
-            .bind(z)
-            .bind(x)
-            .bind(y)
-            .bind(hash.as_str())
-            .execute(&mut *tx)
-            .await?;
-        tx.commit().await?;
-        Ok(())
-    }
-    async fn put_tiles(&mut self, tiles: &[(u8, u32, u32, Vec<u8>)]) -> Result<(), TileStoreError> {
-        let mut conn = self.pool.acquire().await?;
-        // Why have to use our own SQL functions with a precomputed hash,
-        // because extension functions (md5) are registered only when using
-        // Mbtiles::open but not via SqlitePool.
-        // self.mbtiles
-        //     .insert_tiles(&mut conn, self.layout, CopyDuplicateMode::Override, tiles)
-        //     .await?;
-        let mut tx = conn.begin().await?;
-        let sql2 = tx
+        let insert_empty_image = tx
             .prepare(
                 "INSERT OR IGNORE INTO images (tile_id, tile_data)
-                VALUES (?1, ?2);",
+                SELECT ?1, ?2 FROM metadata WHERE name = ?3 AND value = ?1;",
             )
             .await?;
-        let sql1 = tx
+        let insert_empty_map = tx
             .prepare(
                 "INSERT OR REPLACE INTO map (zoom_level, tile_column, tile_row, tile_id)
-                VALUES (?1, ?2, ?3, ?4);",
+                SELECT ?1, ?2, ?3, value FROM metadata WHERE name = ?4;",
             )
             .await?;
-        for (z, x, y, tile_data) in tiles {
-            let hash = blake3::hash(tile_data).to_hex();
-            sql2.query()
-                .bind(hash.as_str())
-                .bind(tile_data)
-                .execute(&mut *tx)
-                .await?;
-
-            let y = invert_y_value(*z, *y);
-            sql1.query()
-                .bind(z)
-                .bind(x)
-                .bind(y)
-                .bind(hash.as_str())
-                .execute(&mut *tx)
-                .await?;
+        for tile in tiles {
+            let (z, x) = (tile.xyz.z, tile.xyz.x as u32);
+            let y = invert_y_value(z, tile.xyz.y as u32);
+            let hash = blake3::hash(&tile.data).to_hex();
+            if tile.empty {
+                register_empty
+                    .query()
+                    .bind(EMPTY_TILE_ID)
+                    .bind(hash.as_str())
+                    .execute(&mut *tx)
+                    .await?;
+                insert_empty_image
+                    .query()
+                    .bind(hash.as_str())
+                    .bind(&tile.data)
+                    .bind(EMPTY_TILE_ID)
+                    .execute(&mut *tx)
+                    .await?;
+                insert_empty_map
+                    .query()
+                    .bind(z)
+                    .bind(x)
+                    .bind(y)
+                    .bind(EMPTY_TILE_ID)
+                    .execute(&mut *tx)
+                    .await?;
+            } else {
+                insert_image
+                    .query()
+                    .bind(hash.as_str())
+                    .bind(&tile.data)
+                    .execute(&mut *tx)
+                    .await?;
+                insert_map
+                    .query()
+                    .bind(z)
+                    .bind(x)
+                    .bind(y)
+                    .bind(hash.as_str())
+                    .execute(&mut *tx)
+                    .await?;
+            }
         }
         tx.commit().await?;
-
         Ok(())
     }
 }
@@ -173,7 +216,30 @@ impl TileWriter for MbtilesDatasource {
 #[async_trait]
 impl TileReader for MbtilesDatasource {
     async fn get_tile(&self, xyz: &Xyz) -> Result<Option<TileResponse>, TileStoreError> {
-        let resp = if let Some(content) = self.get_tile(xyz.z, xyz.x as u32, xyz.y as u32).await? {
+        let tile = self.get_stored_tile(xyz).await?;
+        Ok(tile.map(|tile| tile.response))
+    }
+    async fn get_stored_tile(&self, xyz: &Xyz) -> Result<Option<StoredTile>, TileStoreError> {
+        let tile = if let MbtType::Normalized { .. } = self.layout {
+            let y = invert_y_value(xyz.z, xyz.y as u32);
+            let row = sqlx::query(
+                "SELECT images.tile_data, map.tile_id IN (SELECT value FROM metadata WHERE name = ?4)
+                FROM map JOIN images ON images.tile_id = map.tile_id
+                WHERE map.zoom_level = ?1 AND map.tile_column = ?2 AND map.tile_row = ?3",
+            )
+            .bind(xyz.z)
+            .bind(xyz.x as u32)
+            .bind(y)
+            .bind(EMPTY_TILE_ID)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(|row| Ok::<_, sqlx::Error>((row.try_get::<Vec<u8>, _>(0)?, row.try_get(1)?)))
+                .transpose()?
+        } else {
+            let tile = self.get_tile(xyz.z, xyz.x as u32, xyz.y as u32).await?;
+            tile.map(|content| (content, false))
+        };
+        let resp = if let Some((content, empty)) = tile {
             let mut response = TileResponse::new();
             if self.format_info.format == TileFormat::Mvt {
                 response.set_content_type("application/x-protobuf");
@@ -181,11 +247,50 @@ impl TileReader for MbtilesDatasource {
             if let Some(encoding) = self.format_info.encoding.content_encoding() {
                 response.insert_header(("Content-Encoding", encoding));
             }
+            // The archive modification time doesn't tell when a tile has changed
+            insert_validators(&mut response, &content, None);
             let body = Box::new(Cursor::new(content));
-            Some(response.with_body(body))
+            Some(StoredTile {
+                response: response.with_body(body),
+                empty,
+            })
         } else {
             None
         };
         Ok(resp)
     }
+    async fn tile_list(
+        &self,
+        tms: &Tms,
+        minzoom: u8,
+        maxzoom: u8,
+        extent: Option<&BoundingBox>,
+    ) -> Result<Option<TileList>, TileStoreError> {
+        // Filter zoom levels and tile limits in SQL and stream rows per zoom level
+        let mut tiles = Vec::new();
+        for z in minzoom..=maxzoom {
+            let query = if let Some(extent) = extent {
+                let Some([xmin, ymin, xmax, ymax]) = extent_tile_limits(tms, extent, z) else {
+                    continue;
+                };
+                sqlx::query(
+                    "SELECT tile_column, tile_row FROM tiles WHERE zoom_level = ?1 AND tile_column BETWEEN ?2 AND ?3 AND tile_row BETWEEN ?4 AND ?5",
+                )
+                .bind(z)
+                .bind(xmin as u32)
+                .bind(xmax as u32)
+                .bind(invert_y_value(z, ymax as u32))
+                .bind(invert_y_value(z, ymin as u32))
+            } else {
+                sqlx::query("SELECT tile_column, tile_row FROM tiles WHERE zoom_level = ?1").bind(z)
+            };
+            let mut rows = query.fetch(&self.pool);
+            while let Some(row) = rows.try_next().await? {
+                let x: u32 = row.try_get(0)?;
+                let y = invert_y_value(z, row.try_get(1)?);
+                tiles.push(Xyz::new(u64::from(x), u64::from(y), z));
+            }
+        }
+        Ok(Some(Box::new(tiles.into_iter())))
+    }
 }
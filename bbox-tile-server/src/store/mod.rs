@@ -7,13 +7,20 @@ pub mod s3putfiles;
 
 use crate::config::{StoreCompressionCfg, TileStoreCfg};
 use crate::mbtiles_ds::Error as MbtilesDsError;
+use crate::service::TmsExtensions;
 use crate::store::s3::S3StoreError;
+use actix_web::http::header::HttpDate;
 use async_trait::async_trait;
 use bbox_core::{Compression, Format, TileResponse};
 use dyn_clone::{clone_trait_object, DynClone};
+use flate2::read::GzDecoder;
 use martin_mbtiles::{MbtError, Metadata};
+use martin_tile_utils::Encoding;
+use serde_json::json;
+use std::io;
 use std::path::{Path, PathBuf};
-use tile_grid::Xyz;
+use std::time::SystemTime;
+use tile_grid::{BoundingBox, Tms, Xyz};
 
 #[derive(thiserror::Error, Debug)]
 pub enum TileStoreError {
@@ -23,6 +30,8 @@ pub enum TileStoreError {
     ArgMissing(String),
     #[error("Operation not supported on readonly data store")]
     ReadOnly,
+    #[error("Operation `{0}` not supported by tile store")]
+    Unsupported(&'static str),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
@@ -38,9 +47,13 @@ pub enum TileStoreError {
 }
 
 pub trait StoreFromConfig {
+    /// Create store for a tileset grid
+    ///
+    /// Additional grids of a tileset are stored in a separate `grid_namespace`.
     fn to_store(
         &self,
         tileset_name: &str,
+        grid_namespace: Option<&str>,
         format: &Format,
         compression: &Option<StoreCompressionCfg>,
         metadata: Metadata,
@@ -53,6 +66,10 @@ pub trait TileStore: DynClone + Send + Sync {
     fn compression(&self) -> Compression;
     async fn setup_reader(&self, seeding: bool) -> Result<Box<dyn TileReader>, TileStoreError>;
     async fn setup_writer(&self, seeding: bool) -> Result<Box<dyn TileWriter>, TileStoreError>;
+    /// Remove all stored tiles
+    async fn truncate(&self) -> Result<(), TileStoreError> {
+        Err(TileStoreError::Unsupported("truncate"))
+    }
 }
 
 clone_trait_object!(TileStore);
@@ -65,6 +82,12 @@ pub trait TileWriter: DynClone + Send + Sync {
     async fn exists(&self, xyz: &Xyz) -> bool;
     /// Write tile into store
     async fn put_tile(&self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError>;
+    /// Write tile without visible content into store
+    ///
+    /// Stores supporting references keep a single copy of the empty tile.
+    async fn put_empty_tile(&self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError> {
+        self.put_tile(xyz, data).await
+    }
     /// Write tile into store requiring &mut self
     // mut is required for PMTiles writer
     async fn put_tile_mut(&mut self, xyz: &Xyz, data: Vec<u8>) -> Result<(), TileStoreError> {
@@ -72,10 +95,11 @@ pub trait TileWriter: DynClone + Send + Sync {
         self.put_tile(xyz, data).await
     }
     /// Write multiple tiles into store
-    async fn put_tiles(&mut self, tiles: &[(u8, u32, u32, Vec<u8>)]) -> Result<(), TileStoreError> {
-        for (z, x, y, tile) in tiles {
+    // PMTiles deduplicates identical tiles, including empty tiles
+    async fn put_tiles(&mut self, tiles: &[TileData]) -> Result<(), TileStoreError> {
+        for tile in tiles {
             let _ = self
-                .put_tile_mut(&Xyz::new(*x as u64, *y as u64, *z), tile.to_vec()) //FIXME: avoid clone!
+                .put_tile_mut(&tile.xyz, tile.data.to_vec()) //FIXME: avoid clone!
                 .await;
         }
         Ok(())
@@ -88,14 +112,154 @@ pub trait TileWriter: DynClone + Send + Sync {
 
 clone_trait_object!(TileWriter);
 
+/// Tile written into a store
+pub struct TileData {
+    pub xyz: Xyz,
+    pub data: Vec<u8>,
+    /// Tile without visible content
+    pub empty: bool,
+}
+
+/// Tile read from a store
+pub struct StoredTile {
+    pub response: TileResponse,
+    /// Tile is a reference to the stored empty tile
+    pub empty: bool,
+}
+
 #[async_trait]
 pub trait TileReader: DynClone + Send + Sync {
     /// Lookup tile and return Read stream, if found
     async fn get_tile(&self, xyz: &Xyz) -> Result<Option<TileResponse>, TileStoreError>;
+    /// Lookup tile, detecting references to the stored empty tile
+    ///
+    /// Stores without empty tile references return all tiles as they are.
+    async fn get_stored_tile(&self, xyz: &Xyz) -> Result<Option<StoredTile>, TileStoreError> {
+        let tile = self.get_tile(xyz).await?;
+        Ok(tile.map(|response| StoredTile {
+            response,
+            empty: false,
+        }))
+    }
+    /// Iterate over stored tiles within zoom range and extent, if supported by the store
+    ///
+    /// Stores may return tiles slightly outside of the extent.
+    async fn tile_list(
+        &self,
+        _tms: &Tms,
+        _minzoom: u8,
+        _maxzoom: u8,
+        _extent: Option<&BoundingBox>,
+    ) -> Result<Option<TileList>, TileStoreError> {
+        Ok(None)
+    }
 }
 
 clone_trait_object!(TileReader);
 
+pub type TileList = Box<dyn Iterator<Item = Xyz> + Send>;
+
+/// Stored tiles within zoom range and extent
+///
+/// Stores which can't list their tiles are iterated over the grid,
+/// which requires a known zoom range.
+pub async fn stored_tiles(
+    reader: &dyn TileReader,
+    tms: &Tms,
+    minzoom: Option<u8>,
+    maxzoom: Option<u8>,
+    extent: Option<&BoundingBox>,
+) -> Result<TileList, TileStoreError> {
+    let range = (
+        minzoom.unwrap_or(tms.minzoom()),
+        maxzoom.unwrap_or(tms.maxzoom()),
+    );
+    let Some(tiles) = reader.tile_list(tms, range.0, range.1, extent).await? else {
+        let (Some(minzoom), Some(maxzoom)) = (minzoom, maxzoom) else {
+            return Err(TileStoreError::ArgMissing(
+                "zoom range (minzoom and maxzoom)".to_string(),
+            ));
+        };
+        let griditer: TileList = if let Some(bbox) = extent {
+            Box::new(tms.xyz_iterator(bbox, minzoom, maxzoom))
+        } else {
+            Box::new(tms.hilbert_iterator(minzoom, maxzoom))
+        };
+        return Ok(griditer);
+    };
+    let (minzoom, maxzoom) = range;
+    let tms = tms.clone();
+    let extent = extent.cloned();
+    Ok(Box::new(tiles.filter(move |xyz| {
+        if xyz.z < minzoom || xyz.z > maxzoom {
+            return false;
+        }
+        let Some(extent) = &extent else {
+            return true;
+        };
+        let bounds = tms.xy_bounds(xyz);
+        bounds.left < extent.right
+            && bounds.right > extent.left
+            && bounds.bottom < extent.top
+            && bounds.top > extent.bottom
+    })))
+}
+
+/// Column and row limits `[xmin, ymin, xmax, ymax]` of tiles intersecting an extent
+pub(crate) fn extent_tile_limits(tms: &Tms, extent: &BoundingBox, zoom: u8) -> Option<[u64; 4]> {
+    let origin = tms.xy_bounds(&Xyz::new(0, 0, zoom));
+    let matrix = tms.matrix(zoom);
+    let matrix = matrix.as_ref();
+    let (width, height) = (origin.right - origin.left, origin.top - origin.bottom);
+    let col = |x: f64| ((x - origin.left) / width).floor();
+    let row = |y: f64| ((origin.top - y) / height).floor();
+    let maxcol = (u64::from(matrix.matrix_width) - 1) as f64;
+    let maxrow = (u64::from(matrix.matrix_height) - 1) as f64;
+    let (xmin, xmax) = (col(extent.left).max(0.0), col(extent.right).min(maxcol));
+    let (ymin, ymax) = (row(extent.top).max(0.0), row(extent.bottom).min(maxrow));
+    (xmin <= xmax && ymin <= ymax).then(|| [xmin as u64, ymin as u64, xmax as u64, ymax as u64])
+}
+
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Entity tag of tile content.
+///
+/// Weak tag, because the same content may be delivered with different content encodings.
+/// The hash is computed over uncompressed data, independent of the compression of the tile store.
+pub fn tile_etag(data: &[u8]) -> String {
+    let hash = if data.starts_with(&GZIP_MAGIC) {
+        let mut hasher = blake3::Hasher::new();
+        match io::copy(&mut GzDecoder::new(data), &mut hasher) {
+            Ok(_) => hasher.finalize(),
+            Err(_) => blake3::hash(data),
+        }
+    } else {
+        blake3::hash(data)
+    };
+    format!("W/\"{}\"", hash.to_hex())
+}
+
+/// Insert `ETag` and optional `Last-Modified` headers into tile response.
+pub fn insert_validators(
+    response: &mut TileResponse,
+    data: &[u8],
+    modified: Option<SystemTime>,
+) -> &mut TileResponse {
+    response.insert_header(("ETag", tile_etag(data)));
+    insert_last_modified(response, modified)
+}
+
+/// Insert optional `Last-Modified` header into tile response.
+pub fn insert_last_modified(
+    response: &mut TileResponse,
+    modified: Option<SystemTime>,
+) -> &mut TileResponse {
+    if let Some(modified) = modified {
+        response.insert_header(("Last-Modified", HttpDate::from(modified).to_string()));
+    }
+    response
+}
+
 #[derive(Clone, Debug)]
 pub enum CacheLayout {
     Zxy,
@@ -120,6 +284,13 @@ impl CacheLayout {
         path.push("shared");
         path
     }
+    /// Shared file referenced by empty tiles
+    pub fn empty_tile_path(&self, base_dir: &Path, format: &Format) -> PathBuf {
+        let mut path = self.shared_path(base_dir);
+        path.push("empty");
+        path.set_extension(format.file_suffix());
+        path
+    }
     // pub fn initialize(&self, base_dir: &Path) -> Result<(), TileStoreError> {
     //     let path = self.shared_path(base_dir);
     //     fs::create_dir_all(&path).map_err(|e| TileStoreError::FileError(path, e))?;
@@ -147,6 +318,9 @@ impl TileStore for NoStore {
     async fn setup_writer(&self, _seeding: bool) -> Result<Box<dyn TileWriter>, TileStoreError> {
         Ok(Box::new(self.clone()))
     }
+    async fn truncate(&self) -> Result<(), TileStoreError> {
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -169,15 +343,162 @@ impl TileReader for NoStore {
 pub async fn tile_store_from_config(
     config: &TileStoreCfg,
     tileset_name: &str,
+    grid_namespace: Option<&str>,
     format: &Format,
     compression: &Option<StoreCompressionCfg>,
     metadata: Metadata,
 ) -> Box<dyn TileStore> {
     match &config {
-        TileStoreCfg::Files(cfg) => cfg.to_store(tileset_name, format, compression, metadata),
-        TileStoreCfg::S3(cfg) => cfg.to_store(tileset_name, format, compression, metadata),
-        TileStoreCfg::Mbtiles(cfg) => cfg.to_store(tileset_name, format, compression, metadata),
-        TileStoreCfg::Pmtiles(cfg) => cfg.to_store(tileset_name, format, compression, metadata),
+        TileStoreCfg::Files(cfg) => {
+            cfg.to_store(tileset_name, grid_namespace, format, compression, metadata)
+        }
+        TileStoreCfg::S3(cfg) => {
+            cfg.to_store(tileset_name, grid_namespace, format, compression, metadata)
+        }
+        TileStoreCfg::Mbtiles(cfg) => {
+            cfg.to_store(tileset_name, grid_namespace, format, compression, metadata)
+        }
+        TileStoreCfg::Pmtiles(cfg) => {
+            cfg.to_store(tileset_name, grid_namespace, format, compression, metadata)
+        }
         TileStoreCfg::NoStore => Box::new(NoStore),
     }
 }
+
+/// Directory name or key prefix of tileset grid namespace
+///
+/// `rivers_lakes` -> `rivers_lakes-LV95`
+pub fn grid_dir_name(tileset_name: &str, grid_namespace: Option<&str>) -> String {
+    match grid_namespace {
+        Some(namespace) => format!("{tileset_name}-{namespace}"),
+        None => tileset_name.to_string(),
+    }
+}
+
+/// Path of single file store for grid namespace
+///
+/// `tiles.mbtiles` -> `tiles-LV95.mbtiles`
+pub fn grid_file_path(path: &Path, grid_namespace: Option<&str>) -> PathBuf {
+    let Some(namespace) = grid_namespace else {
+        return path.to_path_buf();
+    };
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file_name = format!("{stem}-{namespace}");
+    if let Some(ext) = path.extension() {
+        file_name.push('.');
+        file_name.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(file_name)
+}
+
+/// Record TMS of stored tiles in the `json` metadata entry
+///
+/// Grids other than WebMercator are described with the tiling scheme entries of
+/// the GDAL MVT driver and the full OGC `tile_matrix_set` definition.
+pub fn metadata_with_tms(mut metadata: Metadata, tms: &Tms) -> Metadata {
+    let mut json = metadata.json.take().unwrap_or_else(|| json!({}));
+    if let Some(obj) = json.as_object_mut() {
+        if let Some(vector_layers) = &metadata.tilejson.vector_layers {
+            obj.entry("vector_layers")
+                .or_insert_with(|| json!(vector_layers));
+        }
+        obj.insert("tms".to_string(), json!(tms.id()));
+        if tms.srid() != 3857 {
+            let zoom = tms.minzoom();
+            let tile = tms.xy_bounds(&Xyz::new(0, 0, zoom));
+            let matrix = tms.matrix(zoom);
+            let matrix = matrix.as_ref();
+            let entries = [
+                ("crs", json!(format!("EPSG:{}", tms.srid()))),
+                ("tile_origin_upper_left_x", json!(tile.left)),
+                ("tile_origin_upper_left_y", json!(tile.top)),
+                ("tile_dimension_zoom_0", json!(tile.right - tile.left)),
+                (
+                    "tile_matrix_width_zoom_0",
+                    json!(u64::from(matrix.matrix_width)),
+                ),
+                (
+                    "tile_matrix_height_zoom_0",
+                    json!(u64::from(matrix.matrix_height)),
+                ),
+                ("tile_matrix_set", json!(tms.tms)),
+            ];
+            for (key, value) in entries {
+                obj.insert(key.to_string(), value);
+            }
+        }
+    }
+    metadata.json = Some(json);
+    metadata
+}
+
+/// TMS id recorded in `json` metadata entry
+pub fn metadata_tms_id(json: &serde_json::Value) -> Option<&str> {
+    json.get("tms")
+        .or_else(|| json.get("tile_matrix_set").and_then(|tms| tms.get("id")))
+        .and_then(|id| id.as_str())
+}
+
+/// MBTiles encoding of stored tiles
+pub fn tile_encoding(compression: &Compression) -> Encoding {
+    match compression {
+        Compression::Gzip => Encoding::Gzip,
+        Compression::None => Encoding::Uncompressed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_namespace_path() {
+        let path = Path::new("/tmp/tiles.mbtiles");
+        assert_eq!(grid_file_path(path, None), path);
+        assert_eq!(
+            grid_file_path(path, Some("LV95")),
+            Path::new("/tmp/tiles-LV95.mbtiles")
+        );
+        assert_eq!(grid_dir_name("rivers_lakes", None), "rivers_lakes");
+        assert_eq!(
+            grid_dir_name("rivers_lakes", Some("LV95")),
+            "rivers_lakes-LV95"
+        );
+    }
+
+    #[test]
+    fn etag_of_uncompressed_content() {
+        use std::io::Write;
+        let data = b"tile data".to_vec();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&data).unwrap();
+        let gzip = gz.finish().unwrap();
+        assert_eq!(tile_etag(&gzip), tile_etag(&data));
+        assert_ne!(tile_etag(&data), tile_etag(b"other data"));
+    }
+
+    #[test]
+    fn grid_metadata() {
+        let metadata = Metadata {
+            id: "tiles".to_string(),
+            tile_info: martin_tile_utils::TileInfo {
+                format: martin_tile_utils::Format::Mvt,
+                encoding: Encoding::Gzip,
+            },
+            tilejson: tilejson::tilejson! { tiles: vec![] },
+            layer_type: None,
+            json: None,
+            agg_tiles_hash: None,
+        };
+        let tms = tile_grid::tms().lookup("WebMercatorQuad").unwrap();
+        let json = metadata_with_tms(metadata.clone(), &tms).json.unwrap();
+        assert_eq!(metadata_tms_id(&json), Some("WebMercatorQuad"));
+        assert!(json.get("tile_matrix_set").is_none());
+
+        let tms = tile_grid::tms().lookup("EuropeanETRS89_LAEAQuad").unwrap();
+        let json = metadata_with_tms(metadata, &tms).json.unwrap();
+        assert_eq!(metadata_tms_id(&json), Some("EuropeanETRS89_LAEAQuad"));
+        assert_eq!(json["crs"], "EPSG:3035");
+        assert_eq!(json["tile_matrix_width_zoom_0"], 1);
+    }
+}
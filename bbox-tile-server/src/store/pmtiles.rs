@@ -1,5 +1,8 @@
 use crate::config::{PmtilesStoreCfg, StoreCompressionCfg};
-use crate::store::{NoStore, StoreFromConfig, TileReader, TileStore, TileStoreError, TileWriter};
+use crate::store::{
+    grid_file_path, insert_validators, NoStore, StoreFromConfig, TileReader, TileStore,
+    TileStoreError, TileWriter,
+};
 use async_trait::async_trait;
 use bbox_core::{Compression, Format, TileResponse};
 use log::{info, warn};
@@ -26,12 +29,13 @@ impl StoreFromConfig for PmtilesStoreCfg {
     fn to_store(
         &self,
         _tileset_name: &str,
+        grid_namespace: Option<&str>,
         format: &Format,
         _compression: &Option<StoreCompressionCfg>,
         metadata: Metadata,
     ) -> Box<dyn TileStore> {
         Box::new(PmtilesStore {
-            path: self.abs_path(),
+            path: grid_file_path(&self.abs_path(), grid_namespace),
             format: *format,
             metadata,
         })
@@ -68,7 +72,7 @@ impl TileStore for PmtilesStore {
         let tile_type = match self.format {
             Format::Jpeg => TileType::Jpeg,
             Format::Mvt => TileType::Mvt,
-            Format::Png => TileType::Png,
+            Format::Png | Format::Png8 => TileType::Png,
             Format::Webp => TileType::Webp,
             _ => TileType::Unknown,
         };
@@ -99,6 +103,12 @@ impl TileStore for PmtilesStore {
         if let Some(vector_layers) = &self.metadata.tilejson.vector_layers {
             meta_data["vector_layers"] = json!(vector_layers);
         }
+        // Additional entries like the tile grid
+        if let Some(serde_json::Value::Object(entries)) = &self.metadata.json {
+            for (key, value) in entries {
+                meta_data[key.as_str()] = value.clone();
+            }
+        }
         pmtiles = pmtiles.metadata(&meta_data.to_string());
 
         info!("Writing {}", self.path.display());
@@ -169,6 +179,8 @@ impl TileReader for PmtilesStoreReader {
             //     response.insert_header(("Content-Encoding", encoding.to_lowercase()));
             // }
             response.insert_header(("Content-Encoding", "gzip"));
+            // The archive modification time doesn't tell when a tile has changed
+            insert_validators(&mut response, &tile, None);
             Some(response.with_body(Box::new(Cursor::new(tile))))
         } else {
             None
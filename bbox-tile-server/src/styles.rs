@@ -0,0 +1,218 @@
+//! Style documents with local glyphs and sprites.
+
+use crate::config::{TileServiceCfg, VectorRenderSourceParamsCfg};
+use crate::datasource::SourceType;
+use crate::service::{ServiceError, TileService, TileSet};
+use bbox_core::config::app_dir;
+use log::warn;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Style document of a tileset
+#[derive(Clone, Debug)]
+pub struct TilesetStyle {
+    pub id: String,
+    pub tileset: String,
+    json: PathBuf,
+    sprite: Option<PathBuf>,
+}
+
+/// Configured style documents and glyphs
+#[derive(Clone, Default, Debug)]
+pub struct Styles {
+    styles: Vec<TilesetStyle>,
+    glyphs_dir: Option<PathBuf>,
+}
+
+const SPRITE_FILES: [&str; 4] = [
+    "sprite.json",
+    "sprite.png",
+    "sprite@2x.json",
+    "sprite@2x.png",
+];
+
+impl Styles {
+    pub fn from_config(config: &TileServiceCfg) -> Self {
+        let mut styles: Vec<TilesetStyle> = Vec::new();
+        for ts in &config.tilesets {
+            for style in &ts.styles {
+                let id = style.id.clone().unwrap_or(ts.name.clone());
+                if styles.iter().any(|s| s.id == id) {
+                    warn!("Skipping duplicate style `{id}`");
+                    continue;
+                }
+                styles.push(TilesetStyle {
+                    id,
+                    tileset: ts.name.clone(),
+                    json: app_dir(&style.json),
+                    sprite: style.sprite.as_ref().map(app_dir),
+                });
+            }
+        }
+        Styles {
+            styles,
+            glyphs_dir: config.glyphs.as_ref().map(|glyphs| glyphs.abs_path()),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.styles.is_empty()
+    }
+    pub fn styles(&self) -> &[TilesetStyle] {
+        &self.styles
+    }
+    pub fn style(&self, id: &str) -> Option<&TilesetStyle> {
+        self.styles.iter().find(|style| style.id == id)
+    }
+    /// Default style of a tileset
+    pub fn tileset_style(&self, tileset: &str) -> Option<&TilesetStyle> {
+        self.styles.iter().find(|style| style.tileset == tileset)
+    }
+    /// Style for rendering raster tiles of a vector tileset
+    pub async fn render_style(
+        &self,
+        cfg: &VectorRenderSourceParamsCfg,
+        tileset: &TileSet,
+    ) -> Result<serde_json::Value, ServiceError> {
+        let style = match &cfg.style {
+            Some(id) => Some(
+                self.style(id)
+                    .ok_or(ServiceError::StyleNotFound(id.clone()))?,
+            ),
+            None => self.tileset_style(&tileset.name),
+        };
+        match style {
+            Some(style) => style.read_json(),
+            None => tileset.stylejson("", "", None).await,
+        }
+    }
+    /// Glyphs URL template for style documents
+    pub fn glyphs_url(&self, base_url: &str) -> Option<String> {
+        self.glyphs_dir
+            .as_ref()
+            .map(|_| format!("{base_url}/fonts/{{fontstack}}/{{range}}.pbf"))
+    }
+    /// Glyph range of the first available font of a comma separated font stack
+    pub fn glyphs(&self, fontstack: &str, range: &str) -> Option<Vec<u8>> {
+        let dir = self.glyphs_dir.as_ref()?;
+        if !is_glyph_range(range) {
+            return None;
+        }
+        fontstack
+            .split(',')
+            .map(str::trim)
+            .filter(|font| is_file_name(font))
+            .find_map(|font| fs::read(dir.join(font).join(format!("{range}.pbf"))).ok())
+    }
+}
+
+impl TilesetStyle {
+    /// Style title from style document
+    pub fn title(&self) -> Option<String> {
+        let style = self.read_json().ok()?;
+        style["name"].as_str().map(str::to_string)
+    }
+    /// Style document with URLs referencing this service
+    pub fn style_json(
+        &self,
+        service: &TileService,
+        base_url: &str,
+    ) -> Result<serde_json::Value, ServiceError> {
+        let mut style = self.read_json()?;
+        let sprite_url = self
+            .sprite
+            .as_ref()
+            .map(|_| format!("{base_url}/styles/{}/sprite", self.id));
+        rewrite_urls(
+            &mut style,
+            service,
+            base_url,
+            sprite_url,
+            service.styles().glyphs_url(base_url),
+        );
+        Ok(style)
+    }
+    /// Path of sprite file
+    pub fn sprite_file(&self, file: &str) -> Option<PathBuf> {
+        let dir = self.sprite.as_ref()?;
+        SPRITE_FILES.contains(&file).then(|| dir.join(file))
+    }
+    pub(crate) fn read_json(&self) -> Result<serde_json::Value, ServiceError> {
+        let json = fs::read_to_string(&self.json)?;
+        serde_json::from_str(&json)
+            .map_err(|e| ServiceError::StyleError(self.json.display().to_string(), e))
+    }
+}
+
+/// Rewrite URLs of tileset sources, sprites and glyphs
+fn rewrite_urls(
+    style: &mut serde_json::Value,
+    service: &TileService,
+    base_url: &str,
+    sprite_url: Option<String>,
+    glyphs_url: Option<String>,
+) {
+    if let Some(sources) = style["sources"].as_object_mut() {
+        for (name, source) in sources.iter_mut() {
+            let Some(ts) = service.tileset(name) else {
+                continue;
+            };
+            let Some(source) = source.as_object_mut() else {
+                continue;
+            };
+            match ts.source.source_type() {
+                SourceType::Vector => {
+                    source.remove("tiles");
+                    source.insert(
+                        "url".to_string(),
+                        format!("{base_url}/xyz/{name}.json").into(),
+                    );
+                }
+                SourceType::Raster => {
+                    let suffix = ts.tile_format().file_suffix();
+                    source.remove("url");
+                    source.insert(
+                        "tiles".to_string(),
+                        vec![format!("{base_url}/xyz/{name}/{{z}}/{{x}}/{{y}}.{suffix}")].into(),
+                    );
+                }
+            }
+        }
+    }
+    if let Some(sprite_url) = sprite_url {
+        style["sprite"] = sprite_url.into();
+    }
+    if let Some(glyphs_url) = glyphs_url {
+        style["glyphs"] = glyphs_url.into();
+    }
+}
+
+/// Check for glyph range like `0-255`
+fn is_glyph_range(range: &str) -> bool {
+    let mut parts = range.split('-');
+    matches!(
+        (parts.next(), parts.next(), parts.next()),
+        (Some(start), Some(end), None)
+            if start.parse::<u32>().is_ok() && end.parse::<u32>().is_ok()
+    )
+}
+
+/// Check for plain file name without path components
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && Path::new(name).file_name() == Some(name.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glyph_request_params() {
+        assert!(is_glyph_range("0-255"));
+        assert!(!is_glyph_range("0-255-511"));
+        assert!(!is_glyph_range("../0-255"));
+        assert!(is_file_name("Open Sans Regular"));
+        assert!(!is_file_name(".."));
+        assert!(!is_file_name("../fonts"));
+        assert!(!is_file_name("fonts/Arial"));
+    }
+}
@@ -0,0 +1,173 @@
+//! OGC WMTS 1.0.0 capabilities.
+
+use crate::service::{TileService, TileSet, TmsExtensions};
+use bbox_core::auth::Identity;
+use std::collections::BTreeMap;
+use tile_grid::Tms;
+
+/// WMTS GetCapabilities document with the tilesets accessible by `identity`
+pub fn capabilities(service: &TileService, base_url: &str, identity: Option<&Identity>) -> String {
+    let mut tilesets = service
+        .tileset_list()
+        .into_iter()
+        .filter(|ts| ts.config().is_accessible(identity))
+        .collect::<Vec<_>>();
+    tilesets.sort_by(|a, b| a.name.cmp(&b.name));
+    // Grids of listed tilesets, sorted by id
+    let grids = tilesets
+        .iter()
+        .flat_map(|ts| &ts.tms)
+        .map(|grid| (grid.tms.id().to_string(), grid.tms.clone()))
+        .collect::<BTreeMap<String, Tms>>()
+        .into_values()
+        .collect::<Vec<_>>();
+
+    let kvp_url = format!("{base_url}/wmts?");
+    let operations = ["GetCapabilities", "GetTile"]
+        .iter()
+        .map(|operation| {
+            format!(
+                r#"<ows:Operation name="{operation}"><ows:DCP><ows:HTTP><ows:Get xlink:href="{kvp_url}"><ows:Constraint name="GetEncoding"><ows:AllowedValues><ows:Value>KVP</ows:Value></ows:AllowedValues></ows:Constraint></ows:Get></ows:HTTP></ows:DCP></ows:Operation>"#
+            )
+        })
+        .collect::<String>();
+    let layers = tilesets
+        .iter()
+        .map(|ts| layer(ts, base_url))
+        .collect::<String>();
+    let tile_matrix_sets = grids
+        .iter()
+        .map(|tms| tile_matrix_set(tms))
+        .collect::<String>();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<Capabilities xmlns="http://www.opengis.net/wmts/1.0" xmlns:ows="http://www.opengis.net/ows/1.1" xmlns:xlink="http://www.w3.org/1999/xlink" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.opengis.net/wmts/1.0 http://schemas.opengis.net/wmts/1.0/wmtsGetCapabilities_response.xsd" version="1.0.0">
+<ows:ServiceIdentification><ows:Title>BBOX tile server</ows:Title><ows:ServiceType>OGC WMTS</ows:ServiceType><ows:ServiceTypeVersion>1.0.0</ows:ServiceTypeVersion></ows:ServiceIdentification>
+<ows:OperationsMetadata>{operations}</ows:OperationsMetadata>
+<Contents>
+{layers}{tile_matrix_sets}</Contents>
+<ServiceMetadataURL xlink:href="{base_url}/wmts/1.0.0/WMTSCapabilities.xml"/>
+</Capabilities>
+"#
+    )
+}
+
+fn layer(ts: &TileSet, base_url: &str) -> String {
+    let name = xml_escape(&ts.name);
+    let formats = ts.tile_formats();
+    let format_elements = formats
+        .iter()
+        .map(|format| format!("<Format>{}</Format>", format.content_type()))
+        .collect::<String>();
+    let tms_links = ts
+        .tms
+        .iter()
+        .map(|grid| {
+            let limits = grid
+                .tms
+                .tms
+                .tile_matrices
+                .iter()
+                .filter(|tm| {
+                    matches!(tm.id.parse::<u8>(), Ok(z) if z >= grid.minzoom && z <= grid.served_maxzoom())
+                })
+                .map(|tm| {
+                    format!(
+                        "<TileMatrixLimits><TileMatrix>{}</TileMatrix><MinTileRow>0</MinTileRow><MaxTileRow>{}</MaxTileRow><MinTileCol>0</MinTileCol><MaxTileCol>{}</MaxTileCol></TileMatrixLimits>",
+                        xml_escape(&tm.id),
+                        u64::from(tm.matrix_height) - 1,
+                        u64::from(tm.matrix_width) - 1,
+                    )
+                })
+                .collect::<String>();
+            format!(
+                "<TileMatrixSetLink><TileMatrixSet>{}</TileMatrixSet><TileMatrixSetLimits>{limits}</TileMatrixSetLimits></TileMatrixSetLink>",
+                xml_escape(grid.tms.id())
+            )
+        })
+        .collect::<String>();
+    let resource_urls = formats
+        .iter()
+        .map(|format| {
+            format!(
+                r#"<ResourceURL format="{}" resourceType="tile" template="{base_url}/wmts/1.0.0/{name}/default/{{TileMatrixSet}}/{{TileMatrix}}/{{TileRow}}/{{TileCol}}.{}"/>"#,
+                format.content_type(),
+                format.file_suffix()
+            )
+        })
+        .collect::<String>();
+    format!(
+        r#"<Layer><ows:Title>{name}</ows:Title><ows:Identifier>{name}</ows:Identifier><Style isDefault="true"><ows:Identifier>default</ows:Identifier></Style>{format_elements}{tms_links}{resource_urls}</Layer>
+"#
+    )
+}
+
+fn tile_matrix_set(tms: &Tms) -> String {
+    let tile_matrices = tms
+        .tms
+        .tile_matrices
+        .iter()
+        .map(|tm| {
+            format!(
+                "<TileMatrix><ows:Identifier>{}</ows:Identifier><ScaleDenominator>{}</ScaleDenominator><TopLeftCorner>{} {}</TopLeftCorner><TileWidth>{}</TileWidth><TileHeight>{}</TileHeight><MatrixWidth>{}</MatrixWidth><MatrixHeight>{}</MatrixHeight></TileMatrix>",
+                xml_escape(&tm.id),
+                tm.scale_denominator,
+                tm.point_of_origin[0],
+                tm.point_of_origin[1],
+                tm.tile_width,
+                tm.tile_height,
+                tm.matrix_width,
+                tm.matrix_height,
+            )
+        })
+        .collect::<String>();
+    format!(
+        "<TileMatrixSet><ows:Identifier>{}</ows:Identifier><ows:SupportedCRS>urn:ogc:def:crs:EPSG::{}</ows:SupportedCRS>{tile_matrices}</TileMatrixSet>\n",
+        xml_escape(tms.id()),
+        tms.srid()
+    )
+}
+
+/// Zoom level of WMTS tile matrix identifier
+pub fn zoom_level(tms: &Tms, tile_matrix: &str) -> Option<u8> {
+    tms.tms
+        .tile_matrices
+        .iter()
+        .find(|tm| tm.id == tile_matrix)
+        .and_then(|tm| tm.id.parse().ok())
+}
+
+/// OWS exception report
+pub fn exception_report(code: &str, locator: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<ExceptionReport xmlns="http://www.opengis.net/ows/1.1" version="1.1.0"><Exception exceptionCode="{code}" locator="{}"/></ExceptionReport>
+"#,
+        xml_escape(locator)
+    )
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tile_grid::tms;
+
+    #[test]
+    fn tile_matrix_set_xml() {
+        let tms = tms().lookup("WebMercatorQuad").unwrap();
+        let xml = tile_matrix_set(&tms);
+        assert!(xml.starts_with("<TileMatrixSet><ows:Identifier>WebMercatorQuad</ows:Identifier><ows:SupportedCRS>urn:ogc:def:crs:EPSG::3857</ows:SupportedCRS>"));
+        assert!(xml.contains("<TileMatrix><ows:Identifier>0</ows:Identifier>"));
+        assert!(xml.contains("<MatrixWidth>4</MatrixWidth><MatrixHeight>4</MatrixHeight>"));
+        assert_eq!(zoom_level(&tms, "12"), Some(12));
+        assert_eq!(zoom_level(&tms, "99"), None);
+    }
+}